@@ -1,40 +1,312 @@
+use pcap::Device;
 use ratatui::widgets::TableState;
 
 use crate::network::{
-    dns::{reverse_lookup, DNSRData, DnsDirectRecord, DnsMessage, DnsResourceRecord},
-    ip,
-    sniffer::SnifferPacket,
+    dhcp::{self, DhcpMessageType},
+    dns::{DNSRData, DnsMessage, Rcode},
+    dns_tracker::{DnsTracker, DnsTransaction, LatencyStats},
+    error::DecodeError,
+    ip::IpProtocol,
+    resolver::{self, Resolver, ReverseLookup},
+    sniffer::{
+        CaptureEvent, DhcpEvent, DnsEvent, HttpEvent, SnifferEvent, SnifferPacket, TlsEvent,
+    },
+};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    error, fmt,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
 };
-use std::{collections::HashMap, error, fs::File, hash::Hash, io::Write, net::IpAddr, slice::Iter};
 
 /// Application result type.
 pub type AppResult<T> = std::result::Result<T, Box<dyn error::Error>>;
 
+/// A transport protocol and port pair that a host has been seen talking on, e.g. tcp/443.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Service {
+    pub port: u16,
+    pub protocol: IpProtocol,
+}
+
+impl fmt::Display for Service {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.protocol, self.port)
+    }
+}
+
 #[derive(Debug)]
 pub struct HostInfo {
     // pub ip: IpAddr,
     // pub host: String,
     pub num_packets: u32,
+
+    // Packets from this host with a bad IPv4, TCP or UDP checksum
+    pub num_bad_checksums: u32,
+
+    // Services seen in this host's traffic, ordered by port
+    pub services: BTreeSet<Service>,
+
+    // Interfaces this host's traffic was captured on
+    pub interfaces: BTreeSet<Arc<str>>,
+}
+
+/// What a DHCP client said about itself in its most recent messages.
+#[derive(Debug, Default)]
+pub struct DhcpClient {
+    pub hostname: Option<String>,
+    pub vendor_class: Option<String>,
+}
+
+/// An address assigned by a DHCP server, along with the client it was assigned to.
+#[derive(Debug)]
+pub struct DhcpLease {
+    // Hardware address of a DHCPv4 client, or DUID of a DHCPv6 client
+    pub client_id: Vec<u8>,
+
+    pub hostname: Option<String>,
+    pub vendor_class: Option<String>,
+
+    // Server identifier from the acknowledgement, or the address it was sent from
+    pub server: IpAddr,
+
+    pub lease_time: Option<Duration>,
+
+    // Capture time of the acknowledgement as the duration since the Unix epoch
+    pub granted: Duration,
+}
+
+impl DhcpLease {
+    /// Returns the kind of device guessed from the vendor class, or the vendor class
+    /// itself if it is not recognized.
+    pub fn device(&self) -> Option<&str> {
+        let vendor_class = self.vendor_class.as_deref()?;
+        Some(dhcp::device_type(vendor_class).unwrap_or(vendor_class))
+    }
+}
+
+/// The cleartext HTTP requests seen to a server.
+#[derive(Debug, Default)]
+pub struct HttpServer {
+    pub num_requests: u32,
+
+    // Hosts that sent requests to the server
+    pub clients: BTreeSet<IpAddr>,
+
+    // Host name from the most recent Host header that was not an address literal
+    pub host: Option<String>,
+
+    // Request line of the most recent request, e.g. `GET /index.html HTTP/1.1`
+    pub last_request: String,
+
+    pub user_agents: BTreeSet<String>,
+    pub content_types: BTreeSet<String>,
 }
 
 #[derive(Debug)]
 pub struct NetworkEntry<'a> {
     pub ip: &'a IpAddr,
     pub domain: Option<&'a String>,
+    pub device: Option<&'a str>,
+    pub alpn: Option<&'a Vec<String>>,
     pub info: &'a HostInfo,
 }
 
+/// The views of the main screen, switched between with Tab.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tab {
+    Hosts,
+    DnsLog,
+    Latency,
+    Leases,
+    Http,
+}
+
+impl Tab {
+    pub const ALL: [Tab; 5] = [
+        Tab::Hosts,
+        Tab::DnsLog,
+        Tab::Latency,
+        Tab::Leases,
+        Tab::Http,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Tab::Hosts => "Hosts",
+            Tab::DnsLog => "DNS Log",
+            Tab::Latency => "DNS Latency",
+            Tab::Leases => "DHCP Leases",
+            Tab::Http => "HTTP",
+        }
+    }
+
+    fn next(self) -> Tab {
+        let idx = Tab::ALL.iter().position(|&tab| tab == self).unwrap_or(0);
+        Tab::ALL[(idx + 1) % Tab::ALL.len()]
+    }
+}
+
+// The oldest transactions are dropped from the DNS log past this length
+const MAX_DNS_LOG_LEN: usize = 10_000;
+
+/// Restricts the DNS log to transactions with a response code, or without a response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RcodeFilter {
+    All,
+    Rcode(Rcode),
+    Unanswered,
+}
+
+impl RcodeFilter {
+    const CYCLE: [RcodeFilter; 6] = [
+        RcodeFilter::All,
+        RcodeFilter::Rcode(Rcode::NoError),
+        RcodeFilter::Rcode(Rcode::NXDomain),
+        RcodeFilter::Rcode(Rcode::ServFail),
+        RcodeFilter::Rcode(Rcode::Refused),
+        RcodeFilter::Unanswered,
+    ];
+
+    fn next(self) -> RcodeFilter {
+        let idx = RcodeFilter::CYCLE
+            .iter()
+            .position(|&filter| filter == self)
+            .unwrap_or(0);
+        RcodeFilter::CYCLE[(idx + 1) % RcodeFilter::CYCLE.len()]
+    }
+
+    fn matches(&self, transaction: &DnsTransaction) -> bool {
+        match (self, &transaction.response) {
+            (RcodeFilter::All, _) => true,
+            (RcodeFilter::Rcode(rcode), Some(response)) => response.rcode() == *rcode,
+            (RcodeFilter::Unanswered, None) => !transaction.pending,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for RcodeFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RcodeFilter::All => write!(f, "all"),
+            RcodeFilter::Rcode(rcode) => write!(f, "{}", rcode),
+            RcodeFilter::Unanswered => write!(f, "unanswered"),
+        }
+    }
+}
+
+/// Startup screen for choosing the interface to capture on.
+#[derive(Debug)]
+pub struct InterfacePicker {
+    pub devices: Vec<Device>,
+    pub state: TableState,
+
+    // Indices of the devices marked for capture
+    pub marked: BTreeSet<usize>,
+}
+
+impl InterfacePicker {
+    /// Creates a picker over `devices`, with the device named `default` selected if present.
+    pub fn new(devices: Vec<Device>, default: Option<String>) -> Self {
+        let selected = default
+            .and_then(|name| devices.iter().position(|d| d.name == name))
+            .unwrap_or(0);
+
+        Self {
+            devices,
+            state: TableState::new().with_selected(Some(selected)),
+            marked: BTreeSet::new(),
+        }
+    }
+
+    /// Marks or unmarks the highlighted device for capture.
+    pub fn toggle_mark(&mut self) {
+        if let Some(idx) = self.state.selected() {
+            if !self.marked.remove(&idx) {
+                self.marked.insert(idx);
+            }
+        }
+    }
+
+    /// Marks every device that is up, or clears the marks if they are all marked already.
+    pub fn toggle_mark_all(&mut self) {
+        let up = (0..self.devices.len())
+            .filter(|&idx| self.devices[idx].flags.is_up())
+            .collect::<BTreeSet<_>>();
+        self.marked = if self.marked == up {
+            BTreeSet::new()
+        } else {
+            up
+        };
+    }
+
+    pub fn prev_entry(&mut self) {
+        let idx = self.state.selected().unwrap_or(0);
+        self.state.select(Some(idx.saturating_sub(1)));
+    }
+
+    pub fn next_entry(&mut self) {
+        let idx = self.state.selected().unwrap_or(0);
+        self.state
+            .select(Some((idx + 1).min(self.devices.len().saturating_sub(1))));
+    }
+}
+
 /// Application.
 #[derive(Debug)]
 pub struct App {
+    pub tab: Tab,
+
     pub state: TableState,
 
+    // Selected row of the per-domain latency table
+    pub latency_state: TableState,
+
+    // Selected row of the DNS log
+    pub log_state: TableState,
+
+    // Selected row of the DHCP lease table
+    pub leases_state: TableState,
+
+    // Selected row of the HTTP server table
+    pub http_state: TableState,
+
+    // Mapping between ip address and the server name that clients asked for when
+    // connecting over TLS (SNI). It names hosts even when the lookup was not seen,
+    // e.g. with DNS over HTTPS, and is trusted over DNS answers as it names the
+    // server a connection was actually made for.
+    pub ip_to_server_name: HashMap<IpAddr, String>,
+
+    // Servers that cleartext HTTP requests were sent to. The Host header names the
+    // server a request was actually made for, so it is trusted over DNS answers.
+    pub http_servers: BTreeMap<IpAddr, HttpServer>,
+
+    // Application protocols that clients offered when connecting to each address over
+    // TLS, or that SVCB and HTTPS records advertised for it until a connection is seen
+    pub ip_to_alpn: HashMap<IpAddr, Vec<String>>,
+
     // Mapping between ip address and hostname from live DNS traffic
     pub ip_to_domain: HashMap<IpAddr, String>,
 
+    // Mapping between ip address and hostname from mDNS and LLMNR responses, which
+    // hosts on the local link use to advertise their own names
+    pub ip_to_local_name: HashMap<IpAddr, String>,
+
+    // Clients seen in DHCP traffic, keyed by hardware address or DUID
+    pub dhcp_clients: HashMap<Vec<u8>, DhcpClient>,
+
+    // Leases acknowledged by DHCP servers, keyed by the assigned address
+    pub dhcp_leases: BTreeMap<IpAddr, DhcpLease>,
+
     // Mapping between ip address and hostname from reverse lookups (PTR records)
     pub ip_to_domain_fallback: HashMap<IpAddr, String>,
 
+    // When the PTR record of a host without one may be looked up again. Lookups that
+    // failed or were dropped because the resolver was busy are only retried from here.
+    pub lookup_retries: HashMap<IpAddr, Instant>,
+
     // Mainain map insert order with a separate hosts vector
     // TODO: abstract into a separate HashMap class
     pub host_ips: Vec<IpAddr>,
@@ -45,6 +317,50 @@ pub struct App {
     pub inv_cname_map: HashMap<String, String>,
 
     pub host_info: HashMap<IpAddr, HostInfo>,
+
+    // Matches DNS queries with their responses to measure resolver latency
+    pub dns_tracker: DnsTracker,
+
+    // Completed DNS transactions, oldest first
+    pub dns_log: VecDeque<DnsTransaction>,
+
+    // Queries still waiting for a response, oldest first, shown above the log
+    pub dns_pending: Vec<DnsTransaction>,
+
+    // Set when DNS traffic may have changed the pending queries, which are rebuilt
+    // at the next tick rather than for every message
+    dns_pending_changed: bool,
+
+    // Only show DNS transactions whose name contains this text, ignoring case
+    pub log_name_filter: String,
+
+    // Set while keys are typed into the name filter
+    pub editing_log_filter: bool,
+
+    pub log_rcode_filter: RcodeFilter,
+
+    // Background resolver for PTR lookups of newly seen hosts
+    pub resolver: Option<Resolver>,
+
+    // Shown instead of the host table until an interface is chosen
+    pub interface_picker: Option<InterfacePicker>,
+
+    // Interfaces chosen in the picker, taken by the main loop to start capturing
+    pub chosen_interfaces: Vec<Device>,
+
+    // Interfaces that traffic has been captured on, in the order they were first seen
+    pub interfaces: Vec<Arc<str>>,
+
+    // Only show hosts seen on this interface
+    pub interface_filter: Option<Arc<str>>,
+
+    // Number of frames the capture pipeline failed to decode, and the most recent error
+    pub num_parse_errors: u32,
+    pub last_parse_error: Option<DecodeError>,
+
+    // Most recent interface that could not be opened or stopped capturing, and why
+    pub capture_error: Option<(Arc<str>, pcap::Error)>,
+
     pub running: bool,
 }
 
@@ -52,12 +368,39 @@ impl Default for App {
     fn default() -> Self {
         Self {
             running: true,
+            tab: Tab::Hosts,
             state: TableState::new(),
+            latency_state: TableState::new(),
+            log_state: TableState::new(),
+            leases_state: TableState::new(),
+            http_state: TableState::new(),
             host_ips: Vec::new(),
             inv_cname_map: HashMap::new(),
+            ip_to_server_name: HashMap::new(),
+            ip_to_alpn: HashMap::new(),
+            http_servers: BTreeMap::new(),
             ip_to_domain: HashMap::new(),
+            ip_to_local_name: HashMap::new(),
+            dhcp_clients: HashMap::new(),
+            dhcp_leases: BTreeMap::new(),
             ip_to_domain_fallback: HashMap::new(),
+            lookup_retries: HashMap::new(),
             host_info: HashMap::new(),
+            dns_tracker: DnsTracker::default(),
+            dns_log: VecDeque::new(),
+            dns_pending: Vec::new(),
+            dns_pending_changed: false,
+            log_name_filter: String::new(),
+            editing_log_filter: false,
+            log_rcode_filter: RcodeFilter::All,
+            resolver: None,
+            interface_picker: None,
+            chosen_interfaces: Vec::new(),
+            interfaces: Vec::new(),
+            interface_filter: None,
+            num_parse_errors: 0,
+            last_parse_error: None,
+            capture_error: None,
         }
     }
 }
@@ -69,33 +412,338 @@ impl App {
     }
 
     /// Handles the tick event of the terminal.
-    pub fn tick(&self) {}
+    pub fn tick(&mut self) {
+        let expired = self.dns_tracker.tick(Instant::now());
+        if !expired.is_empty() || self.dns_pending_changed {
+            self.dns_pending = self.dns_tracker.pending();
+            self.dns_pending_changed = false;
+            self.push_dns_log(expired);
+        }
+    }
 
     /// Set running to false to quit the application.
     pub fn quit(&mut self) {
         self.running = false;
     }
 
+    /// Closes the interface picker and hands the marked devices, or the highlighted
+    /// device if none are marked, to the main loop. The picker stays open if there is
+    /// no device to choose.
+    pub fn choose_interfaces(&mut self) {
+        let Some(picker) = &self.interface_picker else {
+            return;
+        };
+
+        let mut marked = picker.marked.clone();
+        if marked.is_empty() {
+            marked.extend(picker.state.selected());
+        }
+        if !marked.iter().any(|&idx| idx < picker.devices.len()) {
+            return;
+        }
+
+        if let Some(picker) = self.interface_picker.take() {
+            self.chosen_interfaces = picker
+                .devices
+                .into_iter()
+                .enumerate()
+                .filter(|(idx, _)| marked.contains(idx))
+                .map(|(_, device)| device)
+                .collect();
+        }
+    }
+
+    /// Cycles the host table between showing all hosts and only the hosts seen on
+    /// each interface in turn.
+    pub fn cycle_interface_filter(&mut self) {
+        let next = match &self.interface_filter {
+            None => self.interfaces.first(),
+            Some(current) => self
+                .interfaces
+                .iter()
+                .position(|interface| interface == current)
+                .and_then(|idx| self.interfaces.get(idx + 1)),
+        };
+        self.interface_filter = next.cloned();
+        self.state.select(Some(0));
+    }
+
+    /// Switches to the next view of the main screen.
+    pub fn next_tab(&mut self) {
+        self.tab = self.tab.next();
+    }
+
+    /// Dispatches an event from the capture pipeline to its handler.
+    pub fn handle_capture_event(&mut self, event: CaptureEvent) {
+        match event.event {
+            SnifferEvent::Packet(packet) => self.handle_packet(event.interface, packet),
+            SnifferEvent::Dns(event) => self.handle_dns_event(event),
+            SnifferEvent::Dhcp(event) => self.handle_dhcp_event(event),
+            SnifferEvent::Tls(event) => self.handle_tls_event(event),
+            SnifferEvent::Http(event) => self.handle_http_event(event),
+            SnifferEvent::ParseError(error) => self.handle_parse_error(error),
+            SnifferEvent::CaptureError(error) => self.handle_capture_error(event.interface, error),
+        }
+    }
+
+    pub fn handle_parse_error(&mut self, error: DecodeError) {
+        self.num_parse_errors += 1;
+        self.last_parse_error = Some(error);
+    }
+
+    /// Records that capturing on `interface` could not start or stopped early, so
+    /// that it is reported rather than the interface going quiet.
+    pub fn handle_capture_error(&mut self, interface: Arc<str>, error: pcap::Error) {
+        self.capture_error = Some((interface, error));
+    }
+
+    /// Learns names from a DNS message and matches it with its query or response.
+    pub fn handle_dns_event(&mut self, event: DnsEvent) {
+        // mDNS and LLMNR queries are multicast and answered by whichever host owns the
+        // name, so they have no resolver to match them with or measure
+        if event.protocol.is_link_local() {
+            self.handle_local_names(&event.message);
+            return;
+        }
+
+        self.handle_dns_message(&event.message);
+
+        let transaction = self.dns_tracker.record(event);
+        self.dns_pending_changed = true;
+        self.push_dns_log(transaction);
+    }
+
+    fn push_dns_log(&mut self, transactions: impl IntoIterator<Item = DnsTransaction>) {
+        self.dns_log.extend(transactions);
+        while self.dns_log.len() > MAX_DNS_LOG_LEN {
+            self.dns_log.pop_front();
+        }
+    }
+
+    /// Returns the DNS transactions that match the log filters, newest first, starting
+    /// with the queries that are still waiting for a response.
+    pub fn dns_log_entries(&self) -> impl Iterator<Item = &DnsTransaction> {
+        let name_filter = self.log_name_filter.to_lowercase();
+        let transactions = self
+            .dns_pending
+            .iter()
+            .rev()
+            .chain(self.dns_log.iter().rev());
+        transactions.filter(move |transaction| {
+            self.log_rcode_filter.matches(transaction)
+                && transaction.qname.to_lowercase().contains(&name_filter)
+        })
+    }
+
+    /// Cycles the DNS log between showing every transaction and only those with
+    /// each common response code in turn.
+    pub fn cycle_log_rcode_filter(&mut self) {
+        self.log_rcode_filter = self.log_rcode_filter.next();
+        self.log_state.select(Some(0));
+    }
+
+    /// Starts typing into the DNS log name filter.
+    pub fn edit_log_filter(&mut self) {
+        self.editing_log_filter = true;
+    }
+
+    pub fn push_log_filter(&mut self, c: char) {
+        self.log_name_filter.push(c);
+        self.log_state.select(Some(0));
+    }
+
+    pub fn pop_log_filter(&mut self) {
+        self.log_name_filter.pop();
+        self.log_state.select(Some(0));
+    }
+
     /// We assume that we process all CNAME resolution queries before the terminal query. In other words,
     /// when we process the terminal query, we will already have a graph mapping from all resolved CNAMES
     /// to the original query domain. *NOTE*: this assumption may not be valid.
-    pub fn handle_dns_message(&mut self, data: DnsMessage) {
-        for resource in data.answers {
-            match resource.rdata {
+    pub fn handle_dns_message(&mut self, data: &DnsMessage) {
+        // Queries carry no answers to learn names from
+        if !data.is_response() {
+            return;
+        }
+
+        for resource in &data.answers {
+            match &resource.rdata {
                 DNSRData::CNAME(cname) => {
-                    self.inv_cname_map.insert(cname, resource.name);
+                    self.inv_cname_map
+                        .insert(cname.clone(), resource.name.clone());
                 }
                 DNSRData::A(ipv4) => {
-                    self.update_ip_domain_mapping(IpAddr::V4(ipv4), resource.name);
+                    self.update_ip_domain_mapping(IpAddr::V4(*ipv4), resource.name.clone());
                 }
                 DNSRData::AAAA(ipv6) => {
-                    self.update_ip_domain_mapping(IpAddr::V6(ipv6), resource.name);
+                    self.update_ip_domain_mapping(IpAddr::V6(*ipv6), resource.name.clone());
+                }
+                // Browsers may connect to the hinted addresses without waiting for the A
+                // and AAAA answers, so the hints are mapped to the endpoint they belong to.
+                // The advertised protocols stand in until a client's own ALPN is seen.
+                DNSRData::HTTPS(svcb) | DNSRData::SVCB(svcb) => {
+                    let endpoint = svcb.endpoint(&resource.name).to_string();
+                    for ip in svcb.hints() {
+                        self.update_ip_domain_mapping(ip, endpoint.clone());
+                        if !svcb.alpn().is_empty() {
+                            self.ip_to_alpn
+                                .entry(ip)
+                                .or_insert_with(|| svcb.alpn().to_vec());
+                        }
+                    }
                 }
                 _ => (),
             };
         }
     }
 
+    /// Learns the names that hosts on the local link advertise for their addresses in
+    /// mDNS announcements and responses, or in LLMNR responses.
+    pub fn handle_local_names(&mut self, data: &DnsMessage) {
+        if !data.is_response() {
+            return;
+        }
+
+        // mDNS responders often put their addresses in the additional section when
+        // answering for a service, so both sections are searched
+        let addresses = data
+            .answers
+            .iter()
+            .chain(&data.additional)
+            .filter_map(|record| match record.rdata {
+                DNSRData::A(ipv4) => Some((IpAddr::V4(ipv4), record)),
+                DNSRData::AAAA(ipv6) => Some((IpAddr::V6(ipv6), record)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        // A cache-flush record replaces the addresses of its name and family learned
+        // from earlier messages, e.g. after the host moved to a new address. The
+        // records of this message are inserted afterwards so that they are all kept.
+        for (ip, record) in &addresses {
+            if record.cache_flush() {
+                self.ip_to_local_name.retain(|old_ip, name| {
+                    old_ip.is_ipv4() != ip.is_ipv4() || !name.eq_ignore_ascii_case(&record.name)
+                });
+            }
+        }
+
+        for (ip, record) in addresses {
+            // A zero TTL is a goodbye announcement of a host leaving the network
+            if record.ttl == 0 {
+                if self
+                    .ip_to_local_name
+                    .get(&ip)
+                    .is_some_and(|name| name.eq_ignore_ascii_case(&record.name))
+                {
+                    self.ip_to_local_name.remove(&ip);
+                }
+            } else {
+                self.ip_to_local_name.insert(ip, record.name.clone());
+            }
+        }
+    }
+
+    /// Records the names and vendor classes that DHCP clients send, and the leases
+    /// that servers acknowledge for them.
+    pub fn handle_dhcp_event(&mut self, event: DhcpEvent) {
+        let message = event.message;
+
+        if message.message_type.is_from_client() {
+            let client = self
+                .dhcp_clients
+                .entry(message.client_id.clone())
+                .or_default();
+            if let Some(name) = message.client_name() {
+                client.hostname = Some(name.clone());
+            }
+            if let Some(vendor_class) = &message.vendor_class {
+                client.vendor_class = Some(vendor_class.clone());
+            }
+
+            // A released or declined address no longer belongs to the client
+            if matches!(
+                message.message_type,
+                DhcpMessageType::Release | DhcpMessageType::Decline
+            ) {
+                if let Some(ip) = message.requested_ip {
+                    if self
+                        .dhcp_leases
+                        .get(&ip)
+                        .is_some_and(|lease| lease.client_id == message.client_id)
+                    {
+                        self.dhcp_leases.remove(&ip);
+                    }
+                }
+            }
+            return;
+        }
+
+        if !matches!(
+            message.message_type,
+            DhcpMessageType::Ack | DhcpMessageType::Reply
+        ) {
+            return;
+        }
+
+        // Servers rarely repeat the client's name, so it is taken from the client's
+        // own messages when missing
+        let client = self.dhcp_clients.get(&message.client_id);
+        let hostname = message
+            .client_name()
+            .or(client.and_then(|client| client.hostname.as_ref()));
+        let vendor_class = message
+            .vendor_class
+            .as_ref()
+            .or(client.and_then(|client| client.vendor_class.as_ref()));
+
+        for ip in &message.assigned_ips {
+            self.dhcp_leases.insert(
+                *ip,
+                DhcpLease {
+                    client_id: message.client_id.clone(),
+                    hostname: hostname.cloned(),
+                    vendor_class: vendor_class.cloned(),
+                    server: message.server_id.unwrap_or(event.src.ip()),
+                    lease_time: message.lease_time,
+                    granted: event.timestamp,
+                },
+            );
+        }
+    }
+
+    /// Names the server of a TLS connection after the SNI in its ClientHello.
+    pub fn handle_tls_event(&mut self, event: TlsEvent) {
+        let hello = event.client_hello;
+        if let Some(server_name) = hello.server_name {
+            self.ip_to_server_name.insert(event.dst.ip(), server_name);
+        }
+        if !hello.alpn.is_empty() {
+            self.ip_to_alpn.insert(event.dst.ip(), hello.alpn);
+        }
+    }
+
+    /// Records a cleartext HTTP request against the server it was sent to.
+    pub fn handle_http_event(&mut self, event: HttpEvent) {
+        let request = event.request;
+        let server = self.http_servers.entry(event.dst.ip()).or_default();
+
+        server.num_requests += 1;
+        server.clients.insert(event.src.ip());
+        if let Some(name) = request.host_name() {
+            if name.parse::<IpAddr>().is_err() {
+                server.host = Some(name.to_string());
+            }
+        }
+        server.last_request = request.to_string();
+        if let Some(user_agent) = request.user_agent {
+            server.user_agents.insert(user_agent);
+        }
+        if let Some(content_type) = request.content_type {
+            server.content_types.insert(content_type);
+        }
+    }
+
     /// Updates the ip-domain mapping so that the ip points to the domain after
     /// handling any CNAME resolutions.
     pub fn update_ip_domain_mapping(&mut self, ip: IpAddr, domain: String) {
@@ -107,51 +755,148 @@ impl App {
         self.ip_to_domain.insert(ip, domain);
     }
 
-    pub fn handle_packet(&mut self, data: SnifferPacket) {
+    pub fn handle_packet(&mut self, interface: Arc<str>, data: SnifferPacket) {
+        if !self.interfaces.contains(&interface) {
+            self.interfaces.push(Arc::clone(&interface));
+        }
+
         if !self.host_info.contains_key(&data.src) {
             self.host_ips.push(data.src);
+        }
 
-            // Look up PTR record to resolve domain name
-            if let Some(domain) = reverse_lookup(data.src) {
-                self.ip_to_domain_fallback
-                    .insert(data.src, format!("!!: {}", domain));
-            }
+        // Look up PTR record to resolve domain name, until one is found
+        if !self.ip_to_domain_fallback.contains_key(&data.src) {
+            self.lookup_domain(data.src, Instant::now());
+        }
+
+        let info = self.host_info.entry(data.src).or_insert(HostInfo {
+            num_packets: 0,
+            num_bad_checksums: 0,
+            services: BTreeSet::new(),
+            interfaces: BTreeSet::new(),
+        });
+        info.num_packets += 1;
+        info.interfaces.insert(interface);
+
+        // The ports of a corrupted packet may be wrong, so it only counts towards the total
+        if data.bad_checksum {
+            info.num_bad_checksums += 1;
+            return;
+        }
+
+        // The well-known side of a connection usually has the lower port number,
+        // so we use it to identify the service regardless of traffic direction
+        if let (Some(src_port), Some(dst_port)) = (data.src_port, data.dst_port) {
+            info.services.insert(Service {
+                port: src_port.min(dst_port),
+                protocol: data.protocol,
+            });
+        }
+    }
+
+    /// Asks the background resolver for the PTR record of `ip`, unless it was already
+    /// asked less than [`resolver::FAILURE_TTL`] ago.
+    fn lookup_domain(&mut self, ip: IpAddr, now: Instant) {
+        let Some(resolver) = &self.resolver else {
+            return;
+        };
+        if self
+            .lookup_retries
+            .get(&ip)
+            .is_some_and(|retry| *retry > now)
+        {
+            return;
         }
 
-        self.host_info
-            .entry(data.src)
-            .and_modify(|e| (*e).num_packets += 1)
-            .or_insert(HostInfo { num_packets: 1 });
+        self.lookup_retries.insert(ip, now + resolver::FAILURE_TTL);
+        resolver.lookup(ip);
+    }
+
+    /// Records the result of a PTR lookup requested from the background resolver.
+    pub fn handle_reverse_lookup(&mut self, lookup: ReverseLookup) {
+        if let Some(domain) = lookup.domain {
+            self.lookup_retries.remove(&lookup.ip);
+            self.ip_to_domain_fallback
+                .insert(lookup.ip, format!("!!: {}", domain));
+        }
     }
 
-    /// Returns a list of network entries to render, ordered by insertion time
-    pub fn entries_to_render(&self) -> impl Iterator<Item = NetworkEntry> {
-        self.host_ips.iter().map(|ip| {
+    /// Returns a list of network entries to render, ordered by insertion time and
+    /// restricted to the interface filter if one is set
+    pub fn entries_to_render(&self) -> impl Iterator<Item = NetworkEntry<'_>> {
+        self.host_ips.iter().filter_map(|ip| {
             let info = self
                 .host_info
                 .get(ip)
-                .expect(format!("missing ip {} in host info", ip).as_str());
+                .unwrap_or_else(|| panic!("missing ip {} in host info", ip));
 
-            NetworkEntry {
+            if let Some(interface) = &self.interface_filter {
+                if !info.interfaces.contains(interface) {
+                    return None;
+                }
+            }
+
+            let lease = self.dhcp_leases.get(ip);
+            Some(NetworkEntry {
                 ip,
                 domain: self
-                    .ip_to_domain
+                    .ip_to_server_name
                     .get(ip)
+                    .or(self
+                        .http_servers
+                        .get(ip)
+                        .and_then(|server| server.host.as_ref()))
+                    .or(self.ip_to_domain.get(ip))
+                    .or(self.ip_to_local_name.get(ip))
+                    .or(lease.and_then(|lease| lease.hostname.as_ref()))
                     .or(self.ip_to_domain_fallback.get(ip)),
-                info: info,
-            }
+                device: lease.and_then(|lease| lease.device()),
+                alpn: self.ip_to_alpn.get(ip),
+                info,
+            })
         })
     }
 
+    /// Returns the latency statistics of every queried domain, slowest first.
+    pub fn domain_latencies(&self) -> Vec<(&String, &LatencyStats)> {
+        let mut domains = self.dns_tracker.domains.iter().collect::<Vec<_>>();
+        domains.sort_by(|(a_name, a), (b_name, b)| {
+            b.average()
+                .cmp(&a.average())
+                .then_with(|| a_name.cmp(b_name))
+        });
+        domains
+    }
+
     pub fn prev_entry(&mut self) {
-        let idx = self.state.selected().unwrap_or(0);
-        self.state
-            .select(if idx > 0 { Some(idx - 1) } else { Some(idx) });
+        let state = match self.tab {
+            Tab::Hosts => &mut self.state,
+            Tab::DnsLog => &mut self.log_state,
+            Tab::Latency => &mut self.latency_state,
+            Tab::Leases => &mut self.leases_state,
+            Tab::Http => &mut self.http_state,
+        };
+        let idx = state.selected().unwrap_or(0);
+        state.select(if idx > 0 { Some(idx - 1) } else { Some(idx) });
     }
 
     pub fn next_entry(&mut self) {
-        let idx = self.state.selected().unwrap_or(0);
-        self.state.select(if idx + 1 < self.host_ips.len() {
+        let count = match self.tab {
+            Tab::Hosts => self.entries_to_render().count(),
+            Tab::DnsLog => self.dns_log_entries().count(),
+            Tab::Latency => self.dns_tracker.domains.len(),
+            Tab::Leases => self.dhcp_leases.len(),
+            Tab::Http => self.http_servers.len(),
+        };
+        let state = match self.tab {
+            Tab::Hosts => &mut self.state,
+            Tab::DnsLog => &mut self.log_state,
+            Tab::Latency => &mut self.latency_state,
+            Tab::Leases => &mut self.leases_state,
+            Tab::Http => &mut self.http_state,
+        };
+        let idx = state.selected().unwrap_or(0);
+        state.select(if idx + 1 < count {
             Some(idx + 1)
         } else {
             Some(idx)
@@ -162,7 +907,111 @@ impl App {
         self.inv_cname_map.clear();
         self.host_ips.clear();
         self.host_info.clear();
+        self.ip_to_server_name.clear();
+        self.ip_to_alpn.clear();
+        self.http_servers.clear();
         self.ip_to_domain.clear();
+        self.ip_to_local_name.clear();
+        self.dhcp_clients.clear();
+        self.dhcp_leases.clear();
         self.ip_to_domain_fallback.clear();
+        self.lookup_retries.clear();
+        self.dns_tracker.clear();
+        self.dns_log.clear();
+        self.dns_pending.clear();
+        self.dns_pending_changed = false;
+        self.num_parse_errors = 0;
+        self.last_parse_error = None;
+        self.interfaces.clear();
+        self.interface_filter = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::dns::{self, DnsResourceRecord};
+    use crate::network::resolver::ResolverConfig;
+    use std::net::{Ipv6Addr, UdpSocket};
+    use std::sync::mpsc::{self, Receiver};
+    use std::thread;
+
+    /// Starts a DNS server on localhost that answers every PTR query with
+    /// `host.test`, once something is sent on the returned channel.
+    fn start_dns_server() -> (ResolverConfig, mpsc::Sender<()>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let config = ResolverConfig {
+            nameservers: vec![socket.local_addr().unwrap()],
+            search: Vec::new(),
+            timeout: Duration::from_secs(10),
+            attempts: 1,
+        };
+        let (start, started) = mpsc::channel();
+
+        thread::spawn(move || {
+            let _ = started.recv();
+            let mut buffer = [0u8; 512];
+            while let Ok((size, client)) = socket.recv_from(&mut buffer) {
+                let mut message = DnsMessage::parse(&buffer[..size]).unwrap();
+                message.header.flags.qr = true;
+                message.header.an_count = 1;
+                message.answers.push(DnsResourceRecord {
+                    name: message.questions[0].qname.clone(),
+                    rtype: dns::TYPE_PTR,
+                    rclass: 1,
+                    ttl: 300,
+                    rdata: DNSRData::PTR("host.test".to_string()),
+                });
+                socket.send_to(&message.encode().unwrap(), client).unwrap();
+            }
+        });
+
+        (config, start)
+    }
+
+    /// Hands every lookup result to the app until none arrive for a while.
+    fn receive_lookups(app: &mut App, results: &Receiver<ReverseLookup>) -> Vec<IpAddr> {
+        let mut resolved = Vec::new();
+        while let Ok(lookup) = results.recv_timeout(Duration::from_secs(1)) {
+            resolved.push(lookup.ip);
+            app.handle_reverse_lookup(lookup);
+        }
+        resolved
+    }
+
+    #[test]
+    fn looks_up_again_host_dropped_from_full_queue() {
+        let (config, start) = start_dns_server();
+        let (sender, results) = mpsc::channel();
+        let mut app = App::new();
+        app.resolver = Some(Resolver::new(config, sender));
+
+        // The workers wait on the server, so the queue fills up long before the last
+        // of these lookups is requested
+        let now = Instant::now();
+        let ips = (1..=1024u128)
+            .map(|index| IpAddr::V6(Ipv6Addr::from(index)))
+            .collect::<Vec<_>>();
+        for ip in &ips {
+            app.lookup_domain(*ip, now);
+        }
+        let last = *ips.last().unwrap();
+
+        start.send(()).unwrap();
+        let resolved = receive_lookups(&mut app, &results);
+        assert!(!resolved.is_empty());
+        assert!(!resolved.contains(&last));
+
+        // Nothing is requested again until the failure TTL has passed
+        app.lookup_domain(last, now + Duration::from_secs(1));
+        assert!(receive_lookups(&mut app, &results).is_empty());
+
+        app.lookup_domain(last, now + resolver::FAILURE_TTL);
+        assert_eq!(receive_lookups(&mut app, &results), [last]);
+        assert_eq!(
+            app.ip_to_domain_fallback.get(&last).map(String::as_str),
+            Some("!!: host.test")
+        );
+        assert!(!app.lookup_retries.contains_key(&last));
     }
 }
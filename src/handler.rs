@@ -1,7 +1,14 @@
-use crate::app::{App, AppResult};
+use crate::app::{App, AppResult, Tab};
 use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 
 pub fn handle_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
+    if app.interface_picker.is_some() {
+        return handle_picker_key_events(key_event, app);
+    }
+    if app.editing_log_filter {
+        return handle_filter_key_events(key_event, app);
+    }
+
     match key_event.code {
         KeyCode::Esc | KeyCode::Char('q') => {
             app.quit();
@@ -19,6 +26,73 @@ pub fn handle_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
                 app.clear();
             }
         }
+        KeyCode::Char('i') | KeyCode::Char('I') => {
+            app.cycle_interface_filter();
+        }
+        KeyCode::Tab => {
+            app.next_tab();
+        }
+        KeyCode::Char('/') if app.tab == Tab::DnsLog => {
+            app.edit_log_filter();
+        }
+        KeyCode::Char('r') | KeyCode::Char('R') if app.tab == Tab::DnsLog => {
+            app.cycle_log_rcode_filter();
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn handle_filter_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
+    match key_event.code {
+        KeyCode::Char('c') | KeyCode::Char('C') if key_event.modifiers == KeyModifiers::CONTROL => {
+            app.quit();
+        }
+        KeyCode::Enter | KeyCode::Esc => {
+            app.editing_log_filter = false;
+        }
+        KeyCode::Backspace => {
+            app.pop_log_filter();
+        }
+        KeyCode::Char(c) => {
+            app.push_log_filter(c);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn handle_picker_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
+    match key_event.code {
+        KeyCode::Esc | KeyCode::Char('q') => {
+            app.quit();
+        }
+        KeyCode::Char('c') | KeyCode::Char('C') if key_event.modifiers == KeyModifiers::CONTROL => {
+            app.quit();
+        }
+        KeyCode::Up | KeyCode::Char('k') => {
+            if let Some(picker) = app.interface_picker.as_mut() {
+                picker.prev_entry();
+            }
+        }
+        KeyCode::Down | KeyCode::Char('j') => {
+            if let Some(picker) = app.interface_picker.as_mut() {
+                picker.next_entry();
+            }
+        }
+        KeyCode::Char(' ') => {
+            if let Some(picker) = app.interface_picker.as_mut() {
+                picker.toggle_mark();
+            }
+        }
+        KeyCode::Char('a') | KeyCode::Char('A') => {
+            if let Some(picker) = app.interface_picker.as_mut() {
+                picker.toggle_mark_all();
+            }
+        }
+        KeyCode::Enter => {
+            app.choose_interfaces();
+        }
         _ => {}
     }
     Ok(())
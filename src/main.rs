@@ -1,39 +1,88 @@
+use pcap::Device;
 use ratatui::backend::CrosstermBackend;
 use ratatui::Terminal;
 use std::env;
-use std::net::{IpAddr, Ipv4Addr};
-use std::sync::mpsc;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender};
 use std::{io, thread};
-use wirecrab::app::{App, AppResult};
+use wirecrab::app::{App, AppResult, InterfacePicker};
 use wirecrab::event::{Event, EventHandler};
 use wirecrab::handler::handle_key_events;
-use wirecrab::network::sniffer::{Sniffer, SnifferPacket};
+use wirecrab::network::interface;
+use wirecrab::network::resolver::{self, Resolver, ResolverConfig};
+use wirecrab::network::sniffer::{CaptureEvent, Sniffer};
 use wirecrab::tui::Tui;
 
 fn main() -> AppResult<()> {
     let args = env::args().collect::<Vec<_>>();
     let debug = args.iter().any(|arg| arg == "--debug");
+    let realtime = args.iter().any(|arg| arg == "--realtime");
+    let read_file = arg_values(&args, "--read").next().map(PathBuf::from);
+    let interface_names = arg_values(&args, "--interface").collect::<Vec<_>>();
+    let all_interfaces = args.iter().any(|arg| arg == "--all-interfaces");
+    let ignore_local_checksums = args.iter().any(|arg| arg == "--ignore-local-checksums");
+
+    if args.iter().any(|arg| arg == "--list-interfaces") {
+        return list_interfaces();
+    }
+
+    // Servers given with --resolver replace the ones from resolv.conf
+    let mut resolver_config = ResolverConfig::system();
+    let resolver_overrides = arg_values(&args, "--resolver")
+        .map(|server| {
+            resolver::parse_nameserver(server)
+                .ok_or_else(|| format!("invalid --resolver address {}", server))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if !resolver_overrides.is_empty() {
+        resolver_config.nameservers = resolver_overrides;
+    }
 
     let mut app = App::new();
 
+    let (tx_lookup, rx_lookup) = mpsc::channel();
+    app.resolver = Some(Resolver::new(resolver_config, tx_lookup));
+
     let backend = CrosstermBackend::new(io::stderr());
     let terminal = Terminal::new(backend)?;
     let events = EventHandler::new(250);
 
-    // TODO: move into a single Sniffer instance
-    // TODO: sniff across all network devices
-    let dns = Sniffer::new("en0".into());
-    let sniffer = Sniffer::new("en0".into());
+    // Every interface is captured on its own thread, and all of them send their
+    // events over the same channel
     let (tx, rx) = mpsc::channel();
-    let (tx_dns, rx_dns) = mpsc::channel();
-
-    let _t = thread::spawn(move || {
-        sniffer.start_packet_capture(tx);
-    });
-
-    let _t = thread::spawn(move || {
-        dns.start_dns_capture(tx_dns);
-    });
+    if let Some(path) = read_file {
+        spawn_capture(
+            Sniffer::from_file(path, realtime),
+            &tx,
+            ignore_local_checksums,
+        )?;
+    } else if all_interfaces {
+        for device in interface::active_devices()? {
+            spawn_capture(Sniffer::new(device), &tx, ignore_local_checksums)?;
+        }
+    } else if !interface_names.is_empty() {
+        for name in interface_names {
+            spawn_capture(
+                Sniffer::new(find_device(name)?),
+                &tx,
+                ignore_local_checksums,
+            )?;
+        }
+    } else if debug {
+        // There is no picker without the TUI, so capture on the default route's interface
+        let name = interface::default_interface()
+            .ok_or("no default interface found, pass one with --interface")?;
+        spawn_capture(
+            Sniffer::new(find_device(&name)?),
+            &tx,
+            ignore_local_checksums,
+        )?;
+    } else {
+        app.interface_picker = Some(InterfacePicker::new(
+            interface::list_devices()?,
+            interface::default_interface(),
+        ));
+    }
 
     let mut tui = Tui::new(terminal, events);
     if !debug {
@@ -41,12 +90,19 @@ fn main() -> AppResult<()> {
     }
 
     while app.running {
-        while let Ok(data) = rx.try_recv() {
-            app.handle_packet(data);
+        for device in std::mem::take(&mut app.chosen_interfaces) {
+            let interface = device.name.as_str().into();
+            if let Err(error) = spawn_capture(Sniffer::new(device), &tx, ignore_local_checksums) {
+                app.handle_capture_error(interface, error);
+            }
+        }
+
+        while let Ok(event) = rx.try_recv() {
+            app.handle_capture_event(event);
         }
 
-        while let Ok(data) = rx_dns.try_recv() {
-            app.handle_dns_message(data);
+        while let Ok(lookup) = rx_lookup.try_recv() {
+            app.handle_reverse_lookup(lookup);
         }
 
         if !debug {
@@ -64,3 +120,50 @@ fn main() -> AppResult<()> {
     tui.exit()?;
     Ok(())
 }
+
+fn find_device(name: &str) -> AppResult<Device> {
+    Ok(interface::find_device(name)?
+        .ok_or_else(|| format!("interface {} not found, see --list-interfaces", name))?)
+}
+
+/// Opens the sniffer's capture and starts capturing on a thread of its own, or
+/// returns the error if the capture cannot be opened.
+fn spawn_capture(
+    sniffer: Sniffer,
+    tx: &Sender<CaptureEvent>,
+    ignore_local_checksums: bool,
+) -> Result<(), pcap::Error> {
+    let sniffer = sniffer.ignore_local_checksums(ignore_local_checksums);
+    let cap = sniffer.open()?;
+
+    let tx = tx.clone();
+    thread::spawn(move || sniffer.start_capture(cap, tx));
+    Ok(())
+}
+
+/// Prints the capture devices with their flags and addresses.
+fn list_interfaces() -> AppResult<()> {
+    let default = interface::default_interface();
+    for device in interface::list_devices()? {
+        let marker = if Some(&device.name) == default.as_ref() {
+            "*"
+        } else {
+            " "
+        };
+        println!(
+            "{} {:<16} {:<28} {}",
+            marker,
+            device.name,
+            interface::describe_flags(&device),
+            interface::describe_addresses(&device)
+        );
+    }
+    Ok(())
+}
+
+/// Returns the value following each occurrence of `flag` in the command line arguments.
+fn arg_values<'a>(args: &'a [String], flag: &'a str) -> impl Iterator<Item = &'a String> {
+    args.windows(2)
+        .filter(move |pair| pair[0] == flag)
+        .map(|pair| &pair[1])
+}
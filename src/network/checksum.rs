@@ -0,0 +1,61 @@
+use std::net::IpAddr;
+
+// Internet checksum: https://datatracker.ietf.org/doc/html/rfc1071
+
+/// Adds the 16 bit big endian words of `data` to `sum`, padding an odd trailing byte
+/// with zero. The carries are folded in by [`fold`].
+fn add_words(mut sum: u32, data: &[u8]) -> u32 {
+    let mut chunks = data.chunks_exact(2);
+    for word in &mut chunks {
+        sum += u16::from_be_bytes([word[0], word[1]]) as u32;
+    }
+    if let [last] = chunks.remainder() {
+        sum += u16::from_be_bytes([*last, 0]) as u32;
+    }
+    sum
+}
+
+/// Folds the carries of a 32 bit sum into a 16 bit one's complement sum.
+fn fold(mut sum: u32) -> u16 {
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    sum as u16
+}
+
+/// Returns the sum of the pseudo header that the TCP and UDP checksums cover in
+/// addition to the segment itself.
+fn pseudo_header_sum(src: IpAddr, dst: IpAddr, protocol: u8, length: usize) -> u32 {
+    match (src, dst) {
+        // https://datatracker.ietf.org/doc/html/rfc9293#section-3.1
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            let sum = add_words(add_words(0, &src.octets()), &dst.octets());
+            sum + protocol as u32 + length as u32
+        }
+        // https://datatracker.ietf.org/doc/html/rfc8200#section-8.1
+        (src, dst) => {
+            let sum = add_words(add_words(0, &ip_octets(src)), &ip_octets(dst));
+            add_words(sum, &(length as u32).to_be_bytes()) + protocol as u32
+        }
+    }
+}
+
+fn ip_octets(ip: IpAddr) -> [u8; 16] {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped().octets(),
+        IpAddr::V6(ip) => ip.octets(),
+    }
+}
+
+/// Returns true if the checksum stored in an IPv4 header is correct. The sum over a
+/// header including its checksum field is all ones.
+pub fn verify_ipv4_header(header: &[u8]) -> bool {
+    fold(add_words(0, header)) == 0xFFFF
+}
+
+/// Returns true if the checksum of a TCP or UDP `segment`, including its header, is
+/// correct for the given addresses and protocol number.
+pub fn verify_transport(src: IpAddr, dst: IpAddr, protocol: u8, segment: &[u8]) -> bool {
+    let sum = pseudo_header_sum(src, dst, protocol, segment.len());
+    fold(add_words(sum, segment)) == 0xFFFF
+}
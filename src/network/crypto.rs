@@ -0,0 +1,479 @@
+// The primitives needed to remove the protection of QUIC Initial packets, whose
+// keys are derived from values sent in the clear. They are only ever used to read
+// captured traffic, so they favour simplicity over speed and side channel resistance.
+//
+// They are written out here rather than taken from a crate such as `ring` or
+// `aes-gcm` because none of what a vetted implementation guards is at stake: there
+// are no secrets to leak, as anyone on the path can derive the same keys, and a
+// wrong result only means that a packet is not decoded. Three short algorithms
+// against known-answer tests cost less than a cryptography dependency tree for a
+// sniffer that otherwise only depends on pcap and ratatui.
+
+// SHA-256: https://nvlpubs.nist.gov/nistpubs/FIPS/NIST.FIPS.180-4.pdf - section 6.2
+const SHA256_INITIAL_HASH: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+const SHA256_ROUND_CONSTANTS: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+const SHA256_BLOCK_LENGTH: usize = 64;
+
+/// Returns the SHA-256 digest of `data`.
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    // Pad with a one bit, zeros and the length in bits to a whole number of blocks
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % SHA256_BLOCK_LENGTH != SHA256_BLOCK_LENGTH - 8 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    let mut hash = SHA256_INITIAL_HASH;
+    for block in message.chunks_exact(SHA256_BLOCK_LENGTH) {
+        let mut schedule = [0u32; 64];
+        for (word, bytes) in schedule.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for t in 16..64 {
+            let s0 = schedule[t - 15].rotate_right(7)
+                ^ schedule[t - 15].rotate_right(18)
+                ^ (schedule[t - 15] >> 3);
+            let s1 = schedule[t - 2].rotate_right(17)
+                ^ schedule[t - 2].rotate_right(19)
+                ^ (schedule[t - 2] >> 10);
+            schedule[t] = schedule[t - 16]
+                .wrapping_add(s0)
+                .wrapping_add(schedule[t - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = hash;
+        for t in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(SHA256_ROUND_CONSTANTS[t])
+                .wrapping_add(schedule[t]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(majority);
+
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+
+        for (word, value) in hash.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *word = word.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0u8; 32];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(hash) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+/// Returns the HMAC-SHA256 of `data` under `key` (RFC 2104).
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; SHA256_BLOCK_LENGTH];
+    if key.len() > SHA256_BLOCK_LENGTH {
+        block[..32].copy_from_slice(&sha256(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = block.map(|byte| byte ^ 0x36).to_vec();
+    inner.extend_from_slice(data);
+    let mut outer = block.map(|byte| byte ^ 0x5c).to_vec();
+    outer.extend_from_slice(&sha256(&inner));
+    sha256(&outer)
+}
+
+/// Extracts a pseudorandom key from `ikm` (RFC 5869 section 2.2).
+pub fn hkdf_extract(salt: &[u8], ikm: &[u8]) -> [u8; 32] {
+    hmac_sha256(salt, ikm)
+}
+
+/// Expands a secret into `length` bytes of keying material for the given label, as
+/// TLS 1.3 and QUIC do (RFC 8446 section 7.1).
+pub fn hkdf_expand_label(secret: &[u8], label: &str, context: &[u8], length: usize) -> Vec<u8> {
+    let label = format!("tls13 {}", label);
+    let mut info = (length as u16).to_be_bytes().to_vec();
+    info.push(label.len() as u8);
+    info.extend_from_slice(label.as_bytes());
+    info.push(context.len() as u8);
+    info.extend_from_slice(context);
+
+    // HKDF-Expand: https://datatracker.ietf.org/doc/html/rfc5869#section-2.3
+    let mut output = Vec::with_capacity(length);
+    let mut previous: Vec<u8> = Vec::new();
+    let mut counter = 1u8;
+    while output.len() < length {
+        let mut input = previous;
+        input.extend_from_slice(&info);
+        input.push(counter);
+        previous = hmac_sha256(secret, &input).to_vec();
+        output.extend_from_slice(&previous);
+        counter += 1;
+    }
+    output.truncate(length);
+    output
+}
+
+// AES: https://nvlpubs.nist.gov/nistpubs/FIPS/NIST.FIPS.197-upd1.pdf
+const AES_SBOX: [u8; 256] = aes_sbox();
+
+/// Builds the AES S-box, which maps each byte to its multiplicative inverse in
+/// GF(2^8) followed by an affine transformation (FIPS 197 section 5.1.1).
+const fn aes_sbox() -> [u8; 256] {
+    let mut sbox = [0u8; 256];
+    sbox[0] = 0x63;
+
+    // p walks every non-zero element as a power of 3 while q walks its inverse
+    let mut p: u8 = 1;
+    let mut q: u8 = 1;
+    loop {
+        p = p ^ (p << 1) ^ if p & 0x80 != 0 { 0x1b } else { 0 };
+
+        q ^= q << 1;
+        q ^= q << 2;
+        q ^= q << 4;
+        if q & 0x80 != 0 {
+            q ^= 0x09;
+        }
+
+        sbox[p as usize] =
+            q ^ q.rotate_left(1) ^ q.rotate_left(2) ^ q.rotate_left(3) ^ q.rotate_left(4) ^ 0x63;
+        if p == 1 {
+            break;
+        }
+    }
+    sbox
+}
+
+/// Multiplies by x in GF(2^8).
+fn xtime(byte: u8) -> u8 {
+    (byte << 1) ^ if byte & 0x80 != 0 { 0x1b } else { 0 }
+}
+
+/// The AES-128 block cipher. Only encryption is needed, as header protection
+/// and GCM both run the cipher forwards.
+#[derive(Debug, Clone)]
+pub struct Aes128 {
+    round_keys: [[u8; 16]; 11],
+}
+
+impl Aes128 {
+    pub fn new(key: &[u8; 16]) -> Self {
+        // Key expansion: https://nvlpubs.nist.gov/nistpubs/FIPS/NIST.FIPS.197-upd1.pdf - section 5.2
+        let mut words = [[0u8; 4]; 44];
+        for (word, bytes) in words.iter_mut().zip(key.chunks_exact(4)) {
+            word.copy_from_slice(bytes);
+        }
+
+        let mut round_constant = 1u8;
+        for i in 4..44 {
+            let mut word = words[i - 1];
+            if i % 4 == 0 {
+                word.rotate_left(1);
+                word = word.map(|byte| AES_SBOX[byte as usize]);
+                word[0] ^= round_constant;
+                round_constant = xtime(round_constant);
+            }
+            for (byte, previous) in word.iter_mut().zip(words[i - 4]) {
+                *byte ^= previous;
+            }
+            words[i] = word;
+        }
+
+        let mut round_keys = [[0u8; 16]; 11];
+        for (round_key, round_words) in round_keys.iter_mut().zip(words.chunks_exact(4)) {
+            for (bytes, word) in round_key.chunks_exact_mut(4).zip(round_words) {
+                bytes.copy_from_slice(word);
+            }
+        }
+        Self { round_keys }
+    }
+
+    /// Encrypts a single block in place. The state is kept column by column, as
+    /// the bytes of a block are laid out.
+    pub fn encrypt_block(&self, block: &mut [u8; 16]) {
+        add_round_key(block, &self.round_keys[0]);
+        for round in 1..11 {
+            for byte in block.iter_mut() {
+                *byte = AES_SBOX[*byte as usize];
+            }
+            shift_rows(block);
+            if round != 10 {
+                mix_columns(block);
+            }
+            add_round_key(block, &self.round_keys[round]);
+        }
+    }
+}
+
+fn add_round_key(block: &mut [u8; 16], round_key: &[u8; 16]) {
+    for (byte, key) in block.iter_mut().zip(round_key) {
+        *byte ^= key;
+    }
+}
+
+/// Rotates row r of the state left by r columns.
+fn shift_rows(block: &mut [u8; 16]) {
+    let state = *block;
+    for column in 0..4 {
+        for row in 0..4 {
+            block[column * 4 + row] = state[((column + row) % 4) * 4 + row];
+        }
+    }
+}
+
+/// Multiplies each column of the state by the fixed MixColumns polynomial.
+fn mix_columns(block: &mut [u8; 16]) {
+    for column in block.chunks_exact_mut(4) {
+        let [a0, a1, a2, a3] = [column[0], column[1], column[2], column[3]];
+        let all = a0 ^ a1 ^ a2 ^ a3;
+        column[0] = a0 ^ all ^ xtime(a0 ^ a1);
+        column[1] = a1 ^ all ^ xtime(a1 ^ a2);
+        column[2] = a2 ^ all ^ xtime(a2 ^ a3);
+        column[3] = a3 ^ all ^ xtime(a3 ^ a0);
+    }
+}
+
+// GCM: https://nvlpubs.nist.gov/nistpubs/Legacy/SP/nistspecialpublication800-38d.pdf
+const GCM_TAG_LENGTH: usize = 16;
+
+/// AES-128 in Galois/Counter Mode with 96-bit nonces and 128-bit tags.
+#[derive(Debug, Clone)]
+pub struct Aes128Gcm {
+    cipher: Aes128,
+
+    // The hash subkey, the encryption of the zero block
+    hash_key: u128,
+}
+
+impl Aes128Gcm {
+    pub fn new(key: &[u8; 16]) -> Self {
+        let cipher = Aes128::new(key);
+        let mut hash_key = [0u8; 16];
+        cipher.encrypt_block(&mut hash_key);
+        Self {
+            cipher,
+            hash_key: u128::from_be_bytes(hash_key),
+        }
+    }
+
+    /// Decrypts `ciphertext`, which ends with its authentication tag, returning
+    /// `None` if the tag does not match the ciphertext and `aad`.
+    pub fn open(&self, nonce: &[u8; 12], aad: &[u8], ciphertext: &[u8]) -> Option<Vec<u8>> {
+        let tag_start = ciphertext.len().checked_sub(GCM_TAG_LENGTH)?;
+        let (ciphertext, tag) = ciphertext.split_at(tag_start);
+
+        let difference = self
+            .tag(nonce, aad, ciphertext)
+            .iter()
+            .zip(tag)
+            .fold(0, |difference, (a, b)| difference | (a ^ b));
+        if difference != 0 {
+            return None;
+        }
+
+        let mut plaintext = ciphertext.to_vec();
+        self.apply_keystream(nonce, &mut plaintext);
+        Some(plaintext)
+    }
+
+    /// Encrypts `plaintext` and appends its authentication tag. Only the tests
+    /// encrypt, to build the packets that the decoders are checked against.
+    #[cfg(test)]
+    pub fn seal(&self, nonce: &[u8; 12], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let mut ciphertext = plaintext.to_vec();
+        self.apply_keystream(nonce, &mut ciphertext);
+        let tag = self.tag(nonce, aad, &ciphertext);
+        ciphertext.extend_from_slice(&tag);
+        ciphertext
+    }
+
+    /// Returns the tag of the ciphertext and `aad`: their hash, masked with the
+    /// encryption of the first counter block.
+    fn tag(&self, nonce: &[u8; 12], aad: &[u8], ciphertext: &[u8]) -> [u8; 16] {
+        let mut tag = counter_block(nonce, 1);
+        self.cipher.encrypt_block(&mut tag);
+        let hash = self.ghash(aad, ciphertext).to_be_bytes();
+        for (byte, hash) in tag.iter_mut().zip(hash) {
+            *byte ^= hash;
+        }
+        tag
+    }
+
+    /// Encrypts or decrypts `data` in counter mode, starting from the block after
+    /// the one that masks the tag.
+    fn apply_keystream(&self, nonce: &[u8; 12], data: &mut [u8]) {
+        for (index, chunk) in data.chunks_mut(16).enumerate() {
+            let mut keystream = counter_block(nonce, (index as u32).wrapping_add(2));
+            self.cipher.encrypt_block(&mut keystream);
+            for (byte, key) in chunk.iter_mut().zip(keystream) {
+                *byte ^= key;
+            }
+        }
+    }
+
+    /// Hashes the zero padded AAD and ciphertext followed by their lengths in bits.
+    fn ghash(&self, aad: &[u8], ciphertext: &[u8]) -> u128 {
+        let mut hash = 0u128;
+        for data in [aad, ciphertext] {
+            for chunk in data.chunks(16) {
+                let mut block = [0u8; 16];
+                block[..chunk.len()].copy_from_slice(chunk);
+                hash = gf128_multiply(hash ^ u128::from_be_bytes(block), self.hash_key);
+            }
+        }
+
+        let lengths = ((aad.len() as u128 * 8) << 64) | (ciphertext.len() as u128 * 8);
+        gf128_multiply(hash ^ lengths, self.hash_key)
+    }
+}
+
+/// Returns the counter block for the given block number of a 96-bit nonce.
+fn counter_block(nonce: &[u8; 12], counter: u32) -> [u8; 16] {
+    let mut block = [0u8; 16];
+    block[..12].copy_from_slice(nonce);
+    block[12..].copy_from_slice(&counter.to_be_bytes());
+    block
+}
+
+/// Multiplies two elements of GF(2^128) in the bit order GCM uses, where the most
+/// significant bit holds the coefficient of x^0 (SP 800-38D section 6.3).
+fn gf128_multiply(x: u128, y: u128) -> u128 {
+    const R: u128 = 0xe1 << 120;
+
+    let mut product = 0;
+    let mut v = y;
+    for i in 0..128 {
+        if x & (1 << (127 - i)) != 0 {
+            product ^= v;
+        }
+        v = if v & 1 != 0 { (v >> 1) ^ R } else { v >> 1 };
+    }
+    product
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(string: &str) -> Vec<u8> {
+        (0..string.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&string[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    // FIPS 180-4 example "abc"
+    #[test]
+    fn sha256_known_answer() {
+        assert_eq!(
+            sha256(b"abc").to_vec(),
+            hex("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+        );
+        assert_eq!(
+            sha256(b"").to_vec(),
+            hex("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+        );
+    }
+
+    // RFC 9001 appendix A.1, which also exercises HMAC and HKDF-Extract
+    #[test]
+    fn hkdf_expand_label_known_answer() {
+        let initial_salt = hex("38762cf7f55934b34d179ae6a4c80cadccbb7f0a");
+        let initial_secret = hkdf_extract(&initial_salt, &hex("8394c8f03e515708"));
+        assert_eq!(
+            initial_secret.to_vec(),
+            hex("7db5df06e7a69e432496adedb00851923595221596ae2ae9fb8115c1e9ed0a44")
+        );
+
+        let client_secret = hkdf_expand_label(&initial_secret, "client in", &[], 32);
+        assert_eq!(
+            client_secret,
+            hex("c00cf151ca5be075ed0ebfb5c80323c42d6b7db67881289af4008f1f6c357aea")
+        );
+        assert_eq!(
+            hkdf_expand_label(&client_secret, "quic key", &[], 16),
+            hex("1f369613dd76d5467730efcbe3b1a22d")
+        );
+        assert_eq!(
+            hkdf_expand_label(&client_secret, "quic iv", &[], 12),
+            hex("fa044b2f42a3fd3b46fb255c")
+        );
+        assert_eq!(
+            hkdf_expand_label(&client_secret, "quic hp", &[], 16),
+            hex("9f50449e04a0e810283a1e9933adedd2")
+        );
+    }
+
+    // FIPS 197 appendix C.1
+    #[test]
+    fn aes128_known_answer() {
+        let cipher = Aes128::new(&hex("000102030405060708090a0b0c0d0e0f").try_into().unwrap());
+        let mut block = hex("00112233445566778899aabbccddeeff").try_into().unwrap();
+        cipher.encrypt_block(&mut block);
+        assert_eq!(block.to_vec(), hex("69c4e0d86a7b0430d8cdb78070b4c55a"));
+    }
+
+    // GCM specification test case 2: one block, no AAD
+    #[test]
+    fn aes128_gcm_test_case_2() {
+        let gcm = Aes128Gcm::new(&[0; 16]);
+        let ciphertext = hex(concat!(
+            "0388dace60b6a392f328c2b971b2fe78",
+            "ab6e47d42cec13bdf53a67b21257bddf"
+        ));
+        assert_eq!(gcm.open(&[0; 12], &[], &ciphertext), Some(vec![0; 16]));
+        assert_eq!(gcm.seal(&[0; 12], &[], &[0; 16]), ciphertext);
+    }
+
+    // GCM specification test case 4: partial last block and AAD
+    #[test]
+    fn aes128_gcm_test_case_4() {
+        let gcm = Aes128Gcm::new(&hex("feffe9928665731c6d6a8f9467308308").try_into().unwrap());
+        let nonce = hex("cafebabefacedbaddecaf888").try_into().unwrap();
+        let aad = hex("feedfacedeadbeeffeedfacedeadbeefabaddad2");
+        let mut ciphertext = hex(concat!(
+            "42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e",
+            "21d514b25466931c7d8f6a5aac84aa051ba30b396a0aac973d58e091",
+            "5bc94fbc3221a5db94fae95ae7121a47"
+        ));
+        let plaintext = hex(concat!(
+            "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a72",
+            "1c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b39"
+        ));
+        assert_eq!(gcm.seal(&nonce, &aad, &plaintext), ciphertext);
+        assert_eq!(gcm.open(&nonce, &aad, &ciphertext), Some(plaintext));
+
+        // Any change to the AAD, ciphertext or tag is rejected
+        assert_eq!(gcm.open(&nonce, &aad[1..], &ciphertext), None);
+        ciphertext[0] ^= 1;
+        assert_eq!(gcm.open(&nonce, &aad, &ciphertext), None);
+        ciphertext[0] ^= 1;
+        *ciphertext.last_mut().unwrap() ^= 1;
+        assert_eq!(gcm.open(&nonce, &aad, &ciphertext), None);
+    }
+}
@@ -0,0 +1,857 @@
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use pcap::Linktype;
+
+use crate::network::checksum;
+use crate::network::dhcp::{
+    self, DHCPV6_CLIENT_PORT, DHCPV6_SERVER_PORT, DHCP_CLIENT_PORT, DHCP_SERVER_PORT,
+};
+use crate::network::dns::{self, DnsProtocol, DNS_PORT};
+use crate::network::error::DecodeResult;
+use crate::network::http::{self, HTTP_PORT};
+use crate::network::ip;
+use crate::network::ip::IpProtocol;
+use crate::network::link::{self, ETHERTYPE_IPV4, ETHERTYPE_IPV6};
+use crate::network::quic::{InitialDecoder, QUIC_PORT};
+use crate::network::reassembly::{FragmentKey, FragmentReassembler};
+use crate::network::sniffer::{
+    DhcpEvent, DnsEvent, HttpEvent, SnifferEvent, SnifferPacket, TlsEvent,
+};
+use crate::network::stream::{FlowKey, StreamReassembler};
+use crate::network::tcp::{self, TcpSegment};
+use crate::network::tls;
+use crate::network::udp;
+
+/// Decodes captured frames into [`SnifferEvent`]s, keeping the state that spans
+/// multiple frames such as partially reassembled datagrams and TCP streams.
+#[derive(Debug, Default)]
+pub struct Decoder {
+    fragments: FragmentReassembler,
+    streams: StreamReassembler,
+    quic: InitialDecoder,
+
+    // Sources whose checksums are not verified, see `skip_checksums_from`
+    unverified_sources: HashSet<IpAddr>,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stops verifying the checksums of packets sent from the given addresses.
+    ///
+    /// Interfaces with checksum offloading leave the checksums of outgoing packets to
+    /// the network card, so packets captured on the sending host carry checksums
+    /// that would otherwise be counted as bad.
+    pub fn skip_checksums_from(&mut self, addresses: impl IntoIterator<Item = IpAddr>) {
+        self.unverified_sources.extend(addresses);
+    }
+
+    /// Decodes a single frame of the given link type through the ip, transport and
+    /// application layers, returning the events it produces in order. `timestamp` is the capture
+    /// time of the frame.
+    ///
+    /// A frame that fails to decode produces a [`SnifferEvent::ParseError`] after any
+    /// events decoded before the failure. Fragments produce no events until the
+    /// datagram they belong to has been reassembled.
+    pub fn decode_frame(
+        &mut self,
+        linktype: Linktype,
+        timestamp: Duration,
+        data: &[u8],
+    ) -> Vec<SnifferEvent> {
+        let mut events = Vec::new();
+        if let Err(error) = self.decode_link_frame(linktype, timestamp, data, &mut events) {
+            events.push(SnifferEvent::ParseError(error));
+        }
+        events
+    }
+
+    fn decode_link_frame(
+        &mut self,
+        linktype: Linktype,
+        timestamp: Duration,
+        data: &[u8],
+        events: &mut Vec<SnifferEvent>,
+    ) -> DecodeResult<()> {
+        let frame = link::parse_link_frame(linktype, data)?;
+
+        // Holds the payload of a datagram reassembled from this frame and earlier fragments
+        let reassembled;
+
+        let (src, dst, protocol, ip_payload, truncated) = if frame.ethertype == ETHERTYPE_IPV4 {
+            let packet = ip::parse_ipv4_packet(frame.payload)?;
+            let (src, dst) = (IpAddr::V4(packet.src), IpAddr::V4(packet.dst));
+
+            // Nothing past a corrupted header can be trusted, so only the packet is reported
+            if self.verify_checksums(src) && !packet.verify_checksum() {
+                events.push(SnifferEvent::Packet(SnifferPacket {
+                    src,
+                    dst,
+                    protocol: IpProtocol::from(packet.protocol),
+                    src_port: None,
+                    dst_port: None,
+                    bad_checksum: true,
+                }));
+                return Ok(());
+            }
+
+            if packet.is_fragment() {
+                let key = FragmentKey {
+                    src,
+                    dst,
+                    protocol: packet.protocol,
+                    identification: packet.identification as u32,
+                };
+                let offset = packet.fragment_offset as usize * 8;
+                match self.fragments.add(
+                    key,
+                    offset,
+                    packet.more_fragments(),
+                    packet.payload,
+                    timestamp,
+                )? {
+                    Some(payload) => reassembled = payload,
+                    None => return Ok(()),
+                }
+                (
+                    src,
+                    dst,
+                    packet.protocol,
+                    reassembled.as_slice(),
+                    packet.truncated,
+                )
+            } else {
+                (src, dst, packet.protocol, packet.payload, packet.truncated)
+            }
+        } else if frame.ethertype == ETHERTYPE_IPV6 {
+            let packet = ip::parse_ipv6_packet(frame.payload)?;
+            let (src, dst) = (IpAddr::V6(packet.src), IpAddr::V6(packet.dst));
+
+            if let Some((offset, more_fragments, identification)) = packet.fragment() {
+                let key = FragmentKey {
+                    src,
+                    dst,
+                    protocol: packet.protocol,
+                    identification,
+                };
+                match self
+                    .fragments
+                    .add(key, offset, more_fragments, packet.payload, timestamp)?
+                {
+                    Some(payload) => reassembled = payload,
+                    None => return Ok(()),
+                }
+
+                // The reassembled payload starts with the headers that follow the fragment header
+                let (_, protocol, payload) =
+                    ip::parse_ipv6_extension_headers(packet.protocol, &reassembled)?;
+                (src, dst, protocol, payload, packet.truncated)
+            } else {
+                (src, dst, packet.protocol, packet.payload, packet.truncated)
+            }
+        } else {
+            // Ignore non-IP traffic such as ARP
+            return Ok(());
+        };
+
+        // The checksum covers the whole segment, so it cannot be verified when the
+        // capture cut the packet short
+        let verify_checksums = self.verify_checksums(src) && !truncated;
+        let packet = decode_transport(src, dst, protocol, ip_payload, verify_checksums);
+        let protocol = packet.protocol;
+        let bad_checksum = packet.bad_checksum;
+        events.push(SnifferEvent::Packet(packet));
+
+        // The names in a corrupted message could map hosts to the wrong names, so
+        // nothing past the transport layer of a corrupted packet is decoded
+        if bad_checksum {
+            return Ok(());
+        }
+
+        match protocol {
+            IpProtocol::Udp => {
+                let datagram = udp::parse_udp_packet(ip_payload)?;
+                let ports = [datagram.src, datagram.dst];
+                let (src, dst) = (
+                    SocketAddr::new(src, datagram.src),
+                    SocketAddr::new(dst, datagram.dst),
+                );
+
+                // Decode DNS, mDNS and LLMNR messages so that A, AAAA and CNAME records
+                // can be mapped to hosts, and DHCP messages to name the clients on the LAN
+                if let Some(protocol) = DnsProtocol::from_ports(datagram.src, datagram.dst) {
+                    events.push(SnifferEvent::Dns(DnsEvent {
+                        timestamp,
+                        src,
+                        dst,
+                        protocol,
+                        message: dns::DnsMessage::parse(datagram.data)?,
+                    }));
+                } else if ports.contains(&DHCP_SERVER_PORT) || ports.contains(&DHCP_CLIENT_PORT) {
+                    events.push(SnifferEvent::Dhcp(DhcpEvent {
+                        timestamp,
+                        src,
+                        dst,
+                        message: dhcp::parse_dhcpv4_message(datagram.data)?,
+                    }));
+                } else if ports.contains(&DHCPV6_SERVER_PORT) || ports.contains(&DHCPV6_CLIENT_PORT)
+                {
+                    events.push(SnifferEvent::Dhcp(DhcpEvent {
+                        timestamp,
+                        src,
+                        dst,
+                        message: dhcp::parse_dhcpv6_message(datagram.data)?,
+                    }));
+                } else if datagram.dst == QUIC_PORT {
+                    // HTTP/3 names its server in the ClientHello of the QUIC handshake
+                    let hello = self.quic.add(src, dst, datagram.data, timestamp)?;
+                    if let Some(client_hello) = hello {
+                        events.push(SnifferEvent::Tls(TlsEvent {
+                            timestamp,
+                            src,
+                            dst,
+                            client_hello,
+                        }));
+                    }
+                }
+            }
+            IpProtocol::Tcp => {
+                let segment = tcp::parse_tcp_segment(ip_payload)?;
+
+                let key = FlowKey {
+                    src: SocketAddr::new(src, segment.src),
+                    dst: SocketAddr::new(dst, segment.dst),
+                };
+
+                // Streams are only reassembled on the ports decoded below, so that a port
+                // scan does not fill the reassembler. mDNS and LLMNR over TCP are rare
+                // enough to be left out.
+                if truncated {
+                    // The data missing from a segment cut short by the capture leaves a
+                    // gap that the stream cannot get past
+                    self.streams.remove(&key);
+                } else if segment.src == DNS_PORT || segment.dst == DNS_PORT {
+                    self.decode_dns_stream(key, &segment, timestamp, events)?;
+                } else if segment.dst == HTTP_PORT {
+                    self.decode_http_stream(key, &segment, timestamp, events)?;
+                } else if tls::TLS_PORTS.contains(&segment.dst) {
+                    self.decode_tls_stream(key, &segment, timestamp, events)?;
+                }
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Adds a segment to its DNS over TCP stream and decodes the messages it completes.
+    /// Each message is preceded by its length as two bytes (RFC 1035 section 4.2.2).
+    fn decode_dns_stream(
+        &mut self,
+        key: FlowKey,
+        segment: &TcpSegment,
+        timestamp: Duration,
+        events: &mut Vec<SnifferEvent>,
+    ) -> DecodeResult<()> {
+        let Some(data) = self.streams.add(key, segment, timestamp) else {
+            return Ok(());
+        };
+
+        let mut consumed = 0;
+        let mut result = Ok(());
+        while data.bytes.len() >= consumed + 2 {
+            let length =
+                u16::from_be_bytes([data.bytes[consumed], data.bytes[consumed + 1]]) as usize;
+            let end = consumed + 2 + length;
+            if data.bytes.len() < end {
+                break;
+            }
+
+            match dns::DnsMessage::parse(&data.bytes[consumed + 2..end]) {
+                Ok(message) => events.push(SnifferEvent::Dns(DnsEvent {
+                    timestamp,
+                    src: key.src,
+                    dst: key.dst,
+                    protocol: DnsProtocol::Dns,
+                    message,
+                })),
+                Err(error) => {
+                    result = Err(error);
+                    break;
+                }
+            }
+            consumed = end;
+        }
+
+        // A malformed message means the stream is not DNS or has lost its framing, so
+        // it is no longer followed
+        if result.is_err() {
+            self.streams.remove(&key);
+        } else {
+            data.consume(consumed);
+        }
+        result
+    }
+
+    /// Adds a segment to its cleartext HTTP stream and decodes the request heads it
+    /// completes. Only the client's side of the connection is followed.
+    fn decode_http_stream(
+        &mut self,
+        key: FlowKey,
+        segment: &TcpSegment,
+        timestamp: Duration,
+        events: &mut Vec<SnifferEvent>,
+    ) -> DecodeResult<()> {
+        let Some(data) = self.streams.add(key, segment, timestamp) else {
+            return Ok(());
+        };
+
+        while !data.bytes.is_empty() {
+            // Other protocols on port 80 are left alone
+            if !http::could_be_request(&data.bytes) {
+                self.streams.remove(&key);
+                return Ok(());
+            }
+
+            let (request, head_length) =
+                match http::parse_request_head(&data.bytes, &mut data.scanned) {
+                    Ok(Some(request)) => request,
+                    Ok(None) => return Ok(()),
+                    Err(error) => {
+                        self.streams.remove(&key);
+                        return Err(error);
+                    }
+                };
+
+            // The next request starts after the body. Bodies that are chunked or have an
+            // unknown length would have to be parsed to find their end, so the connection
+            // is no longer followed after their request instead.
+            let next_request = head_length
+                .checked_add(request.content_length.unwrap_or(0))
+                .filter(|_| !request.chunked && !request.bad_content_length);
+
+            // The request is reported once its body has arrived, which the stream buffers
+            // up to its limit
+            if next_request.is_some_and(|next_request| data.bytes.len() < next_request) {
+                return Ok(());
+            }
+
+            events.push(SnifferEvent::Http(HttpEvent {
+                timestamp,
+                src: key.src,
+                dst: key.dst,
+                request,
+            }));
+
+            match next_request {
+                Some(next_request) => data.consume(next_request),
+                None => {
+                    self.streams.remove(&key);
+                    return Ok(());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Adds a segment to its TCP stream and decodes the ClientHello that starts the
+    /// stream if it carries TLS. The stream is dropped once the hello is decoded, or
+    /// as soon as it turns out to carry something else, so that only the first few
+    /// segments of each connection are buffered.
+    fn decode_tls_stream(
+        &mut self,
+        key: FlowKey,
+        segment: &TcpSegment,
+        timestamp: Duration,
+        events: &mut Vec<SnifferEvent>,
+    ) -> DecodeResult<()> {
+        let Some(data) = self.streams.add(key, segment, timestamp) else {
+            return Ok(());
+        };
+        let data = &data.bytes;
+
+        // Wait for enough data to tell whether the stream starts with a handshake record
+        if data.len() < 2 {
+            return Ok(());
+        }
+        if !tls::is_handshake_record(data) {
+            self.streams.remove(&key);
+            return Ok(());
+        }
+
+        let message = match tls::read_handshake_message(data) {
+            Ok(Some(message)) => message,
+            Ok(None) => return Ok(()),
+            Err(error) => {
+                self.streams.remove(&key);
+                return Err(error);
+            }
+        };
+        self.streams.remove(&key);
+
+        // The server's side of the connection starts with a ServerHello instead
+        if message[0] == tls::HANDSHAKE_CLIENT_HELLO {
+            events.push(SnifferEvent::Tls(TlsEvent {
+                timestamp,
+                src: key.src,
+                dst: key.dst,
+                client_hello: tls::parse_client_hello(&message)?,
+            }));
+        }
+
+        Ok(())
+    }
+
+    fn verify_checksums(&self, src: IpAddr) -> bool {
+        !self.unverified_sources.contains(&src)
+    }
+}
+
+/// Builds a packet from the network layer addresses, reading the ports of TCP and UDP
+/// from the start of `payload`. If `verify_checksums` is set, the TCP and UDP
+/// checksums are checked against the pseudo header.
+///
+/// Packets truncated by the snapshot length of the capture are still reported with
+/// their ports, as the ports come first in both headers. Decoding their payload is
+/// left to the application layer, which fails on the truncation instead.
+fn decode_transport(
+    src: IpAddr,
+    dst: IpAddr,
+    protocol: u8,
+    payload: &[u8],
+    verify_checksums: bool,
+) -> SnifferPacket {
+    let (src_port, dst_port) = match IpProtocol::from(protocol) {
+        IpProtocol::Tcp | IpProtocol::Udp if payload.len() >= 4 => (
+            Some(u16::from_be_bytes([payload[0], payload[1]])),
+            Some(u16::from_be_bytes([payload[2], payload[3]])),
+        ),
+        _ => (None, None),
+    };
+
+    let checksum_ok = !verify_checksums
+        || match IpProtocol::from(protocol) {
+            IpProtocol::Tcp => checksum::verify_transport(src, dst, protocol, payload),
+            IpProtocol::Udp => match udp::parse_udp_packet(payload) {
+                // A zero checksum means that the sender did not compute one, which IPv4 allows
+                Ok(datagram) => {
+                    (datagram.checksum == 0 && src.is_ipv4())
+                        || checksum::verify_transport(
+                            src,
+                            dst,
+                            protocol,
+                            &payload[..datagram.length as usize],
+                        )
+                }
+                // A datagram that does not fit in the IP payload cannot be verified
+                Err(_) => true,
+            },
+            _ => true,
+        };
+
+    SnifferPacket {
+        src,
+        dst,
+        protocol: IpProtocol::from(protocol),
+        src_port,
+        dst_port,
+        bad_checksum: !checksum_ok,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::error::DecodeError;
+    use crate::network::tcp::TcpFlags;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    const CLIENT: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 10);
+    const SERVER: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 1);
+
+    /// Builds an IPv4 packet from `src` to `dst` around `payload`, with a correct
+    /// header checksum.
+    fn ipv4_packet(src: Ipv4Addr, dst: Ipv4Addr, protocol: u8, payload: &[u8]) -> Vec<u8> {
+        let total_length = (20 + payload.len()) as u16;
+        let mut packet = vec![0x45, 0];
+        packet.extend_from_slice(&total_length.to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 0, 0, 64, protocol, 0, 0]);
+        packet.extend_from_slice(&src.octets());
+        packet.extend_from_slice(&dst.octets());
+
+        let sum = packet
+            .chunks(2)
+            .map(|word| u16::from_be_bytes([word[0], word[1]]) as u32)
+            .sum::<u32>();
+        let checksum = !(((sum & 0xFFFF) + (sum >> 16)) as u16);
+        packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    /// Builds a UDP datagram without a checksum, which IPv4 allows.
+    fn udp_datagram(src: u16, dst: u16, data: &[u8]) -> Vec<u8> {
+        let mut datagram = Vec::new();
+        datagram.extend_from_slice(&src.to_be_bytes());
+        datagram.extend_from_slice(&dst.to_be_bytes());
+        datagram.extend_from_slice(&(8 + data.len() as u16).to_be_bytes());
+        datagram.extend_from_slice(&[0, 0]);
+        datagram.extend_from_slice(data);
+        datagram
+    }
+
+    /// Builds an IPv6 packet from `src` to `dst` around `payload`, which starts with
+    /// the extension headers if `next_header` is one.
+    fn ipv6_packet(src: Ipv6Addr, dst: Ipv6Addr, next_header: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x60, 0, 0, 0];
+        packet.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&[next_header, 64]);
+        packet.extend_from_slice(&src.octets());
+        packet.extend_from_slice(&dst.octets());
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    /// Decodes a frame captured on a raw IP link, which carries IPv4 or IPv6.
+    fn decode(decoder: &mut Decoder, frame: &[u8]) -> Vec<SnifferEvent> {
+        decoder.decode_frame(Linktype::RAW, Duration::from_secs(1_700_000_000), frame)
+    }
+
+    #[test]
+    fn reports_udp_packet_with_its_ports() {
+        let frame = ipv4_packet(CLIENT, SERVER, 17, &udp_datagram(50000, 9999, b"hello"));
+        let events = decode(&mut Decoder::new(), &frame);
+
+        assert_eq!(events.len(), 1);
+        let SnifferEvent::Packet(packet) = &events[0] else {
+            panic!("expected a packet event");
+        };
+        assert_eq!(packet.src, IpAddr::V4(CLIENT));
+        assert_eq!(packet.protocol, IpProtocol::Udp);
+        assert_eq!(packet.src_port, Some(50000));
+        assert_eq!(packet.dst_port, Some(9999));
+        assert!(!packet.bad_checksum);
+    }
+
+    #[test]
+    fn reports_packet_truncated_by_snapshot_length() {
+        let frame = ipv4_packet(SERVER, CLIENT, 17, &udp_datagram(53, 50000, &[0; 64]));
+
+        // Only the IP header and the first bytes of the UDP header were captured
+        let events = decode(&mut Decoder::new(), &frame[..26]);
+
+        assert_eq!(events.len(), 2);
+        let SnifferEvent::Packet(packet) = &events[0] else {
+            panic!("expected a packet event");
+        };
+        assert_eq!(packet.src_port, Some(53));
+        assert_eq!(packet.dst_port, Some(50000));
+        assert!(!packet.bad_checksum);
+        assert!(matches!(
+            events[1],
+            SnifferEvent::ParseError(DecodeError::Truncated { layer: "udp", .. })
+        ));
+    }
+
+    #[test]
+    fn skips_checksum_of_truncated_tcp_segment() {
+        let mut segment = vec![0; 20 + 100];
+        segment[..4].copy_from_slice(&[0xc3, 0x50, 0x01, 0xbb]);
+        segment[12] = 5 << 4;
+        segment[16..18].copy_from_slice(&[0x12, 0x34]);
+
+        let frame = ipv4_packet(CLIENT, SERVER, 6, &segment);
+        let mut decoder = Decoder::new();
+
+        // The checksum of the whole segment is wrong
+        let events = decode(&mut decoder, &frame);
+        let SnifferEvent::Packet(packet) = &events[0] else {
+            panic!("expected a packet event");
+        };
+        assert!(packet.bad_checksum);
+
+        // But it cannot be checked once the capture cut the segment short
+        let events = decode(&mut decoder, &frame[..20 + 40]);
+        let SnifferEvent::Packet(packet) = &events[0] else {
+            panic!("expected a packet event");
+        };
+        assert_eq!(packet.src_port, Some(50000));
+        assert_eq!(packet.dst_port, Some(443));
+        assert!(!packet.bad_checksum);
+    }
+
+    /// Builds a TCP segment with a zero checksum, for decoders that skip verification.
+    fn tcp_segment(src: u16, dst: u16, seq: u32, flags: u8, data: &[u8]) -> Vec<u8> {
+        let mut segment = Vec::new();
+        segment.extend_from_slice(&src.to_be_bytes());
+        segment.extend_from_slice(&dst.to_be_bytes());
+        segment.extend_from_slice(&seq.to_be_bytes());
+        segment.extend_from_slice(&[0, 0, 0, 0, 5 << 4, flags, 0xff, 0xff, 0, 0, 0, 0]);
+        segment.extend_from_slice(data);
+        segment
+    }
+
+    /// Frames DNS messages for TCP, each preceded by its length.
+    fn dns_over_tcp(messages: &[&[u8]]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for message in messages {
+            data.extend_from_slice(&(message.len() as u16).to_be_bytes());
+            data.extend_from_slice(message);
+        }
+        data
+    }
+
+    /// Opens a TCP stream from the client to `port`, returning the sequence number of
+    /// its first data byte.
+    fn open_stream(decoder: &mut Decoder, port: u16) -> u32 {
+        decoder.skip_checksums_from([IpAddr::V4(CLIENT)]);
+        let syn = tcp_segment(50000, port, 1000, TcpFlags::SYN, &[]);
+        decode(decoder, &ipv4_packet(CLIENT, SERVER, 6, &syn));
+        1001
+    }
+
+    fn dns_names(events: &[SnifferEvent]) -> Vec<String> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                SnifferEvent::Dns(event) => Some(event.message.questions[0].qname.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn decodes_pipelined_dns_messages_from_one_segment() {
+        let mut decoder = Decoder::new();
+        let seq = open_stream(&mut decoder, 53);
+
+        let first = dns::DnsMessage::query(1, "example.com", dns::TYPE_A)
+            .encode()
+            .unwrap();
+        let second = dns::DnsMessage::query(2, "example.org", dns::TYPE_A)
+            .encode()
+            .unwrap();
+        let data = dns_over_tcp(&[&first, &second]);
+
+        // The second message is split across two segments
+        let split = data.len() - 5;
+        let segment = tcp_segment(50000, 53, seq, TcpFlags::ACK, &data[..split]);
+        let events = decode(&mut decoder, &ipv4_packet(CLIENT, SERVER, 6, &segment));
+        assert_eq!(dns_names(&events), ["example.com"]);
+
+        let segment = tcp_segment(50000, 53, seq + split as u32, TcpFlags::ACK, &data[split..]);
+        let events = decode(&mut decoder, &ipv4_packet(CLIENT, SERVER, 6, &segment));
+        assert_eq!(dns_names(&events), ["example.org"]);
+    }
+
+    #[test]
+    fn drops_dns_stream_after_malformed_message() {
+        let mut decoder = Decoder::new();
+        let seq = open_stream(&mut decoder, 53);
+
+        let first = dns::DnsMessage::query(1, "example.com", dns::TYPE_A)
+            .encode()
+            .unwrap();
+        let data = dns_over_tcp(&[&first, &[0; 4], &first]);
+        let segment = tcp_segment(50000, 53, seq, TcpFlags::ACK, &data);
+        let events = decode(&mut decoder, &ipv4_packet(CLIENT, SERVER, 6, &segment));
+
+        assert_eq!(dns_names(&events), ["example.com"]);
+        assert!(matches!(events.last(), Some(SnifferEvent::ParseError(_))));
+
+        // The rest of the stream is no longer followed
+        let seq = seq + data.len() as u32;
+        let data = dns_over_tcp(&[&first]);
+        let segment = tcp_segment(50000, 53, seq, TcpFlags::ACK, &data);
+        let events = decode(&mut decoder, &ipv4_packet(CLIENT, SERVER, 6, &segment));
+        assert!(dns_names(&events).is_empty());
+    }
+
+    fn http_targets(events: &[SnifferEvent]) -> Vec<String> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                SnifferEvent::Http(event) => Some(event.request.target.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Sends `data` on a new HTTP stream, then a well-formed request after it, and
+    /// returns the targets decoded from each segment.
+    fn http_stream_targets(data: &[u8]) -> (Vec<String>, Vec<String>) {
+        let mut decoder = Decoder::new();
+        let seq = open_stream(&mut decoder, http::HTTP_PORT);
+
+        let segment = tcp_segment(50000, http::HTTP_PORT, seq, TcpFlags::ACK, data);
+        let events = decode(&mut decoder, &ipv4_packet(CLIENT, SERVER, 6, &segment));
+        assert!(!events
+            .iter()
+            .any(|event| matches!(event, SnifferEvent::ParseError(_))));
+        let first = http_targets(&events);
+
+        let seq = seq + data.len() as u32;
+        let data = b"GET /next HTTP/1.1\r\n\r\n";
+        let segment = tcp_segment(50000, http::HTTP_PORT, seq, TcpFlags::ACK, data);
+        let events = decode(&mut decoder, &ipv4_packet(CLIENT, SERVER, 6, &segment));
+        (first, http_targets(&events))
+    }
+
+    /// Sends each of `segments` in turn on one HTTP stream, returning the targets of
+    /// the requests decoded from each.
+    fn http_segment_targets(segments: &[&[u8]]) -> Vec<Vec<String>> {
+        let mut decoder = Decoder::new();
+        let mut seq = open_stream(&mut decoder, http::HTTP_PORT);
+
+        let mut targets = Vec::new();
+        for data in segments {
+            let segment = tcp_segment(50000, http::HTTP_PORT, seq, TcpFlags::ACK, data);
+            targets.push(http_targets(&decode(
+                &mut decoder,
+                &ipv4_packet(CLIENT, SERVER, 6, &segment),
+            )));
+            seq += data.len() as u32;
+        }
+        targets
+    }
+
+    #[test]
+    fn buffers_http_body_split_across_segments() {
+        let segments: [&[u8]; 2] = [
+            b"POST /a HTTP/1.1\r\nContent-Length: 4\r\n\r\nab",
+            b"cdGET /b HTTP/1.1\r\n\r\n",
+        ];
+        assert_eq!(
+            http_segment_targets(&segments),
+            vec![Vec::new(), vec!["/a".to_string(), "/b".to_string()]]
+        );
+    }
+
+    #[test]
+    fn finds_end_of_http_head_split_across_segments() {
+        let segments: [&[u8]; 3] = [b"GET /a HTTP/1.1\r\nHost: a\r", b"\n\r", b"\nGET /b"];
+        assert_eq!(
+            http_segment_targets(&segments),
+            vec![Vec::new(), Vec::new(), vec!["/a".to_string()]]
+        );
+    }
+
+    #[test]
+    fn follows_pipelined_http_requests() {
+        let data = b"POST /a HTTP/1.1\r\nContent-Length: 2\r\n\r\nokGET /b HTTP/1.1\r\n\r\n";
+        assert_eq!(
+            http_stream_targets(data),
+            (vec!["/a".into(), "/b".into()], vec!["/next".into()])
+        );
+    }
+
+    #[test]
+    fn stops_following_http_stream_after_bad_content_length() {
+        let data = b"POST /a HTTP/1.1\r\nContent-Length: abc\r\n\r\nGET /b HTTP/1.1\r\n\r\n";
+        assert_eq!(http_stream_targets(data), (vec!["/a".into()], Vec::new()));
+    }
+
+    #[test]
+    fn stops_following_http_stream_after_overflowing_content_length() {
+        let data = format!(
+            "POST /a HTTP/1.1\r\nContent-Length: {}\r\n\r\nGET /b HTTP/1.1\r\n\r\n",
+            usize::MAX
+        );
+        assert_eq!(
+            http_stream_targets(data.as_bytes()),
+            (vec!["/a".into()], Vec::new())
+        );
+    }
+
+    #[test]
+    fn decodes_client_hello_split_across_records_and_segments() {
+        let mut decoder = Decoder::new();
+        let seq = open_stream(&mut decoder, 443);
+
+        let hello = tls::tests::client_hello();
+        let records = tls::tests::handshake_records(&hello, 100);
+        let mut events = Vec::new();
+        for (index, chunk) in records.chunks(70).enumerate() {
+            let seq = seq + (index * 70) as u32;
+            let segment = tcp_segment(50000, 443, seq, TcpFlags::ACK, chunk);
+            events.extend(decode(
+                &mut decoder,
+                &ipv4_packet(CLIENT, SERVER, 6, &segment),
+            ));
+        }
+
+        let hellos = events
+            .iter()
+            .filter_map(|event| match event {
+                SnifferEvent::Tls(event) => Some(&event.client_hello),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(hellos.len(), 1);
+        assert_eq!(hellos[0].server_name.as_deref(), Some("example.com"));
+        assert_eq!(hellos[0].alpn, ["alpn"]);
+    }
+
+    #[test]
+    fn drops_stream_of_segment_truncated_by_capture() {
+        let mut decoder = Decoder::new();
+        let seq = open_stream(&mut decoder, 53);
+
+        let query = dns::DnsMessage::query(1, "example.com", dns::TYPE_A)
+            .encode()
+            .unwrap();
+        let data = dns_over_tcp(&[&query, &query]);
+        let segment = tcp_segment(50000, 53, seq, TcpFlags::ACK, &data);
+        let frame = ipv4_packet(CLIENT, SERVER, 6, &segment);
+        let events = decode(&mut decoder, &frame[..frame.len() - 5]);
+        assert!(dns_names(&events).is_empty());
+
+        // The retransmission is not enough to pick the stream up again
+        let events = decode(&mut decoder, &frame);
+        assert!(dns_names(&events).is_empty());
+    }
+
+    #[test]
+    fn reassembles_ipv6_fragments() {
+        let (client, server) = (
+            "2001:db8::10".parse().unwrap(),
+            "2001:db8::1".parse().unwrap(),
+        );
+        let mut decoder = Decoder::new();
+        decoder.skip_checksums_from([IpAddr::V6(client)]);
+
+        let datagram = udp_datagram(50000, 9999, &[7; 32]);
+        let fragment = |offset: usize, more_fragments: bool| {
+            // The offset in 8 byte units, then the More Fragments flag in the lowest bit
+            let offset_and_flags = ((offset / 8) << 3) as u16 | more_fragments as u16;
+            let mut payload = vec![17, 0];
+            payload.extend_from_slice(&offset_and_flags.to_be_bytes());
+            payload.extend_from_slice(&0x8000_0001u32.to_be_bytes());
+            let end = if more_fragments {
+                offset + 24
+            } else {
+                datagram.len()
+            };
+            payload.extend_from_slice(&datagram[offset..end]);
+            ipv6_packet(client, server, ip::IPV6_FRAGMENT, &payload)
+        };
+
+        // The last fragment arrives first
+        assert!(decode(&mut decoder, &fragment(24, false)).is_empty());
+        let events = decode(&mut decoder, &fragment(0, true));
+
+        let [SnifferEvent::Packet(packet)] = events.as_slice() else {
+            panic!("expected one packet, got {} events", events.len());
+        };
+        assert_eq!(packet.src, IpAddr::V6(client));
+        assert_eq!(packet.protocol, IpProtocol::Udp);
+        assert_eq!(packet.src_port, Some(50000));
+        assert_eq!(packet.dst_port, Some(9999));
+    }
+}
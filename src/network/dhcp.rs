@@ -0,0 +1,672 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+
+use crate::network::error::{DecodeError, DecodeResult};
+
+pub const DHCP_SERVER_PORT: u16 = 67;
+pub const DHCP_CLIENT_PORT: u16 = 68;
+pub const DHCPV6_CLIENT_PORT: u16 = 546;
+pub const DHCPV6_SERVER_PORT: u16 = 547;
+
+// https://datatracker.ietf.org/doc/html/rfc2131#section-3
+const DHCP_MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+
+// DHCPv4 options: https://datatracker.ietf.org/doc/html/rfc2132
+const OPTION_PAD: u8 = 0;
+const OPTION_HOSTNAME: u8 = 12;
+const OPTION_REQUESTED_IP: u8 = 50;
+const OPTION_LEASE_TIME: u8 = 51;
+const OPTION_OVERLOAD: u8 = 52;
+const OPTION_MESSAGE_TYPE: u8 = 53;
+const OPTION_SERVER_ID: u8 = 54;
+const OPTION_VENDOR_CLASS: u8 = 60;
+const OPTION_CLIENT_FQDN: u8 = 81; // RFC 4702
+const OPTION_END: u8 = 255;
+
+// DHCPv6 options: https://datatracker.ietf.org/doc/html/rfc8415#section-21
+const OPTION6_CLIENT_ID: u16 = 1;
+const OPTION6_IA_NA: u16 = 3;
+const OPTION6_IA_TA: u16 = 4;
+const OPTION6_IA_ADDR: u16 = 5;
+const OPTION6_RELAY_MSG: u16 = 9;
+const OPTION6_VENDOR_CLASS: u16 = 16;
+const OPTION6_CLIENT_FQDN: u16 = 39; // RFC 4704
+
+// Relay agents drop messages that have already been relayed this many times
+// https://datatracker.ietf.org/doc/html/rfc8415#section-7.6
+const HOP_COUNT_LIMIT: usize = 32;
+
+// https://www.iana.org/assignments/bootp-dhcp-parameters/bootp-dhcp-parameters.xhtml#message-type-53
+// https://www.iana.org/assignments/dhcpv6-parameters/dhcpv6-parameters.xhtml#dhcpv6-parameters-1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DhcpMessageType {
+    Discover,
+    Offer,
+    Request,
+    Decline,
+    Ack,
+    Nak,
+    Release,
+    Inform,
+    Solicit,
+    Advertise,
+    Confirm,
+    Renew,
+    Rebind,
+    Reply,
+    InformationRequest,
+    Reconfigure,
+    RelayForward,
+    RelayReply,
+    Unknown(u8),
+}
+
+impl DhcpMessageType {
+    fn from_v4(message_type: u8) -> Self {
+        match message_type {
+            1 => DhcpMessageType::Discover,
+            2 => DhcpMessageType::Offer,
+            3 => DhcpMessageType::Request,
+            4 => DhcpMessageType::Decline,
+            5 => DhcpMessageType::Ack,
+            6 => DhcpMessageType::Nak,
+            7 => DhcpMessageType::Release,
+            8 => DhcpMessageType::Inform,
+            other => DhcpMessageType::Unknown(other),
+        }
+    }
+
+    fn from_v6(message_type: u8) -> Self {
+        match message_type {
+            1 => DhcpMessageType::Solicit,
+            2 => DhcpMessageType::Advertise,
+            3 => DhcpMessageType::Request,
+            4 => DhcpMessageType::Confirm,
+            5 => DhcpMessageType::Renew,
+            6 => DhcpMessageType::Rebind,
+            7 => DhcpMessageType::Reply,
+            8 => DhcpMessageType::Release,
+            9 => DhcpMessageType::Decline,
+            10 => DhcpMessageType::Reconfigure,
+            11 => DhcpMessageType::InformationRequest,
+            12 => DhcpMessageType::RelayForward,
+            13 => DhcpMessageType::RelayReply,
+            other => DhcpMessageType::Unknown(other),
+        }
+    }
+
+    /// Whether the message is sent by a client rather than a server.
+    pub fn is_from_client(&self) -> bool {
+        matches!(
+            self,
+            DhcpMessageType::Discover
+                | DhcpMessageType::Request
+                | DhcpMessageType::Decline
+                | DhcpMessageType::Release
+                | DhcpMessageType::Inform
+                | DhcpMessageType::Solicit
+                | DhcpMessageType::Confirm
+                | DhcpMessageType::Renew
+                | DhcpMessageType::Rebind
+                | DhcpMessageType::InformationRequest
+        )
+    }
+}
+
+impl fmt::Display for DhcpMessageType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DhcpMessageType::Discover => write!(f, "DISCOVER"),
+            DhcpMessageType::Offer => write!(f, "OFFER"),
+            DhcpMessageType::Request => write!(f, "REQUEST"),
+            DhcpMessageType::Decline => write!(f, "DECLINE"),
+            DhcpMessageType::Ack => write!(f, "ACK"),
+            DhcpMessageType::Nak => write!(f, "NAK"),
+            DhcpMessageType::Release => write!(f, "RELEASE"),
+            DhcpMessageType::Inform => write!(f, "INFORM"),
+            DhcpMessageType::Solicit => write!(f, "SOLICIT"),
+            DhcpMessageType::Advertise => write!(f, "ADVERTISE"),
+            DhcpMessageType::Confirm => write!(f, "CONFIRM"),
+            DhcpMessageType::Renew => write!(f, "RENEW"),
+            DhcpMessageType::Rebind => write!(f, "REBIND"),
+            DhcpMessageType::Reply => write!(f, "REPLY"),
+            DhcpMessageType::InformationRequest => write!(f, "INFORMATION-REQUEST"),
+            DhcpMessageType::Reconfigure => write!(f, "RECONFIGURE"),
+            DhcpMessageType::RelayForward => write!(f, "RELAY-FORW"),
+            DhcpMessageType::RelayReply => write!(f, "RELAY-REPL"),
+            DhcpMessageType::Unknown(message_type) => write!(f, "TYPE{}", message_type),
+        }
+    }
+}
+
+/// The fields of a DHCPv4 or DHCPv6 message that name a client and describe its lease.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DhcpMessage {
+    pub message_type: DhcpMessageType,
+
+    /* Chosen by the client and copied into the server's answers */
+    pub transaction_id: u32,
+
+    /* The client hardware address for DHCPv4, or the client DUID for DHCPv6 */
+    pub client_id: Vec<u8>,
+
+    pub hostname: Option<String>,     // option 12
+    pub fqdn: Option<String>,         // option 81, or 39 for DHCPv6
+    pub vendor_class: Option<String>, // option 60, or 16 for DHCPv6
+
+    /* The address a client asks for: option 50 or the address being renewed
+    for DHCPv4, or the first IA address of a client message for DHCPv6 */
+    pub requested_ip: Option<IpAddr>,
+
+    /* The addresses a server offers or assigns: yiaddr for DHCPv4, or the IA
+    addresses of a server message for DHCPv6 */
+    pub assigned_ips: Vec<IpAddr>,
+
+    /* Option 51, or the valid lifetime of the first IA address for DHCPv6 */
+    pub lease_time: Option<Duration>,
+
+    /* Option 54, the address of the server that sent or is asked for the lease */
+    pub server_id: Option<IpAddr>,
+}
+
+impl DhcpMessage {
+    fn new(message_type: DhcpMessageType, transaction_id: u32) -> Self {
+        DhcpMessage {
+            message_type,
+            transaction_id,
+            client_id: Vec::new(),
+            hostname: None,
+            fqdn: None,
+            vendor_class: None,
+            requested_ip: None,
+            assigned_ips: Vec::new(),
+            lease_time: None,
+            server_id: None,
+        }
+    }
+
+    /// Returns the name the client gave for itself, preferring the fully qualified one.
+    pub fn client_name(&self) -> Option<&String> {
+        self.fqdn.as_ref().or(self.hostname.as_ref())
+    }
+}
+
+/// Parses a DHCPv4 message from the payload of a UDP datagram.
+// https://datatracker.ietf.org/doc/html/rfc2131#section-2
+pub fn parse_dhcpv4_message(data: &[u8]) -> DecodeResult<DhcpMessage> {
+    DecodeError::check_len("dhcp", data, 240)?;
+
+    let hardware_length = (data[2] as usize).min(16);
+    let transaction_id = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+    let ciaddr = Ipv4Addr::new(data[12], data[13], data[14], data[15]);
+    let yiaddr = Ipv4Addr::new(data[16], data[17], data[18], data[19]);
+
+    if data[236..240] != DHCP_MAGIC_COOKIE {
+        return Err(DecodeError::BadMagic { layer: "dhcp" });
+    }
+
+    // BOOTP messages without option 53 are treated as their DHCP counterparts
+    let message_type = if data[0] == 1 {
+        DhcpMessageType::Request
+    } else {
+        DhcpMessageType::Ack
+    };
+    let mut message = DhcpMessage::new(message_type, transaction_id);
+    message.client_id = data[28..28 + hardware_length].to_vec();
+
+    // The sname and file fields may carry further options (RFC 2132 section 9.3)
+    let overload = parse_dhcpv4_options(&data[240..], &mut message)?;
+    if overload & 1 != 0 {
+        parse_dhcpv4_options(&data[108..236], &mut message)?;
+    }
+    if overload & 2 != 0 {
+        parse_dhcpv4_options(&data[44..108], &mut message)?;
+    }
+
+    if !ciaddr.is_unspecified() && message.message_type.is_from_client() {
+        message.requested_ip.get_or_insert(IpAddr::V4(ciaddr));
+    }
+    if !yiaddr.is_unspecified() {
+        message.assigned_ips.push(IpAddr::V4(yiaddr));
+    }
+
+    Ok(message)
+}
+
+/// Fills in the message from a DHCPv4 options field, returning the value of the
+/// option overload option, if any.
+fn parse_dhcpv4_options(options: &[u8], message: &mut DhcpMessage) -> DecodeResult<u8> {
+    let mut overload = 0;
+    let mut index = 0;
+
+    while index < options.len() {
+        let code = options[index];
+        match code {
+            OPTION_PAD => {
+                index += 1;
+                continue;
+            }
+            OPTION_END => break,
+            _ => (),
+        }
+
+        DecodeError::check_len("dhcp option", options, index + 2)?;
+        let length = options[index + 1] as usize;
+        let start = index + 2;
+        DecodeError::check_len("dhcp option", options, start + length)?;
+        let value = &options[start..start + length];
+        index = start + length;
+
+        match code {
+            OPTION_HOSTNAME => message.hostname = parse_text(value),
+            OPTION_VENDOR_CLASS => message.vendor_class = parse_text(value),
+            OPTION_MESSAGE_TYPE if length == 1 => {
+                message.message_type = DhcpMessageType::from_v4(value[0])
+            }
+            OPTION_REQUESTED_IP => message.requested_ip = parse_ipv4(value),
+            OPTION_SERVER_ID => message.server_id = parse_ipv4(value),
+            OPTION_LEASE_TIME if length == 4 => {
+                let seconds = u32::from_be_bytes([value[0], value[1], value[2], value[3]]);
+                message.lease_time = Some(Duration::from_secs(seconds as u64));
+            }
+            OPTION_OVERLOAD if length == 1 => overload = value[0],
+            // Flags, two deprecated rcodes, then the name. The E flag (0x04) says
+            // whether the name is in DNS wire format or plain ASCII.
+            OPTION_CLIENT_FQDN if length >= 3 => {
+                message.fqdn = if value[0] & 0x04 != 0 {
+                    parse_wire_name(&value[3..])
+                } else {
+                    parse_text(&value[3..])
+                };
+            }
+            _ => (),
+        }
+    }
+
+    Ok(overload)
+}
+
+/// Parses a DHCPv6 message from the payload of a UDP datagram. Messages forwarded
+/// by a relay agent are unwrapped to the client or server message they carry.
+// https://datatracker.ietf.org/doc/html/rfc8415#section-8
+pub fn parse_dhcpv6_message(data: &[u8]) -> DecodeResult<DhcpMessage> {
+    parse_relayed_dhcpv6_message(data, 0)
+}
+
+/// Parses a DHCPv6 message that `relays` relay agents have wrapped so far.
+fn parse_relayed_dhcpv6_message(data: &[u8], relays: usize) -> DecodeResult<DhcpMessage> {
+    DecodeError::check_len("dhcpv6", data, 4)?;
+
+    let message_type = DhcpMessageType::from_v6(data[0]);
+    if matches!(
+        message_type,
+        DhcpMessageType::RelayForward | DhcpMessageType::RelayReply
+    ) {
+        if relays == HOP_COUNT_LIMIT {
+            return Err(DecodeError::BadLength {
+                layer: "dhcpv6 relay chain",
+                length: relays + 1,
+            });
+        }
+
+        // Hop count, link address and peer address precede the options
+        DecodeError::check_len("dhcpv6 relay", data, 34)?;
+        for (code, value) in parse_dhcpv6_options(&data[34..])? {
+            if code == OPTION6_RELAY_MSG {
+                return parse_relayed_dhcpv6_message(value, relays + 1);
+            }
+        }
+        // Relay messages have no transaction id of their own
+        return Ok(DhcpMessage::new(message_type, 0));
+    }
+
+    let transaction_id = u32::from_be_bytes([0, data[1], data[2], data[3]]);
+    let mut message = DhcpMessage::new(message_type, transaction_id);
+
+    let mut addresses = Vec::new();
+    for (code, value) in parse_dhcpv6_options(&data[4..])? {
+        match code {
+            OPTION6_CLIENT_ID => message.client_id = value.to_vec(),
+            // IAID, T1 and T2 precede the options of a non-temporary address association
+            OPTION6_IA_NA if value.len() >= 12 => {
+                parse_dhcpv6_addresses(&value[12..], &mut addresses)?
+            }
+            // A temporary address association only has an IAID
+            OPTION6_IA_TA if value.len() >= 4 => {
+                parse_dhcpv6_addresses(&value[4..], &mut addresses)?
+            }
+            // Enterprise number, then a list of length-prefixed class data
+            OPTION6_VENDOR_CLASS if value.len() >= 6 => {
+                let length = u16::from_be_bytes([value[4], value[5]]) as usize;
+                message.vendor_class = value.get(6..6 + length).and_then(parse_text);
+            }
+            // Flags, then the name in DNS wire format
+            OPTION6_CLIENT_FQDN if !value.is_empty() => message.fqdn = parse_wire_name(&value[1..]),
+            _ => (),
+        }
+    }
+
+    if message.message_type.is_from_client() {
+        message.requested_ip = addresses.first().map(|(address, _)| *address);
+    } else {
+        message.lease_time = addresses.first().map(|(_, lifetime)| *lifetime);
+        message.assigned_ips = addresses
+            .into_iter()
+            // A zero valid lifetime withdraws the address from the client
+            .filter(|(_, lifetime)| !lifetime.is_zero())
+            .map(|(address, _)| address)
+            .collect();
+    }
+
+    Ok(message)
+}
+
+/// Splits a DHCPv6 options field into the code and value of each option.
+fn parse_dhcpv6_options(options: &[u8]) -> DecodeResult<Vec<(u16, &[u8])>> {
+    let mut parsed = Vec::new();
+    let mut index = 0;
+
+    while index < options.len() {
+        DecodeError::check_len("dhcpv6 option", options, index + 4)?;
+        let code = u16::from_be_bytes([options[index], options[index + 1]]);
+        let length = u16::from_be_bytes([options[index + 2], options[index + 3]]) as usize;
+        let start = index + 4;
+        DecodeError::check_len("dhcpv6 option", options, start + length)?;
+        parsed.push((code, &options[start..start + length]));
+        index = start + length;
+    }
+
+    Ok(parsed)
+}
+
+/// Collects the addresses and valid lifetimes of the IA address options of an
+/// address association.
+fn parse_dhcpv6_addresses(
+    options: &[u8],
+    addresses: &mut Vec<(IpAddr, Duration)>,
+) -> DecodeResult<()> {
+    for (code, value) in parse_dhcpv6_options(options)? {
+        // Address, preferred lifetime, valid lifetime, then options
+        if code == OPTION6_IA_ADDR && value.len() >= 24 {
+            let octets: [u8; 16] = value[..16].try_into().unwrap();
+            let valid_lifetime = u32::from_be_bytes([value[20], value[21], value[22], value[23]]);
+            addresses.push((
+                IpAddr::V6(Ipv6Addr::from(octets)),
+                Duration::from_secs(valid_lifetime as u64),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn parse_ipv4(value: &[u8]) -> Option<IpAddr> {
+    let octets: [u8; 4] = value.try_into().ok()?;
+    Some(IpAddr::V4(Ipv4Addr::from(octets)))
+}
+
+/// Decodes a text option, dropping the trailing NULs some clients include.
+fn parse_text(value: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(value);
+    let text = text.trim_end_matches('\0');
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// Decodes an uncompressed name in DNS wire format. Clients may send a partial
+/// name without the terminating root label, so the data may end after any label.
+fn parse_wire_name(data: &[u8]) -> Option<String> {
+    let mut labels = Vec::new();
+    let mut index = 0;
+
+    while index < data.len() {
+        let length = data[index] as usize;
+        if length == 0 {
+            break;
+        }
+        let label = data.get(index + 1..index + 1 + length)?;
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        index += 1 + length;
+    }
+
+    (!labels.is_empty()).then(|| labels.join("."))
+}
+
+/// Guesses the kind of device from the vendor class identifier its DHCP client
+/// sends, e.g. `MSFT 5.0` for Windows.
+pub fn device_type(vendor_class: &str) -> Option<&'static str> {
+    let device = if vendor_class.starts_with("MSFT") {
+        "Windows"
+    } else if vendor_class.starts_with("android-dhcp") {
+        "Android"
+    } else if vendor_class.starts_with("dhcpcd") && vendor_class.contains("Linux") {
+        "Linux"
+    } else if vendor_class.starts_with("udhcp") {
+        "Embedded Linux"
+    } else if vendor_class.starts_with("Cisco") {
+        "Cisco"
+    } else if vendor_class.starts_with("PXEClient") {
+        "Network boot"
+    } else {
+        return None;
+    };
+    Some(device)
+}
+
+/// Formats a client identifier as colon separated hex, e.g. a MAC address.
+pub fn format_client_id(client_id: &[u8]) -> String {
+    client_id
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT_MAC: [u8; 6] = [0x02, 0x00, 0x5e, 0x10, 0x00, 0x01];
+
+    /// Builds a DHCPv4 message from a client with the given options, which should
+    /// end with the end option.
+    fn dhcpv4_message(op: u8, yiaddr: Ipv4Addr, options: &[u8]) -> Vec<u8> {
+        let mut data = vec![0u8; 240];
+        data[0] = op;
+        data[1] = 1; // Ethernet
+        data[2] = CLIENT_MAC.len() as u8;
+        data[4..8].copy_from_slice(&0x3903f326u32.to_be_bytes());
+        data[16..20].copy_from_slice(&yiaddr.octets());
+        data[28..34].copy_from_slice(&CLIENT_MAC);
+        data[236..240].copy_from_slice(&DHCP_MAGIC_COOKIE);
+        data.extend_from_slice(options);
+        data
+    }
+
+    fn option(code: u8, value: &[u8]) -> Vec<u8> {
+        [&[code, value.len() as u8], value].concat()
+    }
+
+    fn option6(code: u16, value: &[u8]) -> Vec<u8> {
+        [
+            &code.to_be_bytes(),
+            &(value.len() as u16).to_be_bytes(),
+            value,
+        ]
+        .concat()
+    }
+
+    fn ia_address(address: &str, preferred: u32, valid: u32) -> Vec<u8> {
+        let address: Ipv6Addr = address.parse().unwrap();
+        let value = [
+            address.octets().as_slice(),
+            &preferred.to_be_bytes(),
+            &valid.to_be_bytes(),
+        ]
+        .concat();
+        option6(OPTION6_IA_ADDR, &value)
+    }
+
+    fn ia_na(addresses: &[Vec<u8>]) -> Vec<u8> {
+        // IAID, T1 and T2
+        let mut value = vec![0, 0, 0, 1, 0, 0, 0x07, 0x08, 0, 0, 0x0b, 0xb8];
+        value.extend(addresses.concat());
+        option6(OPTION6_IA_NA, &value)
+    }
+
+    fn dhcpv6_message(message_type: u8, options: &[Vec<u8>]) -> Vec<u8> {
+        [
+            &[message_type, 0xab, 0xcd, 0xef],
+            options.concat().as_slice(),
+        ]
+        .concat()
+    }
+
+    fn relay_forward(message: &[u8]) -> Vec<u8> {
+        let mut relay = vec![12, 0];
+        relay.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        relay.extend_from_slice(&"fe80::1".parse::<Ipv6Addr>().unwrap().octets());
+        relay.extend(option6(OPTION6_RELAY_MSG, message));
+        relay
+    }
+
+    #[test]
+    fn reads_options_overloaded_into_file_and_sname() {
+        let options = [
+            option(OPTION_MESSAGE_TYPE, &[5]),
+            option(OPTION_OVERLOAD, &[3]),
+            vec![OPTION_END],
+        ]
+        .concat();
+        let mut data = dhcpv4_message(2, Ipv4Addr::new(192, 168, 1, 20), &options);
+
+        let file = [option(OPTION_HOSTNAME, b"laptop"), vec![OPTION_END]].concat();
+        data[108..108 + file.len()].copy_from_slice(&file);
+        let sname = [
+            option(OPTION_VENDOR_CLASS, b"MSFT 5.0"),
+            option(OPTION_LEASE_TIME, &86400u32.to_be_bytes()),
+            vec![OPTION_END],
+        ]
+        .concat();
+        data[44..44 + sname.len()].copy_from_slice(&sname);
+
+        let message = parse_dhcpv4_message(&data).unwrap();
+        assert_eq!(message.message_type, DhcpMessageType::Ack);
+        assert_eq!(message.transaction_id, 0x3903f326);
+        assert_eq!(message.client_id, CLIENT_MAC);
+        assert_eq!(message.hostname.as_deref(), Some("laptop"));
+        assert_eq!(message.vendor_class.as_deref(), Some("MSFT 5.0"));
+        assert_eq!(message.lease_time, Some(Duration::from_secs(86400)));
+        assert_eq!(
+            message.assigned_ips,
+            [IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20))]
+        );
+    }
+
+    #[test]
+    fn ignores_file_and_sname_without_overload() {
+        let options = [option(OPTION_MESSAGE_TYPE, &[1]), vec![OPTION_END]].concat();
+        let mut data = dhcpv4_message(1, Ipv4Addr::UNSPECIFIED, &options);
+        let file = [option(OPTION_HOSTNAME, b"laptop"), vec![OPTION_END]].concat();
+        data[108..108 + file.len()].copy_from_slice(&file);
+
+        let message = parse_dhcpv4_message(&data).unwrap();
+        assert_eq!(message.message_type, DhcpMessageType::Discover);
+        assert_eq!(message.hostname, None);
+    }
+
+    #[test]
+    fn reads_client_fqdn_in_wire_or_ascii_format() {
+        let wire_name = b"\x06laptop\x07example\x03com\x00";
+        let options = [
+            option(OPTION_MESSAGE_TYPE, &[3]),
+            option(
+                OPTION_CLIENT_FQDN,
+                &[&[0x05, 0, 0], wire_name.as_slice()].concat(),
+            ),
+            vec![OPTION_END],
+        ]
+        .concat();
+        let message = parse_dhcpv4_message(&dhcpv4_message(1, Ipv4Addr::UNSPECIFIED, &options));
+        assert_eq!(message.unwrap().fqdn.as_deref(), Some("laptop.example.com"));
+
+        // Without the E flag the name is plain ASCII
+        let options = [
+            option(OPTION_MESSAGE_TYPE, &[3]),
+            option(OPTION_CLIENT_FQDN, b"\x01\x00\x00laptop.example.com"),
+            vec![OPTION_END],
+        ]
+        .concat();
+        let message = parse_dhcpv4_message(&dhcpv4_message(1, Ipv4Addr::UNSPECIFIED, &options));
+        assert_eq!(message.unwrap().fqdn.as_deref(), Some("laptop.example.com"));
+    }
+
+    #[test]
+    fn unwraps_relayed_client_message() {
+        let solicit = dhcpv6_message(
+            1,
+            &[
+                option6(OPTION6_CLIENT_ID, &[0, 3, 0, 1, 2, 0, 0x5e, 0x10, 0, 1]),
+                ia_na(&[ia_address("2001:db8::100", 0, 0)]),
+                option6(OPTION6_CLIENT_FQDN, b"\x01\x06laptop\x00"),
+            ],
+        );
+
+        let message = parse_dhcpv6_message(&relay_forward(&relay_forward(&solicit))).unwrap();
+        assert_eq!(message.message_type, DhcpMessageType::Solicit);
+        assert_eq!(message.transaction_id, 0xabcdef);
+        assert_eq!(message.client_id, [0, 3, 0, 1, 2, 0, 0x5e, 0x10, 0, 1]);
+        assert_eq!(message.fqdn.as_deref(), Some("laptop"));
+        assert_eq!(message.requested_ip, Some("2001:db8::100".parse().unwrap()));
+    }
+
+    #[test]
+    fn rejects_relay_chain_longer_than_hop_count_limit() {
+        let mut data = dhcpv6_message(1, &[]);
+        for _ in 0..HOP_COUNT_LIMIT {
+            data = relay_forward(&data);
+        }
+        assert!(parse_dhcpv6_message(&data).is_ok());
+
+        assert_eq!(
+            parse_dhcpv6_message(&relay_forward(&data)),
+            Err(DecodeError::BadLength {
+                layer: "dhcpv6 relay chain",
+                length: HOP_COUNT_LIMIT + 1
+            })
+        );
+    }
+
+    #[test]
+    fn reads_lease_from_ia_address_lifetimes() {
+        let reply = dhcpv6_message(
+            7,
+            &[ia_na(&[
+                ia_address("2001:db8::100", 1800, 3600),
+                ia_address("2001:db8::200", 0, 0),
+                ia_address("2001:db8::300", 1800, 7200),
+            ])],
+        );
+
+        let message = parse_dhcpv6_message(&reply).unwrap();
+        assert_eq!(message.message_type, DhcpMessageType::Reply);
+        assert_eq!(message.lease_time, Some(Duration::from_secs(3600)));
+        assert_eq!(
+            message.assigned_ips,
+            [
+                "2001:db8::100".parse::<IpAddr>().unwrap(),
+                "2001:db8::300".parse().unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_ia_address_option() {
+        let mut reply = dhcpv6_message(7, &[ia_na(&[ia_address("2001:db8::100", 0, 60)])]);
+        reply.truncate(reply.len() - 1);
+
+        assert!(matches!(
+            parse_dhcpv6_message(&reply),
+            Err(DecodeError::Truncated { .. })
+        ));
+    }
+}
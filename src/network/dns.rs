@@ -1,29 +1,196 @@
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
 use std::vec;
 
 use std::net::UdpSocket;
 
-// Google's DNS server
-// TODO: replace with local DNS server
-// const DNS_SERVER: &str = "8.8.8.8:53";
-const DNS_SERVER: &str = "64.71.255.204:53";
+use crate::network::error::{DecodeError, DecodeResult};
 
 #[derive(Debug)]
 pub struct DnsDirectRecord {
     pub domain: String,
     pub records: Vec<IpAddr>,
 }
-#[derive(Debug)]
+// https://datatracker.ietf.org/doc/html/rfc1035#section-4.1.1
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DnsHeader {
-    id: u16,           // 16 bits
-    flags: u16,        // 16 bits
-    pub qd_count: u16, // 16 bits
-    pub an_count: u16, // 16 bits
-    pub ns_count: u16, // 16 bits
-    pub ar_count: u16, // 16 bits
+    pub id: u16,         // 16 bits
+    pub flags: DnsFlags, // 16 bits
+    pub qd_count: u16,   // 16 bits
+    pub an_count: u16,   // 16 bits
+    pub ns_count: u16,   // 16 bits
+    pub ar_count: u16,   // 16 bits
 }
 
-#[derive(Debug)]
+/* The flags and codes in the second word of the header
+                                1  1  1  1  1  1
+  0  1  2  3  4  5  6  7  8  9  0  1  2  3  4  5
++--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+|QR|   Opcode  |AA|TC|RD|RA| Z|AD|CD|   RCODE   |
++--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DnsFlags {
+    /* Set in responses, clear in queries */
+    pub qr: bool, // 1 bit
+
+    pub opcode: Opcode, // 4 bits
+
+    /* Authoritative Answer */
+    pub aa: bool, // 1 bit
+
+    /* TrunCation, the response did not fit in the datagram */
+    pub tc: bool, // 1 bit
+
+    /* Recursion Desired */
+    pub rd: bool, // 1 bit
+
+    /* Recursion Available */
+    pub ra: bool, // 1 bit
+
+    /* Authentic Data, the resolver validated the answer with DNSSEC (RFC 4035) */
+    pub ad: bool, // 1 bit
+
+    /* Checking Disabled, the client asked for no DNSSEC validation (RFC 4035) */
+    pub cd: bool, // 1 bit
+
+    pub rcode: Rcode, // 4 bits
+}
+
+impl From<u16> for DnsFlags {
+    fn from(flags: u16) -> Self {
+        let bit = |position: u16| flags & (1 << position) != 0;
+        DnsFlags {
+            qr: bit(15),
+            opcode: Opcode::from(((flags >> 11) & 0x0F) as u8),
+            aa: bit(10),
+            tc: bit(9),
+            rd: bit(8),
+            ra: bit(7),
+            ad: bit(5),
+            cd: bit(4),
+            rcode: Rcode::from(flags & 0x0F),
+        }
+    }
+}
+
+impl From<DnsFlags> for u16 {
+    fn from(flags: DnsFlags) -> Self {
+        let bit = |set: bool, position: u16| (set as u16) << position;
+        bit(flags.qr, 15)
+            | (u8::from(flags.opcode) as u16 & 0x0F) << 11
+            | bit(flags.aa, 10)
+            | bit(flags.tc, 9)
+            | bit(flags.rd, 8)
+            | bit(flags.ra, 7)
+            | bit(flags.ad, 5)
+            | bit(flags.cd, 4)
+            | u16::from(flags.rcode) & 0x0F
+    }
+}
+
+// https://www.iana.org/assignments/dns-parameters/dns-parameters.xhtml#dns-parameters-5
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    Query,
+    IQuery,
+    Status,
+    Notify,
+    Update,
+    Other(u8),
+}
+
+impl From<u8> for Opcode {
+    fn from(opcode: u8) -> Self {
+        match opcode {
+            0 => Opcode::Query,
+            1 => Opcode::IQuery,
+            2 => Opcode::Status,
+            4 => Opcode::Notify,
+            5 => Opcode::Update,
+            other => Opcode::Other(other),
+        }
+    }
+}
+
+impl From<Opcode> for u8 {
+    fn from(opcode: Opcode) -> Self {
+        match opcode {
+            Opcode::Query => 0,
+            Opcode::IQuery => 1,
+            Opcode::Status => 2,
+            Opcode::Notify => 4,
+            Opcode::Update => 5,
+            Opcode::Other(opcode) => opcode,
+        }
+    }
+}
+
+// https://www.iana.org/assignments/dns-parameters/dns-parameters.xhtml#dns-parameters-6
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Rcode {
+    NoError,
+    FormErr,
+    ServFail,
+    NXDomain,
+    NotImp,
+    Refused,
+
+    /* Extended RCODE, only possible with EDNS */
+    BadVers,
+
+    Other(u16),
+}
+
+impl From<u16> for Rcode {
+    fn from(rcode: u16) -> Self {
+        match rcode {
+            0 => Rcode::NoError,
+            1 => Rcode::FormErr,
+            2 => Rcode::ServFail,
+            3 => Rcode::NXDomain,
+            4 => Rcode::NotImp,
+            5 => Rcode::Refused,
+            16 => Rcode::BadVers,
+            other => Rcode::Other(other),
+        }
+    }
+}
+
+impl From<Rcode> for u16 {
+    fn from(rcode: Rcode) -> Self {
+        match rcode {
+            Rcode::NoError => 0,
+            Rcode::FormErr => 1,
+            Rcode::ServFail => 2,
+            Rcode::NXDomain => 3,
+            Rcode::NotImp => 4,
+            Rcode::Refused => 5,
+            Rcode::BadVers => 16,
+            Rcode::Other(rcode) => rcode,
+        }
+    }
+}
+
+impl fmt::Display for Rcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rcode::NoError => write!(f, "NOERROR"),
+            Rcode::FormErr => write!(f, "FORMERR"),
+            Rcode::ServFail => write!(f, "SERVFAIL"),
+            Rcode::NXDomain => write!(f, "NXDOMAIN"),
+            Rcode::NotImp => write!(f, "NOTIMP"),
+            Rcode::Refused => write!(f, "REFUSED"),
+            Rcode::BadVers => write!(f, "BADVERS"),
+            Rcode::Other(rcode) => write!(f, "RCODE{}", rcode),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DnsQuestion {
     pub qname: String, // variable length
     pub qtype: u16,    // 16 bits
@@ -31,122 +198,880 @@ pub struct DnsQuestion {
 }
 
 // https://www.ietf.org/rfc/rfc1035.txt - section 4.1
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DnsMessage {
+    pub header: DnsHeader,
     pub questions: Vec<DnsQuestion>,
     pub answers: Vec<DnsResourceRecord>,
-    authorities: Vec<DnsResourceRecord>,
-    additional: Vec<DnsResourceRecord>,
+    pub authorities: Vec<DnsResourceRecord>,
+    pub additional: Vec<DnsResourceRecord>,
+
+    /* Decoded from the OPT record in the additional section, if there is one. The
+    encoder writes the OPT record from `additional` and ignores this field. */
+    pub edns: Option<Edns>,
 }
 
-#[derive(Debug)]
+// EDNS(0): https://datatracker.ietf.org/doc/html/rfc6891#section-6.1.3
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edns {
+    /* The largest UDP payload the sender can reassemble, carried in the CLASS field */
+    pub udp_payload_size: u16, // 16 bits
+
+    /* The upper 8 bits of the 12 bit RCODE, carried in the TTL field */
+    pub extended_rcode: u8, // 8 bits
+
+    pub version: u8, // 8 bits
+
+    /* DNSSEC OK, the sender understands DNSSEC records (RFC 3225) */
+    pub dnssec_ok: bool, // 1 bit
+
+    pub options: Vec<EdnsOption>,
+}
+
+// https://www.iana.org/assignments/dns-parameters/dns-parameters.xhtml#dns-parameters-11
+const EDNS_CLIENT_SUBNET: u16 = 8;
+const EDNS_COOKIE: u16 = 10;
+const EDNS_PADDING: u16 = 12;
+const EDNS_EXTENDED_ERROR: u16 = 15;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EdnsOption {
+    // https://datatracker.ietf.org/doc/html/rfc7871#section-6
+    ClientSubnet {
+        /* The network the query originated from, with the host bits cleared */
+        address: IpAddr,
+        source_prefix: u8,
+        scope_prefix: u8,
+    },
+
+    // https://datatracker.ietf.org/doc/html/rfc7873#section-4
+    Cookie {
+        client: Vec<u8>,
+        server: Vec<u8>,
+    },
+
+    /* Number of padding bytes (RFC 7830) */
+    Padding(usize),
+
+    // https://datatracker.ietf.org/doc/html/rfc8914#section-2
+    ExtendedError {
+        info_code: u16,
+        extra_text: String,
+    },
+
+    Unknown {
+        code: u16,
+        data: Vec<u8>,
+    },
+}
+
+impl EdnsOption {
+    /// Parses the data of an option. Malformed options are kept as unknown ones, so
+    /// that they do not cost the records of the rest of the message.
+    fn parse(code: u16, data: &[u8]) -> EdnsOption {
+        match code {
+            EDNS_CLIENT_SUBNET if data.len() >= 4 => {
+                let family = u16::from_be_bytes([data[0], data[1]]);
+                let source_prefix = data[2];
+                let scope_prefix = data[3];
+
+                // Only the bytes covered by the source prefix are sent
+                let bytes = &data[4..];
+                let address = match (family, bytes.len()) {
+                    (1, 0..=4) => {
+                        let mut octets = [0u8; 4];
+                        octets[..bytes.len()].copy_from_slice(bytes);
+                        IpAddr::V4(Ipv4Addr::from(octets))
+                    }
+                    (2, 0..=16) => {
+                        let mut octets = [0u8; 16];
+                        octets[..bytes.len()].copy_from_slice(bytes);
+                        IpAddr::V6(Ipv6Addr::from(octets))
+                    }
+                    _ => {
+                        return EdnsOption::Unknown {
+                            code,
+                            data: data.to_vec(),
+                        }
+                    }
+                };
+
+                EdnsOption::ClientSubnet {
+                    address,
+                    source_prefix,
+                    scope_prefix,
+                }
+            }
+            // An 8 byte client cookie, optionally followed by an 8 to 32 byte server cookie
+            EDNS_COOKIE if data.len() == 8 || (16..=40).contains(&data.len()) => {
+                EdnsOption::Cookie {
+                    client: data[..8].to_vec(),
+                    server: data[8..].to_vec(),
+                }
+            }
+            EDNS_PADDING => EdnsOption::Padding(data.len()),
+            EDNS_EXTENDED_ERROR if data.len() >= 2 => EdnsOption::ExtendedError {
+                info_code: u16::from_be_bytes([data[0], data[1]]),
+                extra_text: String::from_utf8_lossy(&data[2..]).into_owned(),
+            },
+            _ => EdnsOption::Unknown {
+                code,
+                data: data.to_vec(),
+            },
+        }
+    }
+}
+
+/// Returns the name of an Extended DNS Error info code.
+/// https://www.iana.org/assignments/dns-parameters/dns-parameters.xhtml#extended-dns-error-codes
+pub fn extended_error_name(info_code: u16) -> Option<&'static str> {
+    let name = match info_code {
+        0 => "Other Error",
+        1 => "Unsupported DNSKEY Algorithm",
+        2 => "Unsupported DS Digest Type",
+        3 => "Stale Answer",
+        4 => "Forged Answer",
+        5 => "DNSSEC Indeterminate",
+        6 => "DNSSEC Bogus",
+        7 => "Signature Expired",
+        8 => "Signature Not Yet Valid",
+        9 => "DNSKEY Missing",
+        10 => "RRSIGs Missing",
+        11 => "No Zone Key Bit Set",
+        12 => "NSEC Missing",
+        13 => "Cached Error",
+        14 => "Not Ready",
+        15 => "Blocked",
+        16 => "Censored",
+        17 => "Filtered",
+        18 => "Prohibited",
+        19 => "Stale NXDOMAIN Answer",
+        20 => "Not Authoritative",
+        21 => "Not Supported",
+        22 => "No Reachable Authority",
+        23 => "Network Error",
+        24 => "Invalid Data",
+        25 => "Signature Expired before Valid",
+        26 => "Too Early",
+        27 => "Unsupported NSEC3 Iterations Value",
+        28 => "Unable to conform to policy",
+        29 => "Synthesized",
+        30 => "Invalid Query Type",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Formats the option for display, e.g. `ecs=192.0.2.0/24/0`.
+impl fmt::Display for EdnsOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EdnsOption::ClientSubnet {
+                address,
+                source_prefix,
+                scope_prefix,
+            } => write!(f, "ecs={}/{}/{}", address, source_prefix, scope_prefix),
+            EdnsOption::Cookie { client, server } => {
+                write!(f, "cookie=")?;
+                for byte in client.iter().chain(server) {
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+            EdnsOption::Padding(length) => write!(f, "padding={}", length),
+            EdnsOption::ExtendedError {
+                info_code,
+                extra_text,
+            } => {
+                match extended_error_name(*info_code) {
+                    Some(name) => write!(f, "ede={} ({})", info_code, name)?,
+                    None => write!(f, "ede={}", info_code)?,
+                }
+                if !extra_text.is_empty() {
+                    write!(f, ": {}", extra_text)?;
+                }
+                Ok(())
+            }
+            EdnsOption::Unknown { code, data } => {
+                write!(f, "opt{}=<{} bytes>", code, data.len())
+            }
+        }
+    }
+}
+
+// https://www.iana.org/assignments/dns-parameters/dns-parameters.xhtml#dns-parameters-2
+pub const CLASS_IN: u16 = 1;
+
+/* Multicast DNS reuses the top bit of the class: in questions it asks for a unicast
+response, in records it tells receivers to flush the cached records of the name.
+https://datatracker.ietf.org/doc/html/rfc6762#section-18.12 */
+const MDNS_CLASS_FLAG: u16 = 0x8000;
+
+pub const DNS_PORT: u16 = 53;
+pub const MDNS_PORT: u16 = 5353;
+pub const LLMNR_PORT: u16 = 5355;
+
+/// The protocols that carry DNS messages, told apart by their UDP port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DnsProtocol {
+    Dns,
+    /// Multicast DNS (RFC 6762), used to advertise `.local` names on the link
+    Mdns,
+    /// Link-Local Multicast Name Resolution (RFC 4795), used by Windows hosts
+    Llmnr,
+}
+
+impl DnsProtocol {
+    /// Returns the protocol of a datagram sent between the given ports, if either
+    /// is a DNS port.
+    pub fn from_ports(src: u16, dst: u16) -> Option<DnsProtocol> {
+        let either = |port: u16| src == port || dst == port;
+        if either(DNS_PORT) {
+            Some(DnsProtocol::Dns)
+        } else if either(MDNS_PORT) {
+            Some(DnsProtocol::Mdns)
+        } else if either(LLMNR_PORT) {
+            Some(DnsProtocol::Llmnr)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the names resolved by the protocol are only valid on the local link.
+    pub fn is_link_local(self) -> bool {
+        self != DnsProtocol::Dns
+    }
+}
+
+impl fmt::Display for DnsProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DnsProtocol::Dns => write!(f, "DNS"),
+            DnsProtocol::Mdns => write!(f, "mDNS"),
+            DnsProtocol::Llmnr => write!(f, "LLMNR"),
+        }
+    }
+}
+
+// Resource record types: https://www.iana.org/assignments/dns-parameters/dns-parameters.xhtml#dns-parameters-4
+pub const TYPE_A: u16 = 1;
+pub const TYPE_NS: u16 = 2;
+pub const TYPE_CNAME: u16 = 5;
+pub const TYPE_SOA: u16 = 6;
+pub const TYPE_PTR: u16 = 12;
+pub const TYPE_MX: u16 = 15;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_AAAA: u16 = 28;
+pub const TYPE_SRV: u16 = 33;
+pub const TYPE_OPT: u16 = 41;
+pub const TYPE_SVCB: u16 = 64;
+pub const TYPE_HTTPS: u16 = 65;
+pub const TYPE_ANY: u16 = 255;
+pub const TYPE_CAA: u16 = 257;
+
+/// Returns the mnemonic of a record type, e.g. `AAAA`, or `TYPE<n>` for types
+/// without one as in RFC 3597.
+pub fn type_name(rtype: u16) -> String {
+    let name = match rtype {
+        TYPE_A => "A",
+        TYPE_NS => "NS",
+        TYPE_CNAME => "CNAME",
+        TYPE_SOA => "SOA",
+        TYPE_PTR => "PTR",
+        TYPE_MX => "MX",
+        TYPE_TXT => "TXT",
+        TYPE_AAAA => "AAAA",
+        TYPE_SRV => "SRV",
+        TYPE_OPT => "OPT",
+        TYPE_SVCB => "SVCB",
+        TYPE_HTTPS => "HTTPS",
+        TYPE_ANY => "ANY",
+        TYPE_CAA => "CAA",
+        _ => return format!("TYPE{}", rtype),
+    };
+    name.to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DNSRData {
     A(Ipv4Addr),
     AAAA(Ipv6Addr),
     CNAME(String),
     PTR(String),
-    UNIMPLEMENTED(),
+    NS(String),
+
+    // https://datatracker.ietf.org/doc/html/rfc1035#section-3.3.9
+    MX {
+        preference: u16,
+        exchange: String,
+    },
+
+    /* One or more character strings, which are not required to be text */
+    TXT(Vec<Vec<u8>>),
+
+    // https://datatracker.ietf.org/doc/html/rfc1035#section-3.3.13
+    SOA {
+        mname: String,
+        rname: String,
+        serial: u32,
+        refresh: u32,
+        retry: u32,
+        expire: u32,
+        minimum: u32,
+    },
+
+    // https://datatracker.ietf.org/doc/html/rfc2782
+    SRV {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: String,
+    },
+
+    // https://datatracker.ietf.org/doc/html/rfc8659#section-4.1
+    CAA {
+        flags: u8,
+        tag: String,
+        value: Vec<u8>,
+    },
+
+    // https://datatracker.ietf.org/doc/html/rfc9460#section-2.2
+    SVCB(SvcbData),
+
+    /* The SVCB record for HTTPS origins, with the same format */
+    HTTPS(SvcbData),
+
+    /* The options of an EDNS pseudo record, see `Edns` for the other fields */
+    OPT(Vec<EdnsOption>),
+
+    /* Record types that are not decoded keep their raw data */
+    Unknown {
+        rtype: u16,
+        data: Vec<u8>,
+    },
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SvcbData {
+    /* 0 for alias mode, otherwise the priority of this service endpoint */
+    pub priority: u16, // 16 bits
+
+    /* The domain name of the endpoint, empty if it is the owner name */
+    pub target: String, // variable length
+
+    pub params: Vec<SvcParam>, // variable length, up to the end of the record
+}
+
+// https://www.iana.org/assignments/dns-svcb/dns-svcb.xhtml
+const SVC_PARAM_MANDATORY: u16 = 0;
+const SVC_PARAM_ALPN: u16 = 1;
+const SVC_PARAM_NO_DEFAULT_ALPN: u16 = 2;
+const SVC_PARAM_PORT: u16 = 3;
+const SVC_PARAM_IPV4_HINT: u16 = 4;
+const SVC_PARAM_ECH: u16 = 5;
+const SVC_PARAM_IPV6_HINT: u16 = 6;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SvcParam {
+    Mandatory(Vec<u16>),
+    Alpn(Vec<String>),
+    NoDefaultAlpn,
+    Port(u16),
+    Ipv4Hint(Vec<Ipv4Addr>),
+    Ech(Vec<u8>),
+    Ipv6Hint(Vec<Ipv6Addr>),
+    Unknown { key: u16, value: Vec<u8> },
+}
+
+impl SvcbData {
+    /// Returns the addresses from the ipv4hint and ipv6hint parameters, which clients
+    /// may connect to before the A and AAAA lookups of the target finish.
+    pub fn hints(&self) -> impl Iterator<Item = IpAddr> + '_ {
+        self.params.iter().flat_map(|param| match param {
+            SvcParam::Ipv4Hint(addrs) => addrs.iter().map(|addr| IpAddr::V4(*addr)).collect(),
+            SvcParam::Ipv6Hint(addrs) => addrs.iter().map(|addr| IpAddr::V6(*addr)).collect(),
+            _ => Vec::new(),
+        })
+    }
+
+    /// Returns the name of the service endpoint that the record's hints belong to, given
+    /// the record's owner name. This is the target name, or the owner name without its
+    /// `_port._scheme` prefix if the target is the owner itself (RFC 9460 section 2.5).
+    pub fn endpoint<'a>(&'a self, owner: &'a str) -> &'a str {
+        if !self.target.is_empty() {
+            return &self.target;
+        }
+
+        let mut name = owner;
+        while let Some((_, rest)) = name.strip_prefix('_').and_then(|name| name.split_once('.')) {
+            name = rest;
+        }
+        name
+    }
+
+    /// Returns the protocols from the alpn parameter, e.g. `h2` and `h3`.
+    pub fn alpn(&self) -> &[String] {
+        self.params
+            .iter()
+            .find_map(|param| match param {
+                SvcParam::Alpn(protocols) => Some(protocols.as_slice()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    fn parse(message: &[u8], index: &mut usize, rdata_end: usize) -> DecodeResult<SvcbData> {
+        let priority = read_u16(message, *index)?;
+        *index += 2;
+        let target = parse_name(message, index)?;
+
+        let mut params = Vec::new();
+        while *index < rdata_end {
+            let key = read_u16(message, *index)?;
+            let length = read_u16(message, *index + 2)? as usize;
+            let start = *index + 4;
+            *index = start + length;
+            if *index > rdata_end {
+                return Err(DecodeError::BadLength {
+                    layer: "dns SvcParam",
+                    length,
+                });
+            }
+            params.push(SvcParam::parse(key, &message[start..*index])?);
+        }
+
+        Ok(SvcbData {
+            priority,
+            target,
+            params,
+        })
+    }
+}
+
+impl SvcParam {
+    fn parse(key: u16, value: &[u8]) -> DecodeResult<SvcParam> {
+        let bad_length = |layer| DecodeError::BadLength {
+            layer,
+            length: value.len(),
+        };
+
+        let param = match key {
+            SVC_PARAM_MANDATORY => {
+                if !value.len().is_multiple_of(2) {
+                    return Err(bad_length("dns mandatory"));
+                }
+                SvcParam::Mandatory(
+                    value
+                        .chunks_exact(2)
+                        .map(|key| u16::from_be_bytes([key[0], key[1]]))
+                        .collect(),
+                )
+            }
+            SVC_PARAM_ALPN => {
+                // A list of length prefixed protocol ids
+                let mut protocols = Vec::new();
+                let mut position = 0;
+                while position < value.len() {
+                    let length = value[position] as usize;
+                    DecodeError::check_len("dns alpn", value, position + 1 + length)?;
+                    protocols.push(
+                        String::from_utf8_lossy(&value[position + 1..position + 1 + length])
+                            .into_owned(),
+                    );
+                    position += 1 + length;
+                }
+                SvcParam::Alpn(protocols)
+            }
+            SVC_PARAM_NO_DEFAULT_ALPN => SvcParam::NoDefaultAlpn,
+            SVC_PARAM_PORT => SvcParam::Port(
+                value
+                    .try_into()
+                    .map(u16::from_be_bytes)
+                    .map_err(|_| bad_length("dns port"))?,
+            ),
+            SVC_PARAM_IPV4_HINT => {
+                if value.is_empty() || !value.len().is_multiple_of(4) {
+                    return Err(bad_length("dns ipv4hint"));
+                }
+                SvcParam::Ipv4Hint(
+                    value
+                        .chunks_exact(4)
+                        .map(|octets| Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]))
+                        .collect(),
+                )
+            }
+            SVC_PARAM_ECH => SvcParam::Ech(value.to_vec()),
+            SVC_PARAM_IPV6_HINT => {
+                if value.is_empty() || !value.len().is_multiple_of(16) {
+                    return Err(bad_length("dns ipv6hint"));
+                }
+                SvcParam::Ipv6Hint(
+                    value
+                        .chunks_exact(16)
+                        .map(|octets| Ipv6Addr::from(<[u8; 16]>::try_from(octets).unwrap()))
+                        .collect(),
+                )
+            }
+            _ => SvcParam::Unknown {
+                key,
+                value: value.to_vec(),
+            },
+        };
+        Ok(param)
+    }
+}
+
+/// Formats the parameter as in zone files, e.g. `alpn=h2,h3`.
+impl fmt::Display for SvcParam {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn join<T: ToString>(values: &[T]) -> String {
+            values
+                .iter()
+                .map(|value| value.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        }
+
+        match self {
+            SvcParam::Mandatory(keys) => {
+                let keys = keys
+                    .iter()
+                    .map(|key| format!("key{}", key))
+                    .collect::<Vec<_>>();
+                write!(f, "mandatory={}", keys.join(","))
+            }
+            SvcParam::Alpn(protocols) => write!(f, "alpn={}", protocols.join(",")),
+            SvcParam::NoDefaultAlpn => write!(f, "no-default-alpn"),
+            SvcParam::Port(port) => write!(f, "port={}", port),
+            SvcParam::Ipv4Hint(addrs) => write!(f, "ipv4hint={}", join(addrs)),
+            SvcParam::Ech(config) => write!(f, "ech=<{} bytes>", config.len()),
+            SvcParam::Ipv6Hint(addrs) => write!(f, "ipv6hint={}", join(addrs)),
+            SvcParam::Unknown { key, value } => write!(f, "key{}=<{} bytes>", key, value.len()),
+        }
+    }
+}
+
+/// Formats the record data in the presentation format of zone files, e.g.
+/// `10 mail.example.com` for an MX record.
+impl fmt::Display for DNSRData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DNSRData::A(ipv4) => write!(f, "{}", ipv4),
+            DNSRData::AAAA(ipv6) => write!(f, "{}", ipv6),
+            DNSRData::CNAME(name) | DNSRData::PTR(name) | DNSRData::NS(name) => {
+                write!(f, "{}", name)
+            }
+            DNSRData::MX {
+                preference,
+                exchange,
+            } => write!(f, "{} {}", preference, exchange),
+            DNSRData::TXT(strings) => {
+                let strings = strings
+                    .iter()
+                    .map(|string| format!("{:?}", String::from_utf8_lossy(string)))
+                    .collect::<Vec<_>>();
+                write!(f, "{}", strings.join(" "))
+            }
+            DNSRData::SOA {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => write!(
+                f,
+                "{} {} {} {} {} {} {}",
+                mname, rname, serial, refresh, retry, expire, minimum
+            ),
+            DNSRData::SRV {
+                priority,
+                weight,
+                port,
+                target,
+            } => write!(f, "{} {} {} {}", priority, weight, port, target),
+            DNSRData::CAA { flags, tag, value } => {
+                write!(f, "{} {} {:?}", flags, tag, String::from_utf8_lossy(value))
+            }
+            DNSRData::OPT(options) => {
+                let options = options
+                    .iter()
+                    .map(|option| option.to_string())
+                    .collect::<Vec<_>>();
+                write!(f, "{}", options.join(" "))
+            }
+            DNSRData::SVCB(svcb) | DNSRData::HTTPS(svcb) => {
+                let target = if svcb.target.is_empty() {
+                    "."
+                } else {
+                    &svcb.target
+                };
+                write!(f, "{} {}", svcb.priority, target)?;
+                for param in &svcb.params {
+                    write!(f, " {}", param)?;
+                }
+                Ok(())
+            }
+            // https://datatracker.ietf.org/doc/html/rfc3597#section-5
+            DNSRData::Unknown { data, .. } => {
+                write!(f, "\\# {}", data.len())?;
+                if !data.is_empty() {
+                    write!(f, " ")?;
+                }
+                for byte in data {
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DnsResourceRecord {
-    pub name: String,  // variable length
-    pub rtype: u16,    // 16 bits
-    pub rclass: u16,   // 16 bits
-    pub ttl: u32,      // 32 bits
-    pub rdlength: u16, // 16 bits
-    pub rdata: DNSRData,
+    pub name: String, // variable length
+    pub rtype: u16,   // 16 bits
+    pub rclass: u16,  // 16 bits
+    pub ttl: u32,     // 32 bits
+
+    /* The 16 bit RDLENGTH is not kept, as it depends on how the names in the data
+    were compressed. The encoder computes it from the data. */
+    pub rdata: DNSRData, // variable length
 }
 
 impl DnsResourceRecord {
-    fn parse(message: &[u8], index: &mut usize) -> DnsResourceRecord {
-        let name = parse_name(message, index);
-
-        let rtype = u16::from_be_bytes([message[*index], message[*index + 1]]);
-        let rclass = u16::from_be_bytes([message[*index + 2], message[*index + 3]]);
-        let ttl = u32::from_be_bytes([
-            message[*index + 4],
-            message[*index + 5],
-            message[*index + 6],
-            message[*index + 7],
-        ]);
-        let rdlength = u16::from_be_bytes([message[*index + 8], message[*index + 9]]);
+    /// Whether an mDNS record replaces the records of its name and type cached
+    /// from earlier announcements.
+    pub fn cache_flush(&self) -> bool {
+        self.rclass & MDNS_CLASS_FLAG != 0
+    }
+
+    /// Returns the class without the mDNS cache-flush bit.
+    pub fn class(&self) -> u16 {
+        self.rclass & !MDNS_CLASS_FLAG
+    }
+
+    fn parse(message: &[u8], index: &mut usize) -> DecodeResult<DnsResourceRecord> {
+        let name = parse_name(message, index)?;
+
+        let rtype = read_u16(message, *index)?;
+        let rclass = read_u16(message, *index + 2)?;
+        let ttl = read_u32(message, *index + 4)?;
+        let rdlength = read_u16(message, *index + 8)?;
         *index += 10;
 
         let rdata_end = *index + rdlength as usize;
-        let rdata = DnsResourceRecord::parse_r_data(rtype, message, index);
+        let rdata = DnsResourceRecord::parse_r_data(rtype, rdlength, message, index)?;
 
         *index = rdata_end;
 
-        DnsResourceRecord {
+        Ok(DnsResourceRecord {
             name,
             rtype,
             rclass,
             ttl,
-            rdlength,
             rdata,
-        }
+        })
     }
 
-    pub fn parse_r_data(rtype: u16, message: &[u8], index: &mut usize) -> DNSRData {
-        if rtype == 1 {
-            return DNSRData::A(Ipv4Addr::new(
-                message[*index + 0],
-                message[*index + 1],
-                message[*index + 2],
-                message[*index + 3],
-            ));
-        } else if rtype == 28 {
-            return DNSRData::AAAA(Ipv6Addr::new(
-                u16::from_be_bytes([message[*index + 0], message[*index + 1]]),
-                u16::from_be_bytes([message[*index + 2], message[*index + 3]]),
-                u16::from_be_bytes([message[*index + 4], message[*index + 5]]),
-                u16::from_be_bytes([message[*index + 6], message[*index + 7]]),
-                u16::from_be_bytes([message[*index + 8], message[*index + 9]]),
-                u16::from_be_bytes([message[*index + 10], message[*index + 11]]),
-                u16::from_be_bytes([message[*index + 12], message[*index + 13]]),
-                u16::from_be_bytes([message[*index + 14], message[*index + 15]]),
-            ));
-        } else if rtype == 5 {
-            return DNSRData::CNAME(parse_name(message, index));
-        } else if rtype == 12 {
-            return DNSRData::PTR(parse_name(message, index));
+    pub fn parse_r_data(
+        rtype: u16,
+        rdlength: u16,
+        message: &[u8],
+        index: &mut usize,
+    ) -> DecodeResult<DNSRData> {
+        let rdata_end = *index + rdlength as usize;
+        DecodeError::check_len("dns", message, rdata_end)?;
+        let rdata = &message[*index..rdata_end];
+
+        let rdata = match rtype {
+            TYPE_A => {
+                let octets: [u8; 4] = rdata.try_into().map_err(|_| DecodeError::BadLength {
+                    layer: "dns A record",
+                    length: rdata.len(),
+                })?;
+                DNSRData::A(Ipv4Addr::from(octets))
+            }
+            TYPE_AAAA => {
+                let octets: [u8; 16] = rdata.try_into().map_err(|_| DecodeError::BadLength {
+                    layer: "dns AAAA record",
+                    length: rdata.len(),
+                })?;
+                DNSRData::AAAA(Ipv6Addr::from(octets))
+            }
+            TYPE_CNAME => DNSRData::CNAME(parse_name(message, index)?),
+            TYPE_PTR => DNSRData::PTR(parse_name(message, index)?),
+            TYPE_NS => DNSRData::NS(parse_name(message, index)?),
+            TYPE_MX => {
+                let preference = read_u16(rdata, 0)?;
+                *index += 2;
+                DNSRData::MX {
+                    preference,
+                    exchange: parse_name(message, index)?,
+                }
+            }
+            TYPE_TXT => {
+                let mut strings = Vec::new();
+                let mut position = 0;
+                while position < rdata.len() {
+                    let length = rdata[position] as usize;
+                    DecodeError::check_len("dns TXT record", rdata, position + 1 + length)?;
+                    strings.push(rdata[position + 1..position + 1 + length].to_vec());
+                    position += 1 + length;
+                }
+                DNSRData::TXT(strings)
+            }
+            TYPE_SOA => {
+                let mname = parse_name(message, index)?;
+                let rname = parse_name(message, index)?;
+                let fields = *index;
+                *index += 20;
+                DNSRData::SOA {
+                    mname,
+                    rname,
+                    serial: read_u32(message, fields)?,
+                    refresh: read_u32(message, fields + 4)?,
+                    retry: read_u32(message, fields + 8)?,
+                    expire: read_u32(message, fields + 12)?,
+                    minimum: read_u32(message, fields + 16)?,
+                }
+            }
+            TYPE_SRV => {
+                let priority = read_u16(rdata, 0)?;
+                let weight = read_u16(rdata, 2)?;
+                let port = read_u16(rdata, 4)?;
+                *index += 6;
+                DNSRData::SRV {
+                    priority,
+                    weight,
+                    port,
+                    target: parse_name(message, index)?,
+                }
+            }
+            TYPE_CAA => {
+                DecodeError::check_len("dns CAA record", rdata, 2)?;
+                let tag_end = 2 + rdata[1] as usize;
+                DecodeError::check_len("dns CAA record", rdata, tag_end)?;
+                DNSRData::CAA {
+                    flags: rdata[0],
+                    tag: String::from_utf8_lossy(&rdata[2..tag_end]).into_owned(),
+                    value: rdata[tag_end..].to_vec(),
+                }
+            }
+            TYPE_SVCB => DNSRData::SVCB(SvcbData::parse(message, index, rdata_end)?),
+            TYPE_HTTPS => DNSRData::HTTPS(SvcbData::parse(message, index, rdata_end)?),
+            TYPE_OPT => {
+                let mut options = Vec::new();
+                let mut position = 0;
+                while position < rdata.len() {
+                    let code = read_u16(rdata, position)?;
+                    let length = read_u16(rdata, position + 2)? as usize;
+                    let start = position + 4;
+                    DecodeError::check_len("dns edns option", rdata, start + length)?;
+                    options.push(EdnsOption::parse(code, &rdata[start..start + length]));
+                    position = start + length;
+                }
+                DNSRData::OPT(options)
+            }
+            _ => DNSRData::Unknown {
+                rtype,
+                data: rdata.to_vec(),
+            },
+        };
+
+        // Names and fixed fields must not run past the end of the record data
+        if *index > rdata_end {
+            return Err(DecodeError::BadLength {
+                layer: "dns rdata",
+                length: rdlength as usize,
+            });
         }
-        return DNSRData::UNIMPLEMENTED();
+
+        Ok(rdata)
     }
 }
 
 impl DnsMessage {
-    pub fn parse(message: &[u8]) -> DnsMessage {
+    /// Returns true if the message is a response rather than a query.
+    pub fn is_response(&self) -> bool {
+        self.header.flags.qr
+    }
+
+    pub fn parse(message: &[u8]) -> DecodeResult<DnsMessage> {
         let mut index = 0;
-        let header = DnsHeader::parse(message, &mut index);
+        let header = DnsHeader::parse(message, &mut index)?;
 
-        let questions: Vec<DnsQuestion> = (0..header.qd_count)
+        let questions = (0..header.qd_count)
             .map(|_| DnsQuestion::parse(message, &mut index))
-            .collect();
-        let answers: Vec<DnsResourceRecord> = (0..header.an_count)
+            .collect::<DecodeResult<Vec<DnsQuestion>>>()?;
+        let answers = (0..header.an_count)
             .map(|_| DnsResourceRecord::parse(message, &mut index))
-            .collect();
-        let authorities: Vec<DnsResourceRecord> = (0..header.ns_count)
+            .collect::<DecodeResult<Vec<DnsResourceRecord>>>()?;
+        let authorities = (0..header.ns_count)
             .map(|_| DnsResourceRecord::parse(message, &mut index))
-            .collect();
-        let additional: Vec<DnsResourceRecord> = (0..header.ar_count)
+            .collect::<DecodeResult<Vec<DnsResourceRecord>>>()?;
+        let additional = (0..header.ar_count)
             .map(|_| DnsResourceRecord::parse(message, &mut index))
-            .collect();
+            .collect::<DecodeResult<Vec<DnsResourceRecord>>>()?;
 
-        DnsMessage {
+        let edns = additional.iter().find_map(|record| match &record.rdata {
+            DNSRData::OPT(options) => Some(Edns {
+                udp_payload_size: record.rclass,
+                extended_rcode: (record.ttl >> 24) as u8,
+                version: (record.ttl >> 16) as u8,
+                dnssec_ok: record.ttl & 0x8000 != 0,
+                options: options.clone(),
+            }),
+            _ => None,
+        });
+
+        Ok(DnsMessage {
+            header,
             questions,
             answers,
             authorities,
             additional,
+            edns,
+        })
+    }
+
+    /// Returns the EDNS client subnet option (RFC 7871), which tells the resolvers a
+    /// query is forwarded to which network it was sent from.
+    pub fn client_subnet(&self) -> Option<&EdnsOption> {
+        self.edns_options()
+            .find(|option| matches!(option, EdnsOption::ClientSubnet { .. }))
+    }
+
+    /// Returns the Extended DNS Error options (RFC 8914), which explain why a resolver
+    /// failed, refused or filtered a query.
+    pub fn extended_errors(&self) -> impl Iterator<Item = &EdnsOption> {
+        self.edns_options()
+            .filter(|option| matches!(option, EdnsOption::ExtendedError { .. }))
+    }
+
+    fn edns_options(&self) -> impl Iterator<Item = &EdnsOption> {
+        self.edns.iter().flat_map(|edns| &edns.options)
+    }
+
+    /// Returns the response code, including the upper bits carried in the EDNS record.
+    pub fn rcode(&self) -> Rcode {
+        let rcode = self.header.flags.rcode;
+        match &self.edns {
+            Some(edns) if edns.extended_rcode != 0 => {
+                Rcode::from((edns.extended_rcode as u16) << 4 | u16::from(rcode))
+            }
+            _ => rcode,
         }
     }
 }
 
 impl DnsHeader {
-    fn parse(message: &[u8], index: &mut usize) -> DnsHeader {
+    fn parse(message: &[u8], index: &mut usize) -> DecodeResult<DnsHeader> {
+        DecodeError::check_len("dns", message, 12)?;
+
         let id = ((message[0] as u16) << 8) | (message[1] as u16);
-        let flags = ((message[2] as u16) << 8) | (message[3] as u16);
+        let flags = DnsFlags::from(((message[2] as u16) << 8) | (message[3] as u16));
         let qd_count = ((message[4] as u16) << 8) | (message[5] as u16);
         let an_count = ((message[6] as u16) << 8) | (message[7] as u16);
         let ns_count = ((message[8] as u16) << 8) | (message[9] as u16);
@@ -154,196 +1079,996 @@ impl DnsHeader {
 
         *index = 12;
 
-        DnsHeader {
+        Ok(DnsHeader {
             id,
             flags,
             qd_count,
             an_count,
             ns_count,
             ar_count,
-        }
+        })
     }
 }
 
 impl DnsQuestion {
-    pub fn parse(message: &[u8], index: &mut usize) -> DnsQuestion {
-        let qname = parse_name(message, index);
-        let qtype = ((message[*index] as u16) << 8) | (message[*index + 1] as u16);
-        let qclass = ((message[*index + 2] as u16) << 8) | (message[*index + 3] as u16);
+    /// Whether an mDNS question asks for the answer to be sent by unicast (the QU bit).
+    pub fn unicast_response(&self) -> bool {
+        self.qclass & MDNS_CLASS_FLAG != 0
+    }
+
+    /// Returns the class without the mDNS unicast-response bit.
+    pub fn class(&self) -> u16 {
+        self.qclass & !MDNS_CLASS_FLAG
+    }
+
+    pub fn parse(message: &[u8], index: &mut usize) -> DecodeResult<DnsQuestion> {
+        let qname = parse_name(message, index)?;
+        let qtype = read_u16(message, *index)?;
+        let qclass = read_u16(message, *index + 2)?;
         *index += 4;
 
-        DnsQuestion {
+        Ok(DnsQuestion {
             qname,
             qtype,
             qclass,
-        }
+        })
     }
 }
 
-fn parse_dns_response(response: &[u8]) -> Option<String> {
-    let message = DnsMessage::parse(response);
-    if message.answers.len() == 0 {
-        return None;
-    }
+/// A domain name returned in a PTR record by a reverse lookup.
+#[derive(Debug)]
+pub struct PtrRecord {
+    pub domain: String,
+    pub ttl: u32,
+}
 
-    if let DNSRData::PTR(ptr) = &message.answers[0].rdata {
-        return Some(ptr.to_string());
-    }
+/// Returns the first PTR record in the response, or `None` if the response is
+/// malformed or has no PTR record.
+fn parse_dns_response(message: DnsMessage) -> Option<PtrRecord> {
+    message
+        .answers
+        .into_iter()
+        .find_map(|answer| match answer.rdata {
+            DNSRData::PTR(domain) => Some(PtrRecord {
+                domain,
+                ttl: answer.ttl,
+            }),
+            _ => None,
+        })
+}
 
-    return None;
+fn read_u16(message: &[u8], index: usize) -> DecodeResult<u16> {
+    DecodeError::check_len("dns", message, index + 2)?;
+    Ok(u16::from_be_bytes([message[index], message[index + 1]]))
 }
 
+fn read_u32(message: &[u8], index: usize) -> DecodeResult<u32> {
+    DecodeError::check_len("dns", message, index + 4)?;
+    Ok(u32::from_be_bytes([
+        message[index],
+        message[index + 1],
+        message[index + 2],
+        message[index + 3],
+    ]))
+}
+
+// A name has at most 127 labels, so following more pointers than that means
+// the compression pointers form a loop
+const MAX_COMPRESSION_POINTERS: usize = 127;
+
 // TODO: replace index with cursor
-fn parse_name(packet: &[u8], index: &mut usize) -> String {
+fn parse_name(packet: &[u8], index: &mut usize) -> DecodeResult<String> {
     let mut name = String::new();
-    while packet[*index] != 0 {
-        if packet[*index] & 0xC0 == 0xC0 {
+    let mut position = *index;
+
+    // Where parsing continues after the name, fixed once the first compression pointer is followed
+    let mut name_end = None;
+    let mut pointers = 0;
+
+    loop {
+        DecodeError::check_len("dns", packet, position + 1)?;
+        let length = packet[position] as usize;
+
+        if length & 0xC0 == 0xC0 {
             // Name compression
-            let offset = ((packet[*index] as usize & 0x3F) << 8) | packet[*index + 1] as usize;
-            *index += 2;
-            if let Some(compressed_name) = parse_compressed_name(packet, offset) {
-                name.push_str(&compressed_name);
+            DecodeError::check_len("dns", packet, position + 2)?;
+            let offset = ((length & 0x3F) << 8) | packet[position + 1] as usize;
+            name_end.get_or_insert(position + 2);
+
+            pointers += 1;
+            if pointers > MAX_COMPRESSION_POINTERS {
+                return Err(DecodeError::CompressionLoop { offset });
             }
-            return name;
+            position = offset;
+        } else if length & 0xC0 != 0 {
+            // The 0x40 and 0x80 label types are reserved
+            return Err(DecodeError::BadLength {
+                layer: "dns label",
+                length,
+            });
+        } else if length == 0 {
+            position += 1;
+            break;
         } else {
-            let length = packet[*index] as usize;
-            *index += 1;
-            if *index + length > packet.len() {
-                panic!("exceeded length")
-            }
+            position += 1;
+            DecodeError::check_len("dns", packet, position + length)?;
             if !name.is_empty() {
                 name.push('.');
             }
-            name.push_str(&String::from_utf8_lossy(&packet[*index..*index + length]));
-            *index += length;
+            // Dots and backslashes within a label are escaped, so that the name splits
+            // back into the same labels when it is encoded
+            for c in String::from_utf8_lossy(&packet[position..position + length]).chars() {
+                if c == '.' || c == '\\' {
+                    name.push('\\');
+                }
+                name.push(c);
+            }
+            position += length;
         }
     }
-    *index += 1;
-    name
+
+    *index = name_end.unwrap_or(position);
+    Ok(name)
 }
 
-fn parse_compressed_name(packet: &[u8], mut index: usize) -> Option<String> {
-    let mut name = String::new();
-    while packet[index] != 0 {
-        if packet[index] & 0xC0 == 0xC0 {
-            let offset = ((packet[index] as usize & 0x3F) << 8) | packet[index + 1] as usize;
-            index = offset;
-        } else {
-            let length = packet[index] as usize;
-            index += 1;
-            if index + length > packet.len() {
-                return None;
-            }
-            if !name.is_empty() {
-                name.push('.');
+/// Splits a name into its labels, where `\\.` and `\\\\` stand for a dot and a
+/// backslash within a label, as [`parse_name`] writes them. The root name is empty
+/// or `.`, and other names may end with the dot of the root label. Any other empty
+/// label is an error, since it cannot be told apart from the end of the name.
+fn split_labels(name: &str) -> DecodeResult<Vec<Vec<u8>>> {
+    let mut labels = Vec::new();
+    if name == "." {
+        return Ok(labels);
+    }
+
+    let mut label = Vec::new();
+    let mut bytes = name.bytes();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'\\' => label.push(bytes.next().unwrap_or(byte)),
+            b'.' if label.is_empty() => {
+                return Err(DecodeError::BadLength {
+                    layer: "dns label",
+                    length: 0,
+                })
             }
-            name.push_str(&String::from_utf8_lossy(&packet[index..index + length]));
-            index += length;
+            b'.' => labels.push(std::mem::take(&mut label)),
+            _ => label.push(byte),
         }
     }
-    Some(name)
+    if !label.is_empty() {
+        labels.push(label);
+    }
+    Ok(labels)
 }
 
-fn build_reverse_packet(addr: IpAddr) -> Vec<u8> {
-    /*
-                                    1  1  1  1  1  1
-      0  1  2  3  4  5  6  7  8  9  0  1  2  3  4  5
-    +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-    |                      ID                       |
-    +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-    |QR|   Opcode  |AA|TC|RD|RA|   Z    |   RCODE   |
-    +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-    |                    QDCOUNT                    |
-    +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-    |                    ANCOUNT                    |
-    +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-    |                    NSCOUNT                    |
-    +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-    |                    ARCOUNT                    |
-    +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-     */
-
-    #[rustfmt::skip]
-    let mut packet = vec![
-        0x12, 0x34, // ID
-        0x01, 0x00, // Standard Query with RA (Recursion Available) flag set
-        0x00, 0x01, // Question count = 1
-        0x00, 0x00, // ANCOUNT
-        0x00, 0x00, // NSCOUNT
-        0x00, 0x00, // ARCOUNT
-    ];
-
-    /*
-                                    1  1  1  1  1  1
-      0  1  2  3  4  5  6  7  8  9  0  1  2  3  4  5
-    +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-    |                                               |
-    /                     QNAME                     /
-    /                                               /
-    +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-    |                     QTYPE                     |
-    +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-    |                     QCLASS                    |
-    +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
-     */
+/// Writes messages in the wire format, compressing repeated names.
+///
+/// Fields that the wire format cannot represent, such as a label longer than 63
+/// bytes, are reported as [`DecodeError::BadLength`] rather than truncated, so that
+/// whatever is encoded reads back as the same message.
+struct Encoder {
+    buffer: Vec<u8>,
 
-    let mut parts = Vec::new();
-    if let IpAddr::V4(addr) = addr {
-        for octet in addr.octets().iter().rev() {
-            parts.push(octet.to_string());
+    // Offsets of the names written so far, and of each of their suffixes, by label
+    names: HashMap<Vec<Vec<u8>>, u16>,
+}
+
+// Compression pointers have 14 bits for the offset
+const MAX_POINTER_OFFSET: usize = 0x3FFF;
+
+// https://datatracker.ietf.org/doc/html/rfc1035#section-2.3.4
+const MAX_LABEL_LENGTH: usize = 63;
+const MAX_NAME_LENGTH: usize = 255;
+const MAX_CHARACTER_STRING_LENGTH: usize = 255;
+
+impl Encoder {
+    fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            names: HashMap::new(),
         }
-    } else if let IpAddr::V6(addr) = addr {
-        for segment in addr.segments().iter().rev() {
-            parts.push(format!("{:x}", segment & 0x000F));
-            parts.push(format!("{:x}", (segment & 0x00F0) >> 4));
-            parts.push(format!("{:x}", (segment & 0x0F00) >> 8));
-            parts.push(format!("{:x}", (segment & 0xF000) >> 12));
+    }
+
+    fn write_u16(&mut self, value: u16) {
+        self.buffer.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.buffer.extend_from_slice(&value.to_be_bytes());
+    }
+
+    /// Writes a length that is stored in 16 bits, such as a section count.
+    fn write_length(&mut self, layer: &'static str, length: usize) -> DecodeResult<()> {
+        let length = u16::try_from(length).map_err(|_| DecodeError::BadLength { layer, length })?;
+        self.write_u16(length);
+        Ok(())
+    }
+
+    /// Writes a name, pointing to an earlier copy of its longest known suffix if
+    /// `compress` is set (RFC 1035 section 4.1.4). Names in the record data of types
+    /// defined after RFC 1035 must not be compressed, see RFC 3597 section 4.
+    fn write_name(&mut self, name: &str, compress: bool) -> DecodeResult<()> {
+        let labels = split_labels(name)?;
+
+        // Each label takes its length byte, and the name ends with the root label
+        let length = labels.iter().map(|label| label.len() + 1).sum::<usize>() + 1;
+        if length > MAX_NAME_LENGTH {
+            return Err(DecodeError::BadLength {
+                layer: "dns name",
+                length,
+            });
         }
+        if let Some(label) = labels.iter().find(|label| label.len() > MAX_LABEL_LENGTH) {
+            return Err(DecodeError::BadLength {
+                layer: "dns label",
+                length: label.len(),
+            });
+        }
+
+        for idx in 0..labels.len() {
+            let suffix = labels[idx..].to_vec();
+            if compress {
+                if let Some(&offset) = self.names.get(&suffix) {
+                    self.write_u16(0xC000 | offset);
+                    return Ok(());
+                }
+            }
+            if self.buffer.len() <= MAX_POINTER_OFFSET {
+                self.names.insert(suffix, self.buffer.len() as u16);
+            }
+
+            self.buffer.push(labels[idx].len() as u8);
+            self.buffer.extend_from_slice(&labels[idx]);
+        }
+        self.buffer.push(0);
+        Ok(())
     }
 
-    for part in parts {
-        packet.push(part.len() as u8);
-        packet.extend(part.as_bytes());
+    /// Writes a list of strings that are each prefixed by their one byte length.
+    fn write_character_strings<'a>(
+        &mut self,
+        strings: impl IntoIterator<Item = &'a [u8]>,
+    ) -> DecodeResult<()> {
+        for string in strings {
+            write_character_string(&mut self.buffer, string)?;
+        }
+        Ok(())
     }
 
-    // ipv4 should query `in-addr.arpa`
-    if addr.is_ipv4() {
-        packet.extend(vec![
-            0x07, b'i', b'n', b'-', b'a', b'd', b'd', b'r', 0x04, b'a', b'r', b'p', b'a',
-        ]);
+    fn write_header(&mut self, message: &DnsMessage) -> DecodeResult<()> {
+        self.write_u16(message.header.id);
+        self.write_u16(u16::from(message.header.flags));
+        self.write_length("dns question count", message.questions.len())?;
+        self.write_length("dns answer count", message.answers.len())?;
+        self.write_length("dns authority count", message.authorities.len())?;
+        self.write_length("dns additional count", message.additional.len())
     }
 
-    // ipv6 should query `ip6.arpa`
-    if addr.is_ipv6() {
-        packet.extend(vec![0x03, b'i', b'p', b'6', 0x04, b'a', b'r', b'p', b'a']);
+    fn write_question(&mut self, question: &DnsQuestion) -> DecodeResult<()> {
+        self.write_name(&question.qname, true)?;
+        self.write_u16(question.qtype);
+        self.write_u16(question.qclass);
+        Ok(())
     }
 
-    #[rustfmt::skip]
-    packet.extend(vec![
-        0x00, // null terminator for QNAME
-        0x00, 0x0C, // QTYPE: PTR
-        0x00, 0x01, // QCLASS: IN
-    ]);
+    fn write_record(&mut self, record: &DnsResourceRecord) -> DecodeResult<()> {
+        self.write_name(&record.name, true)?;
+        self.write_u16(record.rtype);
+        self.write_u16(record.rclass);
+        self.write_u32(record.ttl);
 
-    packet
+        // The length is filled in once the record data has been written
+        let length_index = self.buffer.len();
+        self.write_u16(0);
+        self.write_r_data(&record.rdata)?;
+        let rdlength = self.buffer.len() - length_index - 2;
+        let rdlength = u16::try_from(rdlength).map_err(|_| DecodeError::BadLength {
+            layer: "dns record data",
+            length: rdlength,
+        })?;
+        self.buffer[length_index..length_index + 2].copy_from_slice(&rdlength.to_be_bytes());
+        Ok(())
+    }
+
+    fn write_r_data(&mut self, rdata: &DNSRData) -> DecodeResult<()> {
+        match rdata {
+            DNSRData::A(ipv4) => self.buffer.extend_from_slice(&ipv4.octets()),
+            DNSRData::AAAA(ipv6) => self.buffer.extend_from_slice(&ipv6.octets()),
+            DNSRData::CNAME(name) | DNSRData::PTR(name) | DNSRData::NS(name) => {
+                self.write_name(name, true)?
+            }
+            DNSRData::MX {
+                preference,
+                exchange,
+            } => {
+                self.write_u16(*preference);
+                self.write_name(exchange, true)?;
+            }
+            DNSRData::TXT(strings) => {
+                self.write_character_strings(strings.iter().map(|string| string.as_slice()))?
+            }
+            DNSRData::SOA {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => {
+                self.write_name(mname, true)?;
+                self.write_name(rname, true)?;
+                for value in [serial, refresh, retry, expire, minimum] {
+                    self.write_u32(*value);
+                }
+            }
+            DNSRData::SRV {
+                priority,
+                weight,
+                port,
+                target,
+            } => {
+                self.write_u16(*priority);
+                self.write_u16(*weight);
+                self.write_u16(*port);
+                self.write_name(target, false)?;
+            }
+            DNSRData::CAA { flags, tag, value } => {
+                self.buffer.push(*flags);
+                self.write_character_strings([tag.as_bytes()])?;
+                self.buffer.extend_from_slice(value);
+            }
+            DNSRData::SVCB(svcb) | DNSRData::HTTPS(svcb) => {
+                self.write_u16(svcb.priority);
+                self.write_name(&svcb.target, false)?;
+                for param in &svcb.params {
+                    let (key, value) = param.encode()?;
+                    self.write_u16(key);
+                    self.write_length("dns svc param", value.len())?;
+                    self.buffer.extend_from_slice(&value);
+                }
+            }
+            DNSRData::OPT(options) => {
+                for option in options {
+                    let (code, data) = option.encode();
+                    self.write_u16(code);
+                    self.write_length("dns edns option", data.len())?;
+                    self.buffer.extend_from_slice(&data);
+                }
+            }
+            DNSRData::Unknown { data, .. } => self.buffer.extend_from_slice(data),
+        }
+        Ok(())
+    }
 }
 
-/* Returns the domain name pointed to by addr in the PTR record */
-pub fn reverse_lookup(addr: IpAddr) -> Option<String> {
-    let query_packet = build_reverse_packet(addr);
+/// Appends a string prefixed by its one byte length.
+fn write_character_string(buffer: &mut Vec<u8>, string: &[u8]) -> DecodeResult<()> {
+    if string.len() > MAX_CHARACTER_STRING_LENGTH {
+        return Err(DecodeError::BadLength {
+            layer: "dns character string",
+            length: string.len(),
+        });
+    }
+    buffer.push(string.len() as u8);
+    buffer.extend_from_slice(string);
+    Ok(())
+}
 
-    let socket = UdpSocket::bind("0.0.0.0:0").expect("Unable to bind to local socket");
-    socket
-        .connect(DNS_SERVER)
-        .expect("Unable to connect to DNS server");
-    socket
-        .send(&query_packet)
-        .expect("Unable to send DNS query");
+impl SvcParam {
+    /// Returns the key and the wire format value of the parameter.
+    fn encode(&self) -> DecodeResult<(u16, Vec<u8>)> {
+        let param = match self {
+            SvcParam::Mandatory(keys) => (
+                SVC_PARAM_MANDATORY,
+                keys.iter().flat_map(|key| key.to_be_bytes()).collect(),
+            ),
+            SvcParam::Alpn(protocols) => {
+                let mut value = Vec::new();
+                for protocol in protocols {
+                    write_character_string(&mut value, protocol.as_bytes())?;
+                }
+                (SVC_PARAM_ALPN, value)
+            }
+            SvcParam::NoDefaultAlpn => (SVC_PARAM_NO_DEFAULT_ALPN, Vec::new()),
+            SvcParam::Port(port) => (SVC_PARAM_PORT, port.to_be_bytes().to_vec()),
+            SvcParam::Ipv4Hint(addrs) => (
+                SVC_PARAM_IPV4_HINT,
+                addrs.iter().flat_map(|addr| addr.octets()).collect(),
+            ),
+            SvcParam::Ech(config) => (SVC_PARAM_ECH, config.clone()),
+            SvcParam::Ipv6Hint(addrs) => (
+                SVC_PARAM_IPV6_HINT,
+                addrs.iter().flat_map(|addr| addr.octets()).collect(),
+            ),
+            SvcParam::Unknown { key, value } => (*key, value.clone()),
+        };
+        Ok(param)
+    }
+}
+
+impl EdnsOption {
+    /// Returns the code and the wire format data of the option.
+    fn encode(&self) -> (u16, Vec<u8>) {
+        match self {
+            EdnsOption::ClientSubnet {
+                address,
+                source_prefix,
+                scope_prefix,
+            } => {
+                let (family, octets) = match address {
+                    IpAddr::V4(ipv4) => (1u16, ipv4.octets().to_vec()),
+                    IpAddr::V6(ipv6) => (2u16, ipv6.octets().to_vec()),
+                };
+
+                // Only the bytes covered by the source prefix are sent
+                let length = (*source_prefix as usize).div_ceil(8).min(octets.len());
+                let mut data = family.to_be_bytes().to_vec();
+                data.extend_from_slice(&[*source_prefix, *scope_prefix]);
+                data.extend_from_slice(&octets[..length]);
+                (EDNS_CLIENT_SUBNET, data)
+            }
+            EdnsOption::Cookie { client, server } => {
+                (EDNS_COOKIE, [client.as_slice(), server].concat())
+            }
+            EdnsOption::Padding(length) => (EDNS_PADDING, vec![0; *length]),
+            EdnsOption::ExtendedError {
+                info_code,
+                extra_text,
+            } => (
+                EDNS_EXTENDED_ERROR,
+                [&info_code.to_be_bytes(), extra_text.as_bytes()].concat(),
+            ),
+            EdnsOption::Unknown { code, data } => (*code, data.clone()),
+        }
+    }
+}
+
+impl DnsMessage {
+    /// Returns a query for `qname` with recursion desired, as a stub resolver sends.
+    pub fn query(id: u16, qname: &str, qtype: u16) -> DnsMessage {
+        DnsMessage {
+            header: DnsHeader {
+                id,
+                flags: DnsFlags {
+                    rd: true,
+                    ..DnsFlags::from(0)
+                },
+                qd_count: 1,
+                an_count: 0,
+                ns_count: 0,
+                ar_count: 0,
+            },
+            questions: vec![DnsQuestion {
+                qname: qname.to_string(),
+                qtype,
+                qclass: CLASS_IN,
+            }],
+            answers: Vec::new(),
+            authorities: Vec::new(),
+            additional: Vec::new(),
+            edns: None,
+        }
+    }
 
+    /// Encodes the message in the wire format, which [`DnsMessage::parse`] reads back.
+    ///
+    /// The section counts are taken from the sections rather than the header, and
+    /// each record's length is computed from its data. Returns an error if a field
+    /// does not fit in the wire format, e.g. a label longer than 63 bytes.
+    pub fn encode(&self) -> DecodeResult<Vec<u8>> {
+        let mut encoder = Encoder::new();
+        encoder.write_header(self)?;
+        for question in &self.questions {
+            encoder.write_question(question)?;
+        }
+        for record in self
+            .answers
+            .iter()
+            .chain(&self.authorities)
+            .chain(&self.additional)
+        {
+            encoder.write_record(record)?;
+        }
+        Ok(encoder.buffer)
+    }
+}
+
+/// Returns the name that PTR records for `addr` are stored under, e.g.
+/// `4.3.2.1.in-addr.arpa` for 1.2.3.4.
+fn reverse_name(addr: IpAddr) -> String {
+    let mut parts = Vec::new();
+    match addr {
+        IpAddr::V4(addr) => {
+            for octet in addr.octets().iter().rev() {
+                parts.push(octet.to_string());
+            }
+            parts.push("in-addr.arpa".to_string());
+        }
+        IpAddr::V6(addr) => {
+            // One label per nibble, least significant first
+            for octet in addr.octets().iter().rev() {
+                parts.push(format!("{:x}", octet & 0x0F));
+                parts.push(format!("{:x}", octet >> 4));
+            }
+            parts.push("ip6.arpa".to_string());
+        }
+    }
+    parts.join(".")
+}
+
+/* Returns the domain name pointed to by addr in the PTR record, querying `server` with the
+given transaction id. Returns Ok(None) if the server answered without a PTR record, and an
+error if no answer arrived within `timeout`. */
+pub fn reverse_lookup(
+    addr: IpAddr,
+    server: SocketAddr,
+    id: u16,
+    timeout: Duration,
+) -> io::Result<Option<PtrRecord>> {
+    let query_packet = DnsMessage::query(id, &reverse_name(addr), TYPE_PTR)
+        .encode()
+        .map_err(io::Error::other)?;
+
+    let socket = if server.is_ipv4() {
+        UdpSocket::bind("0.0.0.0:0")?
+    } else {
+        UdpSocket::bind("[::]:0")?
+    };
+    socket.connect(server)?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.send(&query_packet)?;
+
+    // Skip over stray datagrams that do not answer our query, e.g. late responses
+    // to an earlier attempt
     let mut response_packet = [0u8; 512];
-    let size = socket
-        .recv(&mut response_packet)
-        .expect("Unable to receive DNS response");
+    loop {
+        let size = socket.recv(&mut response_packet)?;
+        match DnsMessage::parse(&response_packet[..size]) {
+            Ok(response) if response.is_response() && response.header.id == id => {
+                // The server could not answer, which is not the same as there being no name
+                let rcode = response.rcode();
+                if matches!(rcode, Rcode::ServFail | Rcode::Refused) {
+                    return Err(io::Error::other(format!("{} answered {}", server, rcode)));
+                }
+                return Ok(parse_dns_response(response));
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    parse_dns_response(&response_packet[..size])
+    #[test]
+    fn malformed_edns_options_are_kept_as_unknown() {
+        // A 5 byte cookie and a 1 byte extended error
+        assert_eq!(
+            EdnsOption::parse(EDNS_COOKIE, &[1, 2, 3, 4, 5]),
+            EdnsOption::Unknown {
+                code: EDNS_COOKIE,
+                data: vec![1, 2, 3, 4, 5]
+            }
+        );
+        assert_eq!(
+            EdnsOption::parse(EDNS_EXTENDED_ERROR, &[0]),
+            EdnsOption::Unknown {
+                code: EDNS_EXTENDED_ERROR,
+                data: vec![0]
+            }
+        );
+        assert_eq!(
+            EdnsOption::parse(EDNS_EXTENDED_ERROR, &[0, 15]),
+            EdnsOption::ExtendedError {
+                info_code: 15,
+                extra_text: String::new()
+            }
+        );
+    }
+
+    fn record(name: &str, rtype: u16, rdata: DNSRData) -> DnsResourceRecord {
+        DnsResourceRecord {
+            name: name.to_string(),
+            rtype,
+            rclass: CLASS_IN,
+            ttl: 300,
+            rdata,
+        }
+    }
+
+    fn response(
+        answers: Vec<DnsResourceRecord>,
+        authorities: Vec<DnsResourceRecord>,
+        additional: Vec<DnsResourceRecord>,
+    ) -> DnsMessage {
+        let mut message = DnsMessage::query(0x1234, "www.example.com", TYPE_A);
+        message.header.flags.qr = true;
+        message.header.flags.ra = true;
+        message.header.an_count = answers.len() as u16;
+        message.header.ns_count = authorities.len() as u16;
+        message.header.ar_count = additional.len() as u16;
+        message.answers = answers;
+        message.authorities = authorities;
+        message.additional = additional;
+        message
+    }
+
+    /// Asserts that the message reads back unchanged, and that encoding it again
+    /// gives the same bytes.
+    fn assert_round_trip(message: &DnsMessage) -> Vec<u8> {
+        let encoded = message.encode().unwrap();
+        let parsed = DnsMessage::parse(&encoded).unwrap();
+        assert_eq!(&parsed, message);
+        assert_eq!(parsed.encode().unwrap(), encoded);
+        encoded
+    }
+
+    #[test]
+    fn round_trips_header_flags_and_codes() {
+        let mut message = DnsMessage::query(0xBEEF, "example.com", TYPE_AAAA);
+        message.header.flags = DnsFlags {
+            qr: true,
+            opcode: Opcode::Notify,
+            aa: true,
+            tc: true,
+            rd: true,
+            ra: true,
+            ad: true,
+            cd: true,
+            rcode: Rcode::Refused,
+        };
+
+        let encoded = assert_round_trip(&message);
+        assert_eq!(&encoded[..4], &[0xBE, 0xEF, 0xA7, 0xB5]);
+    }
+
+    #[test]
+    fn round_trips_every_section() {
+        let message = response(
+            vec![
+                record(
+                    "www.example.com",
+                    TYPE_CNAME,
+                    DNSRData::CNAME("cdn.example.com".to_string()),
+                ),
+                record(
+                    "cdn.example.com",
+                    TYPE_A,
+                    DNSRData::A(Ipv4Addr::new(192, 0, 2, 1)),
+                ),
+                record(
+                    "cdn.example.com",
+                    TYPE_AAAA,
+                    DNSRData::AAAA("2001:db8::1".parse().unwrap()),
+                ),
+                record(
+                    "1.2.0.192.in-addr.arpa",
+                    TYPE_PTR,
+                    DNSRData::PTR("cdn.example.com".to_string()),
+                ),
+                record(
+                    "example.com",
+                    TYPE_MX,
+                    DNSRData::MX {
+                        preference: 10,
+                        exchange: "mail.example.com".to_string(),
+                    },
+                ),
+                record(
+                    "example.com",
+                    TYPE_TXT,
+                    DNSRData::TXT(vec![b"v=spf1 -all".to_vec(), Vec::new(), vec![0xFF; 255]]),
+                ),
+                record(
+                    "_sip._tcp.example.com",
+                    TYPE_SRV,
+                    DNSRData::SRV {
+                        priority: 1,
+                        weight: 2,
+                        port: 5060,
+                        target: "sip.example.com".to_string(),
+                    },
+                ),
+                record(
+                    "example.com",
+                    TYPE_CAA,
+                    DNSRData::CAA {
+                        flags: 128,
+                        tag: "issue".to_string(),
+                        value: b"ca.example.net".to_vec(),
+                    },
+                ),
+                record(
+                    "example.com",
+                    99,
+                    DNSRData::Unknown {
+                        rtype: 99,
+                        data: vec![1, 2, 3],
+                    },
+                ),
+            ],
+            vec![
+                record(
+                    "example.com",
+                    TYPE_NS,
+                    DNSRData::NS("ns1.example.com".to_string()),
+                ),
+                record(
+                    "example.com",
+                    TYPE_SOA,
+                    DNSRData::SOA {
+                        mname: "ns1.example.com".to_string(),
+                        rname: "hostmaster.example.com".to_string(),
+                        serial: 2024010101,
+                        refresh: 7200,
+                        retry: 3600,
+                        expire: 1209600,
+                        minimum: 300,
+                    },
+                ),
+            ],
+            vec![record(
+                "ns1.example.com",
+                TYPE_A,
+                DNSRData::A(Ipv4Addr::new(192, 0, 2, 53)),
+            )],
+        );
+
+        assert_round_trip(&message);
+    }
+
+    #[test]
+    fn compresses_repeated_names() {
+        let message = response(
+            vec![
+                record(
+                    "www.example.com",
+                    TYPE_CNAME,
+                    DNSRData::CNAME("cdn.example.com".to_string()),
+                ),
+                record(
+                    "cdn.example.com",
+                    TYPE_A,
+                    DNSRData::A(Ipv4Addr::new(192, 0, 2, 1)),
+                ),
+            ],
+            Vec::new(),
+            Vec::new(),
+        );
+        let encoded = assert_round_trip(&message);
+
+        // The question name starts right after the 12 byte header
+        let question_end = 12 + "www.example.com".len() + 2 + 4;
+        assert_eq!(&encoded[question_end..question_end + 2], &[0xC0, 12]);
+
+        // The CNAME target only spells out `cdn` and points to `example.com`
+        let rdata = question_end + 2 + 10;
+        assert_eq!(&encoded[rdata..rdata + 4], b"\x03cdn");
+        assert_eq!(&encoded[rdata + 4..rdata + 6], &[0xC0, 16]);
+
+        // The owner of the A record points to the CNAME target
+        assert_eq!(
+            &encoded[rdata + 6..rdata + 8],
+            &[0xC0, rdata as u8],
+            "owner name should point to the CNAME target"
+        );
+    }
+
+    #[test]
+    fn does_not_compress_names_in_newer_record_types() {
+        let message = response(
+            vec![record(
+                "_sip._tcp.www.example.com",
+                TYPE_SRV,
+                DNSRData::SRV {
+                    priority: 1,
+                    weight: 2,
+                    port: 5060,
+                    target: "www.example.com".to_string(),
+                },
+            )],
+            Vec::new(),
+            Vec::new(),
+        );
+        let encoded = assert_round_trip(&message);
+
+        let target = [b"\x03www\x07example\x03com\x00".as_slice()].concat();
+        assert!(encoded.ends_with(&target));
+    }
+
+    #[test]
+    fn round_trips_opt_record() {
+        let options = vec![
+            EdnsOption::ClientSubnet {
+                address: IpAddr::V4(Ipv4Addr::new(192, 0, 2, 0)),
+                source_prefix: 24,
+                scope_prefix: 0,
+            },
+            EdnsOption::ClientSubnet {
+                address: "2001:db8::".parse().unwrap(),
+                source_prefix: 56,
+                scope_prefix: 48,
+            },
+            EdnsOption::Cookie {
+                client: vec![1; 8],
+                server: vec![2; 16],
+            },
+            EdnsOption::Padding(12),
+            EdnsOption::ExtendedError {
+                info_code: 15,
+                extra_text: "blocked by policy".to_string(),
+            },
+            EdnsOption::Unknown {
+                code: 65001,
+                data: vec![9, 9],
+            },
+        ];
+        let opt = DnsResourceRecord {
+            name: String::new(),
+            rtype: TYPE_OPT,
+            rclass: 1232,
+            ttl: 0x0100_8000,
+            rdata: DNSRData::OPT(options.clone()),
+        };
+        let mut message = response(Vec::new(), Vec::new(), vec![opt]);
+        message.header.flags.rcode = Rcode::from(0x0B);
+        message.edns = Some(Edns {
+            udp_payload_size: 1232,
+            extended_rcode: 1,
+            version: 0,
+            dnssec_ok: true,
+            options,
+        });
+
+        assert_round_trip(&message);
+
+        // The upper bits of the response code are carried in the OPT record
+        assert_eq!(message.rcode(), Rcode::from(0x1B));
+    }
+
+    #[test]
+    fn round_trips_svcb_and_https_records() {
+        let svcb = SvcbData {
+            priority: 1,
+            target: String::new(),
+            params: vec![
+                SvcParam::Mandatory(vec![SVC_PARAM_ALPN, SVC_PARAM_PORT]),
+                SvcParam::Alpn(vec!["h2".to_string(), "h3".to_string()]),
+                SvcParam::NoDefaultAlpn,
+                SvcParam::Port(8443),
+                SvcParam::Ipv4Hint(vec![
+                    Ipv4Addr::new(192, 0, 2, 1),
+                    Ipv4Addr::new(192, 0, 2, 2),
+                ]),
+                SvcParam::Ech(vec![0xAB; 40]),
+                SvcParam::Ipv6Hint(vec!["2001:db8::1".parse().unwrap()]),
+                SvcParam::Unknown {
+                    key: 65000,
+                    value: b"private".to_vec(),
+                },
+            ],
+        };
+        let alias = SvcbData {
+            priority: 0,
+            target: "svc.example.net".to_string(),
+            params: Vec::new(),
+        };
+        let message = response(
+            vec![
+                record("www.example.com", TYPE_HTTPS, DNSRData::HTTPS(svcb)),
+                record("_8443._foo.example.com", TYPE_SVCB, DNSRData::SVCB(alias)),
+            ],
+            Vec::new(),
+            Vec::new(),
+        );
+
+        assert_round_trip(&message);
+    }
+
+    #[test]
+    fn rejects_rdata_past_the_end_of_the_message() {
+        let message = [0u8; 14];
+        let mut index = 12;
+        assert_eq!(
+            DnsResourceRecord::parse_r_data(TYPE_A, 4, &message, &mut index),
+            Err(DecodeError::Truncated {
+                layer: "dns",
+                needed: 16,
+                available: 14
+            })
+        );
+    }
+
+    #[test]
+    fn round_trips_labels_with_dots_and_backslashes() {
+        let message = response(
+            vec![record(
+                "printer\\.lab\\\\1._ipp._tcp.local",
+                TYPE_PTR,
+                DNSRData::PTR("example.com".to_string()),
+            )],
+            Vec::new(),
+            Vec::new(),
+        );
+        let encoded = assert_round_trip(&message);
+
+        let label = b"\x0dprinter.lab\\1\x04_ipp";
+        assert!(encoded.windows(label.len()).any(|window| window == label));
+    }
+
+    #[test]
+    fn accepts_root_label_only_at_the_end() {
+        for name in ["", ".", "example.com."] {
+            let encoded = DnsMessage::query(1, name, TYPE_NS).encode().unwrap();
+            let parsed = DnsMessage::parse(&encoded).unwrap();
+            assert_eq!(parsed.questions[0].qname, name.trim_end_matches('.'));
+        }
+
+        for name in ["a..b", ".example.com", "example.com..", ".."] {
+            assert_eq!(
+                DnsMessage::query(1, name, TYPE_A).encode(),
+                Err(DecodeError::BadLength {
+                    layer: "dns label",
+                    length: 0
+                }),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_fields_the_wire_format_cannot_represent() {
+        let long_label = format!("{}.example.com", "a".repeat(64));
+        assert_eq!(
+            DnsMessage::query(1, &long_label, TYPE_A).encode(),
+            Err(DecodeError::BadLength {
+                layer: "dns label",
+                length: 64
+            })
+        );
+
+        let long_name = vec!["a".repeat(63); 4].join(".");
+        assert_eq!(
+            DnsMessage::query(1, &long_name, TYPE_A).encode(),
+            Err(DecodeError::BadLength {
+                layer: "dns name",
+                length: 257
+            })
+        );
+
+        let message = response(
+            vec![record(
+                "example.com",
+                TYPE_TXT,
+                DNSRData::TXT(vec![vec![b'a'; 256]]),
+            )],
+            Vec::new(),
+            Vec::new(),
+        );
+        assert_eq!(
+            message.encode(),
+            Err(DecodeError::BadLength {
+                layer: "dns character string",
+                length: 256
+            })
+        );
+    }
+
+    #[test]
+    fn svcb_endpoint_is_target_or_owner_without_prefix() {
+        let mut svcb = SvcbData {
+            priority: 1,
+            target: String::new(),
+            params: Vec::new(),
+        };
+        assert_eq!(svcb.endpoint("example.com"), "example.com");
+        assert_eq!(svcb.endpoint("_8443._https.example.com"), "example.com");
+        assert_eq!(svcb.endpoint("_dns.resolver.arpa"), "resolver.arpa");
+
+        svcb.target = "svc.example.net".to_string();
+        assert_eq!(svcb.endpoint("_8443._https.example.com"), "svc.example.net");
+    }
 }
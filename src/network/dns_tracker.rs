@@ -0,0 +1,380 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use crate::network::dns::{DnsMessage, EdnsOption};
+use crate::network::sniffer::DnsEvent;
+
+// Queries without a response after this long are counted as unanswered. Stub resolvers
+// usually give up on a server after 5 seconds, so this leaves room for slow answers.
+const DEFAULT_QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Identifies a query so that its response and retransmits can be matched to it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct QueryKey {
+    client: SocketAddr,
+    resolver: SocketAddr,
+    id: u16,
+
+    // Lowercased name and type of the first question, if the message has one
+    question: Option<(String, u16)>,
+}
+
+impl QueryKey {
+    fn new(client: SocketAddr, resolver: SocketAddr, message: &DnsMessage) -> Self {
+        Self {
+            client,
+            resolver,
+            id: message.header.id,
+            question: message
+                .questions
+                .first()
+                .map(|question| (question.qname.to_lowercase(), question.qtype)),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PendingQuery {
+    // Capture time of the first copy of the query
+    sent: Duration,
+
+    // Number of identical copies sent after the first one
+    retransmits: u32,
+
+    client_subnet: Option<EdnsOption>,
+}
+
+/// A query matched with its response, or a query or response that could not be matched.
+#[derive(Debug)]
+pub struct DnsTransaction {
+    /// Capture time of the query, or of the response if no query was seen.
+    pub timestamp: Duration,
+    pub client: SocketAddr,
+    pub resolver: SocketAddr,
+    pub qname: String,
+    pub qtype: u16,
+
+    /// Time from the first copy of the query to the response. `None` if either the
+    /// query or the response is missing.
+    pub latency: Option<Duration>,
+    pub retransmits: u32,
+
+    /// EDNS client subnet of the query, or of the response if the query was not
+    /// seen. Set if the client or a forwarder revealed the network the query came from.
+    pub client_subnet: Option<EdnsOption>,
+
+    /// `None` if the query went unanswered.
+    pub response: Option<DnsMessage>,
+
+    /// Set if the query is still waiting for its response.
+    pub pending: bool,
+}
+
+/// Latency and reliability of the queries sent to a resolver or for a domain.
+#[derive(Debug, Clone, Default)]
+pub struct LatencyStats {
+    pub answered: u32,
+    pub unanswered: u32,
+    pub retransmits: u32,
+    pub min: Option<Duration>,
+    pub max: Option<Duration>,
+
+    // Sum of the latencies of the answered queries
+    total: Duration,
+}
+
+impl LatencyStats {
+    /// Returns the mean latency of the answered queries.
+    pub fn average(&self) -> Option<Duration> {
+        (self.answered > 0).then(|| self.total / self.answered)
+    }
+
+    fn add(&mut self, transaction: &DnsTransaction) {
+        self.retransmits += transaction.retransmits;
+        match (transaction.latency, &transaction.response) {
+            (Some(latency), _) => {
+                self.answered += 1;
+                self.total += latency;
+                self.min = Some(self.min.map_or(latency, |min| min.min(latency)));
+                self.max = Some(self.max.map_or(latency, |max| max.max(latency)));
+            }
+            (None, None) => self.unanswered += 1,
+            // Responses to queries sent before the capture started have no latency
+            (None, Some(_)) => (),
+        }
+    }
+}
+
+/// Matches DNS queries with their responses by transaction id, addresses, ports and
+/// question, and keeps latency statistics per resolver and per domain.
+///
+/// A query repeated with the same id, ports and question before it is answered is
+/// counted as a retransmit of the first copy.
+#[derive(Debug)]
+pub struct DnsTracker {
+    pending: HashMap<QueryKey, PendingQuery>,
+    timeout: Duration,
+
+    // Capture time of the most recent event, and when it was recorded. Queries are
+    // expired against the capture time, which is estimated from the wall clock once
+    // events stop arriving.
+    clock: Option<(Duration, Instant)>,
+
+    // Queries that timed out since the last tick, oldest first
+    timed_out: Vec<DnsTransaction>,
+
+    pub resolvers: BTreeMap<IpAddr, LatencyStats>,
+    pub domains: HashMap<String, LatencyStats>,
+}
+
+impl Default for DnsTracker {
+    fn default() -> Self {
+        Self::new(DEFAULT_QUERY_TIMEOUT)
+    }
+}
+
+impl DnsTracker {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            pending: HashMap::new(),
+            timeout,
+            clock: None,
+            timed_out: Vec::new(),
+            resolvers: BTreeMap::new(),
+            domains: HashMap::new(),
+        }
+    }
+
+    /// Returns the number of queries that are still waiting for a response.
+    pub fn num_pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns the queries that are still waiting for a response as transactions,
+    /// oldest first.
+    pub fn pending(&self) -> Vec<DnsTransaction> {
+        let mut transactions = self
+            .pending
+            .iter()
+            .map(|(key, query)| {
+                let (qname, qtype) = key.question.clone().unwrap_or_default();
+                DnsTransaction {
+                    timestamp: query.sent,
+                    client: key.client,
+                    resolver: key.resolver,
+                    qname,
+                    qtype,
+                    latency: None,
+                    retransmits: query.retransmits,
+                    client_subnet: query.client_subnet.clone(),
+                    response: None,
+                    pending: true,
+                }
+            })
+            .collect::<Vec<_>>();
+        transactions.sort_by_key(|transaction| transaction.timestamp);
+        transactions
+    }
+
+    /// Records a query or response, returning the matched transaction for a response.
+    pub fn record(&mut self, event: DnsEvent) -> Option<DnsTransaction> {
+        // Queries that timed out before this event are expired first, so that a
+        // repeated query is not counted as a retransmit of one that was given up on
+        let expired = self.expire(event.timestamp);
+        self.timed_out.extend(expired);
+        self.clock = Some((event.timestamp, Instant::now()));
+
+        if !event.message.is_response() {
+            let key = QueryKey::new(event.src, event.dst, &event.message);
+            self.pending
+                .entry(key)
+                .and_modify(|query| query.retransmits += 1)
+                .or_insert(PendingQuery {
+                    sent: event.timestamp,
+                    retransmits: 0,
+                    client_subnet: event.message.client_subnet().cloned(),
+                });
+            return None;
+        }
+
+        let key = QueryKey::new(event.dst, event.src, &event.message);
+        let query = self.pending.remove(&key);
+        let (qname, qtype) = key.question.unwrap_or_default();
+        let transaction = DnsTransaction {
+            timestamp: query.as_ref().map_or(event.timestamp, |query| query.sent),
+            client: key.client,
+            resolver: key.resolver,
+            qname,
+            qtype,
+            latency: query
+                .as_ref()
+                .map(|query| event.timestamp.saturating_sub(query.sent)),
+            retransmits: query.as_ref().map_or(0, |query| query.retransmits),
+            client_subnet: query
+                .and_then(|query| query.client_subnet)
+                .or_else(|| event.message.client_subnet().cloned()),
+            response: Some(event.message),
+            pending: false,
+        };
+        self.add_stats(&transaction);
+        Some(transaction)
+    }
+
+    /// Expires the queries that timed out by `now`, returning them as unanswered
+    /// transactions. Meant to be called periodically, so that a query without a
+    /// response is reported even if no other DNS traffic follows it.
+    pub fn tick(&mut self, now: Instant) -> Vec<DnsTransaction> {
+        if let Some((timestamp, recorded)) = self.clock {
+            let expired = self.expire(timestamp + now.saturating_duration_since(recorded));
+            self.timed_out.extend(expired);
+        }
+        std::mem::take(&mut self.timed_out)
+    }
+
+    /// Drops the queries sent more than the timeout before `timestamp`, returning them
+    /// as unanswered transactions.
+    fn expire(&mut self, timestamp: Duration) -> Vec<DnsTransaction> {
+        let timeout = self.timeout;
+        let expired = self
+            .pending
+            .iter()
+            .filter(|(_, query)| timestamp.saturating_sub(query.sent) >= timeout)
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+
+        let mut transactions = expired
+            .into_iter()
+            .map(|key| {
+                let query = self.pending.remove(&key).unwrap();
+                let (qname, qtype) = key.question.unwrap_or_default();
+                DnsTransaction {
+                    timestamp: query.sent,
+                    client: key.client,
+                    resolver: key.resolver,
+                    qname,
+                    qtype,
+                    latency: None,
+                    retransmits: query.retransmits,
+                    client_subnet: query.client_subnet,
+                    response: None,
+                    pending: false,
+                }
+            })
+            .collect::<Vec<_>>();
+        transactions.sort_by_key(|transaction| transaction.timestamp);
+
+        for transaction in &transactions {
+            self.add_stats(transaction);
+        }
+        transactions
+    }
+
+    fn add_stats(&mut self, transaction: &DnsTransaction) {
+        self.resolvers
+            .entry(transaction.resolver.ip())
+            .or_default()
+            .add(transaction);
+        if !transaction.qname.is_empty() {
+            self.domains
+                .entry(transaction.qname.clone())
+                .or_default()
+                .add(transaction);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pending.clear();
+        self.timed_out.clear();
+        self.resolvers.clear();
+        self.domains.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::dns::{DnsProtocol, TYPE_A};
+
+    const START: Duration = Duration::from_secs(1_700_000_000);
+
+    fn client() -> SocketAddr {
+        "192.168.1.10:50000".parse().unwrap()
+    }
+
+    fn resolver() -> SocketAddr {
+        "192.168.1.1:53".parse().unwrap()
+    }
+
+    fn query(timestamp: Duration, id: u16, qname: &str) -> DnsEvent {
+        DnsEvent {
+            timestamp,
+            src: client(),
+            dst: resolver(),
+            protocol: DnsProtocol::Dns,
+            message: DnsMessage::query(id, qname, TYPE_A),
+        }
+    }
+
+    fn response(timestamp: Duration, id: u16, qname: &str) -> DnsEvent {
+        let mut message = DnsMessage::query(id, qname, TYPE_A);
+        message.header.flags.qr = true;
+        DnsEvent {
+            timestamp,
+            src: resolver(),
+            dst: client(),
+            protocol: DnsProtocol::Dns,
+            message,
+        }
+    }
+
+    #[test]
+    fn matches_response_to_query() {
+        let mut tracker = DnsTracker::default();
+        assert!(tracker.record(query(START, 1, "example.com")).is_none());
+        assert_eq!(tracker.pending().len(), 1);
+        assert!(tracker.pending()[0].pending);
+
+        let transaction = tracker
+            .record(response(
+                START + Duration::from_millis(20),
+                1,
+                "example.com",
+            ))
+            .unwrap();
+        assert_eq!(transaction.qname, "example.com");
+        assert_eq!(transaction.latency, Some(Duration::from_millis(20)));
+        assert!(!transaction.pending);
+        assert!(tracker.pending().is_empty());
+        assert_eq!(tracker.resolvers[&resolver().ip()].answered, 1);
+    }
+
+    #[test]
+    fn tick_expires_unanswered_query_without_further_traffic() {
+        let mut tracker = DnsTracker::new(Duration::from_secs(10));
+        let recorded = Instant::now();
+        tracker.record(query(START, 1, "example.com"));
+
+        assert!(tracker.tick(recorded + Duration::from_secs(5)).is_empty());
+
+        let expired = tracker.tick(recorded + Duration::from_secs(11));
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].qname, "example.com");
+        assert!(expired[0].response.is_none());
+        assert!(!expired[0].pending);
+        assert!(tracker.pending().is_empty());
+        assert_eq!(tracker.resolvers[&resolver().ip()].unanswered, 1);
+    }
+
+    #[test]
+    fn queries_expired_by_later_traffic_are_returned_on_tick() {
+        let mut tracker = DnsTracker::new(Duration::from_secs(10));
+        tracker.record(query(START, 1, "example.com"));
+        tracker.record(query(START + Duration::from_secs(30), 2, "example.org"));
+
+        let expired = tracker.tick(Instant::now());
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].qname, "example.com");
+        assert_eq!(tracker.pending().len(), 1);
+    }
+}
@@ -0,0 +1,70 @@
+use std::{error, fmt};
+
+/// Result type returned by the packet decoders in [`crate::network`].
+pub type DecodeResult<T> = Result<T, DecodeError>;
+
+/// Errors raised while decoding a malformed or truncated packet, or while encoding a
+/// message with fields that the wire format cannot represent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The data ended before a field of the given protocol layer could be read.
+    Truncated {
+        layer: &'static str,
+        needed: usize,
+        available: usize,
+    },
+
+    /// A length field of the given protocol layer is inconsistent with the data it describes.
+    BadLength { layer: &'static str, length: usize },
+
+    /// The magic number that identifies the given protocol layer is missing.
+    BadMagic { layer: &'static str },
+
+    /// A compressed DNS name points back into itself.
+    CompressionLoop { offset: usize },
+
+    /// The capture uses a link layer that frames cannot be decoded from.
+    UnsupportedLinktype { linktype: i32 },
+}
+
+impl DecodeError {
+    /// Returns an error if `data` is shorter than `needed` bytes.
+    pub fn check_len(layer: &'static str, data: &[u8], needed: usize) -> DecodeResult<()> {
+        if data.len() < needed {
+            return Err(DecodeError::Truncated {
+                layer,
+                needed,
+                available: data.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Truncated {
+                layer,
+                needed,
+                available,
+            } => write!(
+                f,
+                "{}: truncated, needed {} bytes but only {} available",
+                layer, needed, available
+            ),
+            DecodeError::BadLength { layer, length } => {
+                write!(f, "{}: bad length {}", layer, length)
+            }
+            DecodeError::BadMagic { layer } => write!(f, "{}: bad magic number", layer),
+            DecodeError::CompressionLoop { offset } => {
+                write!(f, "dns: compression loop at offset {}", offset)
+            }
+            DecodeError::UnsupportedLinktype { linktype } => {
+                write!(f, "unsupported link type {}", linktype)
+            }
+        }
+    }
+}
+
+impl error::Error for DecodeError {}
@@ -1,3 +1,5 @@
+use crate::network::error::{DecodeError, DecodeResult};
+
 // https://en.wikipedia.org/wiki/Ethernet_frame
 #[allow(dead_code)]
 pub struct EthernetFrame<'a> {
@@ -7,31 +9,32 @@ pub struct EthernetFrame<'a> {
     pub payload: &'a [u8],
 }
 
-pub fn parse_ethernet_frame(data: &[u8]) -> EthernetFrame {
-    let dst: [u8; 6] = data[..6].try_into().expect("error parsing ethernet dst");
-    let src: [u8; 6] = data[6..12].try_into().expect("error parsing ethernet src");
-    let ethertype: [u8; 2] = data[12..14]
-        .try_into()
-        .expect("error parsing ethernet ethertype");
+pub fn parse_ethernet_frame(data: &[u8]) -> DecodeResult<EthernetFrame<'_>> {
+    DecodeError::check_len("ethernet", data, 14)?;
+
+    let dst: [u8; 6] = data[..6].try_into().unwrap();
+    let src: [u8; 6] = data[6..12].try_into().unwrap();
+    let mut ethertype: [u8; 2] = data[12..14].try_into().unwrap();
 
-    // Check if the ethernet frame has the optional 802.1Q tag
+    // Check if the ethernet frame has the optional 802.1Q tag, in which case the
+    // EtherType of the payload follows the tag
     // https://en.wikipedia.org/wiki/Ethernet_frame#Header
-    let payload_start_idx: usize = if ethertype[0] == 0x81 && ethertype[1] == 0x00 {
-        18
-    } else if ethertype[0] == 0x88 && ethertype[1] == 0xa8 {
+    let payload_start_idx: usize = if (ethertype[0] == 0x81 && ethertype[1] == 0x00)
+        || (ethertype[0] == 0x88 && ethertype[1] == 0xa8)
+    {
+        DecodeError::check_len("ethernet", data, 18)?;
+        ethertype = data[16..18].try_into().unwrap();
         18
     } else {
         14
     };
 
-    let payload: &[u8] = data[payload_start_idx..]
-        .try_into()
-        .expect("error parsing ethernet payload");
+    let payload: &[u8] = &data[payload_start_idx..];
 
-    EthernetFrame {
+    Ok(EthernetFrame {
         dst,
         src,
         ethertype,
         payload,
-    }
+    })
 }
@@ -0,0 +1,151 @@
+use std::fmt;
+
+use crate::network::error::{DecodeError, DecodeResult};
+
+pub const HTTP_PORT: u16 = 80;
+
+// Request heads longer than this are treated as malformed, as most servers do
+const MAX_HEAD_LENGTH: usize = 16 * 1024;
+
+// https://www.rfc-editor.org/rfc/rfc9110#section-9 and RFC 5789 for PATCH
+const METHODS: [&str; 9] = [
+    "GET", "HEAD", "POST", "PUT", "DELETE", "CONNECT", "OPTIONS", "TRACE", "PATCH",
+];
+
+/// The request line and selected headers of an HTTP/1.x request.
+// https://www.rfc-editor.org/rfc/rfc9112#section-3
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpRequest {
+    pub method: String,
+    pub target: String,
+    pub version: String,
+
+    pub host: Option<String>,
+    pub user_agent: Option<String>,
+    pub content_type: Option<String>,
+
+    /* How the body that follows the head is delimited (RFC 9112 section 6.3) */
+    pub content_length: Option<usize>,
+    pub chunked: bool,
+
+    // Set when a Content-Length header is not a number, so the end of the body is unknown
+    pub bad_content_length: bool,
+}
+
+impl HttpRequest {
+    /// Returns the host the request is for without its port, or `None` if the Host
+    /// header is missing or empty.
+    pub fn host_name(&self) -> Option<&str> {
+        let host = self.host.as_deref()?;
+
+        // IPv6 literals are enclosed in brackets, which keep the port apart
+        let name = match host.strip_prefix('[') {
+            Some(literal) => literal.split(']').next().unwrap_or_default(),
+            None => host.split(':').next().unwrap_or_default(),
+        };
+        (!name.is_empty()).then_some(name)
+    }
+}
+
+impl fmt::Display for HttpRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.method, self.target, self.version)
+    }
+}
+
+/// Whether `data` starts with a request method followed by a space, or could still
+/// do so once more data arrives.
+pub fn could_be_request(data: &[u8]) -> bool {
+    METHODS.iter().any(|method| {
+        let method = method.as_bytes();
+        if data.len() > method.len() {
+            data.starts_with(method) && data[method.len()] == b' '
+        } else {
+            method.starts_with(data)
+        }
+    })
+}
+
+/// Parses the request head at the start of `data`, returning the request and the
+/// length of the head, or `None` if `data` ends before the head does.
+///
+/// `scanned` is the length of the start of `data` already searched for the end of
+/// the head, which is updated when it is not found so that the next search for it
+/// resumes there.
+pub fn parse_request_head(
+    data: &[u8],
+    scanned: &mut usize,
+) -> DecodeResult<Option<(HttpRequest, usize)>> {
+    // The end of the head may straddle the bytes already searched and the new ones
+    let start = scanned.saturating_sub(3).min(data.len());
+    let Some(end) = data[start..]
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .map(|position| start + position)
+    else {
+        if data.len() > MAX_HEAD_LENGTH {
+            return Err(DecodeError::BadLength {
+                layer: "http head",
+                length: data.len(),
+            });
+        }
+        *scanned = data.len();
+        return Ok(None);
+    };
+
+    let head = String::from_utf8_lossy(&data[..end]);
+    let mut lines = head.split("\r\n");
+
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split(' ');
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(DecodeError::BadMagic {
+            layer: "http request line",
+        });
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Err(DecodeError::BadMagic {
+            layer: "http version",
+        });
+    }
+
+    let mut request = HttpRequest {
+        method: method.to_string(),
+        target: target.to_string(),
+        version: version.to_string(),
+        host: None,
+        user_agent: None,
+        content_type: None,
+        content_length: None,
+        chunked: false,
+        bad_content_length: false,
+    };
+
+    // https://www.rfc-editor.org/rfc/rfc9112#section-5
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+
+        if name.eq_ignore_ascii_case("host") {
+            request.host = Some(value.to_string());
+        } else if name.eq_ignore_ascii_case("user-agent") {
+            request.user_agent = Some(value.to_string());
+        } else if name.eq_ignore_ascii_case("content-type") {
+            request.content_type = Some(value.to_string());
+        } else if name.eq_ignore_ascii_case("content-length") {
+            // The request is still worth reporting, only the rest of the stream is lost
+            match value.parse() {
+                Ok(length) => request.content_length = Some(length),
+                Err(_) => request.bad_content_length = true,
+            }
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            request.chunked = value.to_ascii_lowercase().contains("chunked");
+        }
+    }
+
+    Ok(Some((request, end + 4)))
+}
@@ -0,0 +1,98 @@
+use std::fs;
+
+use pcap::Device;
+
+pub const PROC_NET_ROUTE: &str = "/proc/net/route";
+
+// Route flags from linux/route.h
+const RTF_UP: u32 = 0x0001;
+
+/// Returns the network devices that can be captured on.
+pub fn list_devices() -> Result<Vec<Device>, pcap::Error> {
+    Device::list()
+}
+
+/// Returns every device that is up and has an address. Pseudo-devices such as the
+/// Linux `any` device are skipped so that packets are not counted twice.
+pub fn active_devices() -> Result<Vec<Device>, pcap::Error> {
+    Ok(list_devices()?
+        .into_iter()
+        .filter(|d| d.name != "any" && d.flags.is_up() && !d.addresses.is_empty())
+        .collect())
+}
+
+/// Returns the device with the given name, if it exists.
+pub fn find_device(name: &str) -> Result<Option<Device>, pcap::Error> {
+    Ok(list_devices()?.into_iter().find(|d| d.name == name))
+}
+
+/// Returns the name of the interface that carries the default route. Falls back to
+/// libpcap's default device on systems without `/proc/net/route`.
+pub fn default_interface() -> Option<String> {
+    if let Ok(contents) = fs::read_to_string(PROC_NET_ROUTE) {
+        if let Some(name) = parse_default_route(&contents) {
+            return Some(name);
+        }
+    }
+
+    Device::lookup().ok().flatten().map(|device| device.name)
+}
+
+/// Parses the routing table in the format of `/proc/net/route` and returns the
+/// interface of the default route with the lowest metric.
+///
+/// ```text
+/// Iface   Destination Gateway  Flags RefCnt Use Metric Mask     MTU Window IRTT
+/// wlan0   00000000    0101A8C0 0003  0      0   600    00000000 0   0      0
+/// ```
+pub fn parse_default_route(contents: &str) -> Option<String> {
+    contents
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            if fields.len() < 8 {
+                return None;
+            }
+
+            let destination = u32::from_str_radix(fields[1], 16).ok()?;
+            let flags = u32::from_str_radix(fields[3], 16).ok()?;
+            let metric = fields[6].parse::<u32>().ok()?;
+            let mask = u32::from_str_radix(fields[7], 16).ok()?;
+
+            if destination == 0 && mask == 0 && flags & RTF_UP != 0 {
+                Some((metric, fields[0]))
+            } else {
+                None
+            }
+        })
+        .min_by_key(|(metric, _)| *metric)
+        .map(|(_, name)| name.to_string())
+}
+
+/// Returns a short description of the device's state, e.g. "up running wireless".
+pub fn describe_flags(device: &Device) -> String {
+    let flags = [
+        (device.flags.is_up(), "up"),
+        (device.flags.is_running(), "running"),
+        (device.flags.is_loopback(), "loopback"),
+        (device.flags.is_wireless(), "wireless"),
+    ];
+
+    flags
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Returns the device's addresses as a comma separated list.
+pub fn describe_addresses(device: &Device) -> String {
+    device
+        .addresses
+        .iter()
+        .map(|address| address.addr.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -1,5 +1,41 @@
-use crate::network::dns;
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use crate::network::checksum;
+use crate::network::error::{DecodeError, DecodeResult};
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+// Assigned Internet Protocol Numbers: https://www.iana.org/assignments/protocol-numbers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IpProtocol {
+    Icmp,
+    Tcp,
+    Udp,
+    Icmpv6,
+    Other(u8),
+}
+
+impl From<u8> for IpProtocol {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => IpProtocol::Icmp,
+            6 => IpProtocol::Tcp,
+            17 => IpProtocol::Udp,
+            58 => IpProtocol::Icmpv6,
+            other => IpProtocol::Other(other),
+        }
+    }
+}
+
+impl fmt::Display for IpProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IpProtocol::Icmp => write!(f, "icmp"),
+            IpProtocol::Tcp => write!(f, "tcp"),
+            IpProtocol::Udp => write!(f, "udp"),
+            IpProtocol::Icmpv6 => write!(f, "icmpv6"),
+            IpProtocol::Other(value) => write!(f, "ip/{}", value),
+        }
+    }
+}
 
 // IPv6: https://datatracker.ietf.org/doc/html/rfc2460
 #[allow(dead_code)]
@@ -12,7 +48,9 @@ pub struct Ipv6Packet<'a> {
 
     payload_length: u16, // 16 bits
 
-    next_header: u8, // 8 bits
+    /* Identifies the type of header immediately following the IPv6 header, which
+    may be an extension header rather than the upper-layer protocol */
+    pub next_header: u8, // 8 bits
 
     hop_limit: u8, // 8 bits
 
@@ -20,9 +58,48 @@ pub struct Ipv6Packet<'a> {
 
     pub dst: Ipv6Addr, // 128 bits
 
+    /* Extension headers between the IPv6 header and the upper-layer header, in order */
+    pub extension_headers: Vec<Ipv6ExtensionHeader<'a>>,
+
+    /* The upper-layer protocol found at the end of the extension header chain, or the
+    header following the fragment header if this packet is a fragment */
+    pub protocol: u8,
+
+    /* The upper-layer payload following the extension headers */
     pub payload: &'a [u8],
+
+    // Set if the capture ended before the payload did, e.g. at the snapshot length
+    pub truncated: bool,
 }
 
+// IPv6 extension headers: https://datatracker.ietf.org/doc/html/rfc8200#section-4
+#[derive(Debug)]
+pub enum Ipv6ExtensionHeader<'a> {
+    HopByHopOptions(&'a [u8]),
+    Routing {
+        routing_type: u8,
+        segments_left: u8,
+        data: &'a [u8],
+    },
+    Fragment {
+        fragment_offset: u16,
+        more_fragments: bool,
+        identification: u32,
+    },
+    DestinationOptions(&'a [u8]),
+    // https://datatracker.ietf.org/doc/html/rfc4302#section-2
+    Authentication {
+        spi: u32,
+        sequence: u32,
+    },
+}
+
+pub const IPV6_HOP_BY_HOP: u8 = 0;
+pub const IPV6_ROUTING: u8 = 43;
+pub const IPV6_FRAGMENT: u8 = 44;
+pub const IPV6_AUTHENTICATION: u8 = 51;
+pub const IPV6_DESTINATION_OPTIONS: u8 = 60;
+
 // IPv4: https://datatracker.ietf.org/doc/html/rfc791#section-3.1
 #[allow(dead_code)]
 #[derive(Debug)]
@@ -50,7 +127,7 @@ pub struct Ipv4Packet<'a> {
     /* An identifying value assigned by the sender to aid in assembling the
     fragments of a datagram.
      */
-    identification: u16, // 16 bits
+    pub identification: u16, // 16 bits
 
     /* Various Control Flags.
 
@@ -58,10 +135,11 @@ pub struct Ipv4Packet<'a> {
      Bit 1: (DF) 0 = May Fragment,  1 = Don't Fragment.
      Bit 2: (MF) 0 = Last Fragment, 1 = More Fragments.
     */
-    flags: u8, // 3 bits
+    pub flags: u8, // 3 bits
 
-    /* This field indicates where in the datagram this fragment belongs.  */
-    fragment_offset: u16, // 13 bits
+    /* This field indicates where in the datagram this fragment belongs.
+    The fragment offset is measured in units of 8 octets (64 bits). */
+    pub fragment_offset: u16, // 13 bits
 
     /* This field indicates the maximum time the datagram is allowed to
     remain in the internet system. */
@@ -86,11 +164,18 @@ pub struct Ipv4Packet<'a> {
 
     options: Option<&'a [u8]>, // variable bits, given by IHL
 
+    // The complete header, kept to verify the checksum
+    header: &'a [u8],
+
     pub payload: &'a [u8],
+
+    // Set if the capture ended before the payload did, e.g. at the snapshot length
+    pub truncated: bool,
 }
 
-// TODO: properly handle next_header
-pub fn parse_ipv6_packet(data: &[u8]) -> Ipv6Packet {
+pub fn parse_ipv6_packet(data: &[u8]) -> DecodeResult<Ipv6Packet<'_>> {
+    DecodeError::check_len("ipv6", data, 40)?;
+
     let version = (data[0] >> 4) & 0x0F;
     let traffic_class = ((data[0] & 0x0F) << 4) | (data[1] >> 4);
     let flow_label = ((data[1] as u32 & 0x0F) << 16) | (data[2] as u32) << 8 | (data[3] as u32);
@@ -120,9 +205,12 @@ pub fn parse_ipv6_packet(data: &[u8]) -> Ipv6Packet {
         u16::from_be_bytes([data[38], data[39]]),
     );
 
-    let payload = &data[40..];
+    // Drop any link layer padding after the payload
+    let payload_end = (40 + payload_length as usize).min(data.len());
+    let (extension_headers, protocol, payload) =
+        parse_ipv6_extension_headers(next_header, &data[40..payload_end])?;
 
-    Ipv6Packet {
+    Ok(Ipv6Packet {
         version,
         traffic_class,
         flow_label,
@@ -131,22 +219,153 @@ pub fn parse_ipv6_packet(data: &[u8]) -> Ipv6Packet {
         hop_limit,
         src,
         dst,
+        extension_headers,
+        protocol,
         payload,
+        truncated: data.len() < 40 + payload_length as usize,
+    })
+}
+
+/// Walks the extension header chain starting at `data`, returning the headers, the
+/// upper-layer protocol and its payload. Walking stops at the first header that is
+/// not an extension header, and after a fragment header since the rest of the chain
+/// is part of the fragmented payload. Once the fragments are reassembled, the walk
+/// continues from the fragment header's next header.
+pub fn parse_ipv6_extension_headers(
+    mut next_header: u8,
+    data: &[u8],
+) -> DecodeResult<(Vec<Ipv6ExtensionHeader<'_>>, u8, &[u8])> {
+    let mut headers = Vec::new();
+    let mut index = 0;
+
+    loop {
+        let header = &data[index..];
+        let (extension_header, length) = match next_header {
+            IPV6_HOP_BY_HOP | IPV6_ROUTING | IPV6_DESTINATION_OPTIONS => {
+                DecodeError::check_len("ipv6 extension header", header, 8)?;
+                let length = (header[1] as usize + 1) * 8;
+                DecodeError::check_len("ipv6 extension header", header, length)?;
+
+                let extension_header = match next_header {
+                    IPV6_HOP_BY_HOP => Ipv6ExtensionHeader::HopByHopOptions(&header[2..length]),
+                    IPV6_ROUTING => Ipv6ExtensionHeader::Routing {
+                        routing_type: header[2],
+                        segments_left: header[3],
+                        data: &header[4..length],
+                    },
+                    _ => Ipv6ExtensionHeader::DestinationOptions(&header[2..length]),
+                };
+                (extension_header, length)
+            }
+            IPV6_FRAGMENT => {
+                DecodeError::check_len("ipv6 fragment header", header, 8)?;
+                let offset_and_flags = u16::from_be_bytes([header[2], header[3]]);
+                (
+                    Ipv6ExtensionHeader::Fragment {
+                        fragment_offset: offset_and_flags >> 3,
+                        more_fragments: offset_and_flags & 0x1 == 1,
+                        identification: u32::from_be_bytes([
+                            header[4], header[5], header[6], header[7],
+                        ]),
+                    },
+                    8,
+                )
+            }
+            IPV6_AUTHENTICATION => {
+                DecodeError::check_len("ipv6 authentication header", header, 12)?;
+                // The payload length of the authentication header is in 32-bit words, minus 2
+                let length = (header[1] as usize + 2) * 4;
+                DecodeError::check_len("ipv6 authentication header", header, length)?;
+                (
+                    Ipv6ExtensionHeader::Authentication {
+                        spi: u32::from_be_bytes([header[4], header[5], header[6], header[7]]),
+                        sequence: u32::from_be_bytes([
+                            header[8], header[9], header[10], header[11],
+                        ]),
+                    },
+                    length,
+                )
+            }
+            protocol => return Ok((headers, protocol, header)),
+        };
+
+        next_header = header[0];
+        index += length;
+
+        let is_fragment = matches!(extension_header, Ipv6ExtensionHeader::Fragment { .. });
+        headers.push(extension_header);
+        if is_fragment {
+            return Ok((headers, next_header, &data[index..]));
+        }
+    }
+}
+
+impl Ipv6Packet<'_> {
+    /// Returns the fragment offset in bytes, the more fragments flag and the
+    /// identification if this packet is a fragment.
+    pub fn fragment(&self) -> Option<(usize, bool, u32)> {
+        self.extension_headers
+            .iter()
+            .find_map(|header| match header {
+                Ipv6ExtensionHeader::Fragment {
+                    fragment_offset,
+                    more_fragments,
+                    identification,
+                } => Some((
+                    *fragment_offset as usize * 8,
+                    *more_fragments,
+                    *identification,
+                )),
+                _ => None,
+            })
     }
 }
 
-pub fn parse_ipv4_packet(data: &[u8]) -> Ipv4Packet {
+impl Ipv4Packet<'_> {
+    /// Returns true if the More Fragments flag is set.
+    pub fn more_fragments(&self) -> bool {
+        self.flags & 0b001 != 0
+    }
+
+    /// Returns true if this packet is a fragment of a larger datagram.
+    pub fn is_fragment(&self) -> bool {
+        self.more_fragments() || self.fragment_offset != 0
+    }
+
+    /// Returns true if the header checksum is correct.
+    pub fn verify_checksum(&self) -> bool {
+        checksum::verify_ipv4_header(self.header)
+    }
+}
+
+pub fn parse_ipv4_packet(data: &[u8]) -> DecodeResult<Ipv4Packet<'_>> {
+    DecodeError::check_len("ipv4", data, 20)?;
+
     let version = data[0] >> 0x4;
     let ihl = data[0] & 0x0F;
-    let ihl_in_bytes = (ihl * 4) as usize;
+    let ihl_in_bytes = ihl as usize * 4;
+    if ihl_in_bytes < 20 {
+        return Err(DecodeError::BadLength {
+            layer: "ipv4",
+            length: ihl_in_bytes,
+        });
+    }
+    DecodeError::check_len("ipv4", data, ihl_in_bytes)?;
 
     let service_type = data[1];
 
     let length = u16::from_be_bytes([data[2], data[3]]);
+    if (length as usize) < ihl_in_bytes {
+        return Err(DecodeError::BadLength {
+            layer: "ipv4",
+            length: length as usize,
+        });
+    }
+
     let identification = u16::from_be_bytes([data[4], data[5]]);
 
     let flags = data[6] >> 0x5;
-    let fragment_offset = u16::from_be_bytes([data[6] & 0b111, data[7]]);
+    let fragment_offset = u16::from_be_bytes([data[6] & 0x1F, data[7]]);
 
     let ttl = data[8];
     let protocol = data[9];
@@ -161,9 +380,10 @@ pub fn parse_ipv4_packet(data: &[u8]) -> Ipv4Packet {
         None
     };
 
-    let payload = &data[ihl_in_bytes..];
+    // Drop any link layer padding after the payload
+    let payload = &data[ihl_in_bytes..(length as usize).min(data.len())];
 
-    Ipv4Packet {
+    Ok(Ipv4Packet {
         version,
         ihl,
         service_type,
@@ -177,10 +397,111 @@ pub fn parse_ipv4_packet(data: &[u8]) -> Ipv4Packet {
         src,
         dst,
         options,
+        header: &data[..ihl_in_bytes],
         payload,
-    }
+        truncated: data.len() < length as usize,
+    })
 }
 
-pub fn translate_ip(ip: IpAddr) -> String {
-    dns::reverse_lookup(ip).unwrap_or(String::from("-"))
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds an IPv6 packet whose payload starts with the header given by `next_header`.
+    fn ipv6_packet(next_header: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x60, 0, 0, 0];
+        packet.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&[next_header, 64]);
+        packet.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        packet.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    #[test]
+    fn walks_extension_header_chain_to_fragment_header() {
+        let payload = [
+            // Hop-by-Hop options with a PadN option filling the 8 bytes
+            &[IPV6_ROUTING, 0, 1, 4, 0, 0, 0, 0][..],
+            // A segment routing header with one segment left and a 16 byte address
+            &[IPV6_FRAGMENT, 2, 4, 1, 0, 0, 0, 0],
+            &[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+            // The second fragment, at offset 1448, of datagram 0x12345678
+            &[6, 0, 0x05, 0xa9, 0x12, 0x34, 0x56, 0x78],
+            b"tcp payload",
+        ]
+        .concat();
+        let packet = ipv6_packet(IPV6_HOP_BY_HOP, &payload);
+        let packet = parse_ipv6_packet(&packet).unwrap();
+
+        assert_eq!(packet.extension_headers.len(), 3);
+        assert!(matches!(
+            packet.extension_headers[0],
+            Ipv6ExtensionHeader::HopByHopOptions(&[1, 4, 0, 0, 0, 0])
+        ));
+        assert!(matches!(
+            packet.extension_headers[1],
+            Ipv6ExtensionHeader::Routing {
+                routing_type: 4,
+                segments_left: 1,
+                data,
+            } if data.len() == 20
+        ));
+        assert!(matches!(
+            packet.extension_headers[2],
+            Ipv6ExtensionHeader::Fragment {
+                fragment_offset: 181,
+                more_fragments: true,
+                identification: 0x12345678,
+            }
+        ));
+        assert_eq!(packet.protocol, 6);
+        assert_eq!(packet.payload, b"tcp payload");
+        assert_eq!(packet.fragment(), Some((1448, true, 0x12345678)));
+        assert!(!packet.truncated);
+    }
+
+    #[test]
+    fn rejects_truncated_extension_header() {
+        // A destination options header that claims 16 bytes but only has 8
+        let payload = [IPV6_ROUTING, 1, 1, 4, 0, 0, 0, 0];
+        let packet = ipv6_packet(IPV6_DESTINATION_OPTIONS, &payload);
+        assert!(matches!(
+            parse_ipv6_packet(&packet),
+            Err(DecodeError::Truncated {
+                layer: "ipv6 extension header",
+                needed: 16,
+                available: 8,
+            })
+        ));
+
+        let packet = ipv6_packet(IPV6_FRAGMENT, &[6, 0, 0, 1]);
+        assert!(matches!(
+            parse_ipv6_packet(&packet),
+            Err(DecodeError::Truncated {
+                layer: "ipv6 fragment header",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn stops_at_unknown_next_header() {
+        // An experimental protocol number
+        let packet = ipv6_packet(253, b"data");
+        let packet = parse_ipv6_packet(&packet).unwrap();
+        assert!(packet.extension_headers.is_empty());
+        assert_eq!(packet.protocol, 253);
+        assert_eq!(packet.payload, b"data");
+        assert_eq!(packet.fragment(), None);
+
+        // No Next Header after a Hop-by-Hop header, with data that must be ignored
+        let payload = [&[59, 0, 1, 4, 0, 0, 0, 0][..], b"ignored"].concat();
+        let packet = ipv6_packet(IPV6_HOP_BY_HOP, &payload);
+        let packet = parse_ipv6_packet(&packet).unwrap();
+        assert_eq!(packet.extension_headers.len(), 1);
+        assert_eq!(packet.protocol, 59);
+        assert_eq!(IpProtocol::from(packet.protocol), IpProtocol::Other(59));
+        assert_eq!(packet.payload, b"ignored");
+    }
 }
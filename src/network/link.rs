@@ -0,0 +1,197 @@
+use pcap::Linktype;
+
+use crate::network::error::{DecodeError, DecodeResult};
+use crate::network::ethernet;
+
+// https://en.wikipedia.org/wiki/EtherType
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const ETHERTYPE_IPV6: u16 = 0x86dd;
+
+// DLT_RAW is 12 on most platforms and 14 on OpenBSD, while capture files use LINKTYPE_RAW (101)
+const DLT_RAW: Linktype = Linktype(12);
+const DLT_RAW_OPENBSD: Linktype = Linktype(14);
+
+// Address family values used by the BSD loopback header. AF_INET6 differs between systems.
+const AF_INET: u32 = 2;
+const AF_INET6_LINUX: u32 = 10;
+const AF_INET6_FREEBSD: u32 = 28;
+const AF_INET6_DARWIN: u32 = 30;
+const AF_INET6_BSD: u32 = 24;
+
+/// The network layer payload of a captured frame, independent of the link type it
+/// was captured with.
+#[derive(Debug)]
+pub struct LinkFrame<'a> {
+    // EtherType of the payload, e.g. ETHERTYPE_IPV4
+    pub ethertype: u16,
+    pub payload: &'a [u8],
+}
+
+/// Strips the link layer header from a frame captured with the given link type.
+///
+/// Supports Ethernet, Linux cooked capture (used by the `any` device), BSD loopback
+/// and the raw IP link types used by tunnel interfaces such as `tun0` and `wg0`.
+pub fn parse_link_frame(linktype: Linktype, data: &[u8]) -> DecodeResult<LinkFrame<'_>> {
+    match linktype {
+        Linktype::ETHERNET => {
+            let frame = ethernet::parse_ethernet_frame(data)?;
+            Ok(LinkFrame {
+                ethertype: u16::from_be_bytes(frame.ethertype),
+                payload: frame.payload,
+            })
+        }
+        Linktype::LINUX_SLL => parse_linux_sll(data),
+        Linktype::LINUX_SLL2 => parse_linux_sll2(data),
+        Linktype::NULL | Linktype::LOOP => parse_loopback(data),
+        Linktype::RAW | DLT_RAW | DLT_RAW_OPENBSD => parse_raw_ip(data),
+        Linktype::IPV4 => Ok(LinkFrame {
+            ethertype: ETHERTYPE_IPV4,
+            payload: data,
+        }),
+        Linktype::IPV6 => Ok(LinkFrame {
+            ethertype: ETHERTYPE_IPV6,
+            payload: data,
+        }),
+        Linktype(other) => Err(DecodeError::UnsupportedLinktype { linktype: other }),
+    }
+}
+
+// https://www.tcpdump.org/linktypes/LINKTYPE_LINUX_SLL.html
+fn parse_linux_sll(data: &[u8]) -> DecodeResult<LinkFrame<'_>> {
+    DecodeError::check_len("linux sll", data, 16)?;
+
+    Ok(LinkFrame {
+        ethertype: u16::from_be_bytes([data[14], data[15]]),
+        payload: &data[16..],
+    })
+}
+
+// https://www.tcpdump.org/linktypes/LINKTYPE_LINUX_SLL2.html
+fn parse_linux_sll2(data: &[u8]) -> DecodeResult<LinkFrame<'_>> {
+    DecodeError::check_len("linux sll2", data, 20)?;
+
+    Ok(LinkFrame {
+        ethertype: u16::from_be_bytes([data[0], data[1]]),
+        payload: &data[20..],
+    })
+}
+
+// https://www.tcpdump.org/linktypes/LINKTYPE_NULL.html
+fn parse_loopback(data: &[u8]) -> DecodeResult<LinkFrame<'_>> {
+    DecodeError::check_len("loopback", data, 4)?;
+
+    // DLT_NULL stores the family in the byte order of the capturing host and DLT_LOOP
+    // in network byte order. Families are small, so the order that gives a value that
+    // fits in the low bytes is the right one.
+    let header = [data[0], data[1], data[2], data[3]];
+    let family = match u32::from_be_bytes(header) {
+        family if family <= 0xFFFF => family,
+        _ => u32::from_le_bytes(header),
+    };
+
+    let ethertype = match family {
+        AF_INET => ETHERTYPE_IPV4,
+        AF_INET6_LINUX | AF_INET6_FREEBSD | AF_INET6_DARWIN | AF_INET6_BSD => ETHERTYPE_IPV6,
+        _ => 0,
+    };
+
+    Ok(LinkFrame {
+        ethertype,
+        payload: &data[4..],
+    })
+}
+
+// https://www.tcpdump.org/linktypes/LINKTYPE_RAW.html
+fn parse_raw_ip(data: &[u8]) -> DecodeResult<LinkFrame<'_>> {
+    DecodeError::check_len("raw ip", data, 1)?;
+
+    let ethertype = match data[0] >> 4 {
+        4 => ETHERTYPE_IPV4,
+        6 => ETHERTYPE_IPV6,
+        _ => 0,
+    };
+
+    Ok(LinkFrame {
+        ethertype,
+        payload: data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Start of an IPv4 and an IPv6 header, enough to be told apart
+    const IPV4: [u8; 4] = [0x45, 0, 0, 20];
+    const IPV6: [u8; 4] = [0x60, 0, 0, 0];
+
+    fn parse(linktype: Linktype, header: &[u8], payload: &[u8]) -> (u16, Vec<u8>) {
+        let frame = [header, payload].concat();
+        let frame = parse_link_frame(linktype, &frame).unwrap();
+        (frame.ethertype, frame.payload.to_vec())
+    }
+
+    #[test]
+    fn parses_linux_cooked_capture() {
+        let mut header = [0u8; 16];
+        header[1] = 4; // Sent by us
+        header[14..].copy_from_slice(&ETHERTYPE_IPV6.to_be_bytes());
+
+        assert_eq!(
+            parse(Linktype::LINUX_SLL, &header, &IPV6),
+            (ETHERTYPE_IPV6, IPV6.to_vec())
+        );
+        assert!(parse_link_frame(Linktype::LINUX_SLL, &header[..15]).is_err());
+    }
+
+    #[test]
+    fn parses_linux_cooked_capture_v2() {
+        let mut header = [0u8; 20];
+        header[..2].copy_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        header[4..8].copy_from_slice(&3u32.to_be_bytes()); // Interface index
+
+        assert_eq!(
+            parse(Linktype::LINUX_SLL2, &header, &IPV4),
+            (ETHERTYPE_IPV4, IPV4.to_vec())
+        );
+        assert!(parse_link_frame(Linktype::LINUX_SLL2, &header[..19]).is_err());
+    }
+
+    #[test]
+    fn parses_loopback_in_host_byte_order() {
+        assert_eq!(
+            parse(Linktype::NULL, &AF_INET.to_le_bytes(), &IPV4),
+            (ETHERTYPE_IPV4, IPV4.to_vec())
+        );
+        assert_eq!(
+            parse(Linktype::NULL, &AF_INET6_DARWIN.to_le_bytes(), &IPV6),
+            (ETHERTYPE_IPV6, IPV6.to_vec())
+        );
+    }
+
+    #[test]
+    fn parses_loopback_in_network_byte_order() {
+        assert_eq!(
+            parse(Linktype::LOOP, &AF_INET.to_be_bytes(), &IPV4),
+            (ETHERTYPE_IPV4, IPV4.to_vec())
+        );
+        assert_eq!(
+            parse(Linktype::NULL, &AF_INET6_FREEBSD.to_be_bytes(), &IPV6),
+            (ETHERTYPE_IPV6, IPV6.to_vec())
+        );
+        assert_eq!(parse(Linktype::LOOP, &7u32.to_be_bytes(), &IPV4).0, 0);
+    }
+
+    #[test]
+    fn parses_raw_ip_by_version() {
+        for linktype in [Linktype::RAW, DLT_RAW, DLT_RAW_OPENBSD] {
+            assert_eq!(parse(linktype, &[], &IPV4), (ETHERTYPE_IPV4, IPV4.to_vec()));
+            assert_eq!(parse(linktype, &[], &IPV6), (ETHERTYPE_IPV6, IPV6.to_vec()));
+        }
+        assert_eq!(
+            parse(Linktype::IPV6, &[], &IPV6),
+            (ETHERTYPE_IPV6, IPV6.to_vec())
+        );
+        assert!(parse_link_frame(Linktype::RAW, &[]).is_err());
+    }
+}
@@ -1,5 +1,20 @@
+pub mod checksum;
+pub mod crypto;
+pub mod decoder;
+pub mod dhcp;
 pub mod dns;
+pub mod dns_tracker;
+pub mod error;
 pub mod ethernet;
+pub mod http;
+pub mod interface;
 pub mod ip;
+pub mod link;
+pub mod quic;
+pub mod reassembly;
+pub mod resolver;
 pub mod sniffer;
+pub mod stream;
+pub mod tcp;
+pub mod tls;
 pub mod udp;
@@ -0,0 +1,642 @@
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use crate::network::crypto::{self, Aes128, Aes128Gcm};
+use crate::network::error::{DecodeError, DecodeResult};
+use crate::network::tls::{self, ClientHello};
+
+pub const QUIC_PORT: u16 = 443;
+
+// https://datatracker.ietf.org/doc/html/rfc9000#section-15
+const QUIC_V1: u32 = 0x00000001;
+const QUIC_V2: u32 = 0x6b3343cf; // RFC 9369
+
+// https://datatracker.ietf.org/doc/html/rfc9001#section-5.2
+const INITIAL_SALT_V1: [u8; 20] = [
+    0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3, 0x4d, 0x17, 0x9a, 0xe6, 0xa4, 0xc8, 0x0c, 0xad,
+    0xcc, 0xbb, 0x7f, 0x0a,
+];
+
+// https://datatracker.ietf.org/doc/html/rfc9369#section-3.3.1
+const INITIAL_SALT_V2: [u8; 20] = [
+    0x0d, 0xed, 0xe3, 0xde, 0xf7, 0x00, 0xa6, 0xdb, 0x81, 0x93, 0x81, 0xbe, 0x6e, 0x26, 0x9d, 0xcb,
+    0xf9, 0xbd, 0x2e, 0xd9,
+];
+
+// Connection IDs are at most 20 bytes long in QUIC v1 and v2
+const MAX_CONNECTION_ID_LENGTH: usize = 20;
+
+// Header protection samples 16 bytes starting 4 bytes after the packet number
+const SAMPLE_OFFSET: usize = 4;
+const SAMPLE_LENGTH: usize = 16;
+
+// Frame types allowed in Initial packets: https://datatracker.ietf.org/doc/html/rfc9000#section-12.4
+const FRAME_PADDING: u64 = 0x00;
+const FRAME_PING: u64 = 0x01;
+const FRAME_ACK: u64 = 0x02;
+const FRAME_ACK_ECN: u64 = 0x03;
+const FRAME_CRYPTO: u64 = 0x06;
+const FRAME_CONNECTION_CLOSE: u64 = 0x1c;
+
+// Connections whose ClientHello is still incomplete after this long are dropped
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Upper bound on the CRYPTO data buffered for a single connection
+const DEFAULT_BUFFER_LIMIT: usize = 64 * 1024;
+
+// Upper bound on the connections tracked at once, the least recently seen one is
+// dropped to make room for a new one
+const DEFAULT_MAX_CONNECTIONS: usize = 1024;
+
+// How often timed out connections are looked for, in capture time
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The keys that protect the Initial packets sent by a client, which are derived
+/// from the destination connection ID of its first packet (RFC 9001 section 5.2).
+struct InitialKeys {
+    aead: Aes128Gcm,
+    iv: [u8; 12],
+    header_protection: Aes128,
+}
+
+impl InitialKeys {
+    fn derive(version: u32, destination_id: &[u8]) -> Self {
+        // QUIC v2 uses its own salt and labels so that v1 middleboxes cannot read it
+        let (salt, labels) = if version == QUIC_V2 {
+            (INITIAL_SALT_V2, ["quicv2 key", "quicv2 iv", "quicv2 hp"])
+        } else {
+            (INITIAL_SALT_V1, ["quic key", "quic iv", "quic hp"])
+        };
+
+        let initial_secret = crypto::hkdf_extract(&salt, destination_id);
+        let client_secret = crypto::hkdf_expand_label(&initial_secret, "client in", &[], 32);
+        let key = crypto::hkdf_expand_label(&client_secret, labels[0], &[], 16);
+        let iv = crypto::hkdf_expand_label(&client_secret, labels[1], &[], 12);
+        let header_protection = crypto::hkdf_expand_label(&client_secret, labels[2], &[], 16);
+
+        Self {
+            aead: Aes128Gcm::new(&key.try_into().unwrap()),
+            iv: iv.try_into().unwrap(),
+            header_protection: Aes128::new(&header_protection.try_into().unwrap()),
+        }
+    }
+}
+
+/// Identifies the Initial packets that a client sends to start a connection.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ConnectionKey {
+    src: SocketAddr,
+    dst: SocketAddr,
+    destination_id: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct CryptoStream {
+    // CRYPTO frame data keyed by offset into the stream
+    fragments: BTreeMap<u64, Vec<u8>>,
+    size: usize,
+
+    // Set once the ClientHello has been decoded, so that retransmissions are ignored
+    done: bool,
+
+    last_seen: Duration,
+}
+
+impl CryptoStream {
+    /// Returns the data at the start of the stream up to the first gap. Overlapping
+    /// data is taken from the fragment with the lowest offset.
+    fn contiguous(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        for (&offset, fragment) in &self.fragments {
+            let offset = offset as usize;
+            if offset > data.len() {
+                break;
+            }
+            if offset + fragment.len() > data.len() {
+                data.extend_from_slice(&fragment[data.len() - offset..]);
+            }
+        }
+        data
+    }
+}
+
+/// Decrypts the Initial packets that QUIC clients send and reassembles the
+/// ClientHello carried in their CRYPTO frames, which may span several packets.
+///
+/// Only the client's side is decoded: the server's Initial packets are protected
+/// with keys of their own and carry nothing that names the server.
+#[derive(Debug)]
+pub struct InitialDecoder {
+    streams: HashMap<ConnectionKey, CryptoStream>,
+    timeout: Duration,
+    buffer_limit: usize,
+    max_connections: usize,
+    last_expiry: Duration,
+}
+
+impl Default for InitialDecoder {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_TIMEOUT,
+            DEFAULT_BUFFER_LIMIT,
+            DEFAULT_MAX_CONNECTIONS,
+        )
+    }
+}
+
+impl InitialDecoder {
+    pub fn new(timeout: Duration, buffer_limit: usize, max_connections: usize) -> Self {
+        Self {
+            streams: HashMap::new(),
+            timeout,
+            buffer_limit,
+            max_connections,
+            last_expiry: Duration::ZERO,
+        }
+    }
+
+    /// Adds a UDP datagram sent from `src` to `dst` at `timestamp`, returning the
+    /// ClientHello once the Initial packets seen so far hold all of it.
+    ///
+    /// Datagrams that carry no Initial packet, such as the short header packets
+    /// of an established connection, and packets that fail to decrypt are ignored.
+    pub fn add(
+        &mut self,
+        src: SocketAddr,
+        dst: SocketAddr,
+        datagram: &[u8],
+        timestamp: Duration,
+    ) -> DecodeResult<Option<ClientHello>> {
+        if timestamp.saturating_sub(self.last_expiry) >= EXPIRY_INTERVAL {
+            self.expire(timestamp);
+        }
+
+        // A datagram may hold several coalesced packets, each with a long header
+        // giving its length, followed by at most one short header packet
+        let mut index = 0;
+        while index < datagram.len() && datagram[index] & 0x80 != 0 {
+            let packet = &datagram[index..];
+            let Some(header) = parse_long_header(packet)? else {
+                break;
+            };
+            index += header.packet_length;
+
+            if !header.is_initial {
+                continue;
+            }
+
+            let key = ConnectionKey {
+                src,
+                dst,
+                destination_id: header.destination_id.to_vec(),
+            };
+            if self.streams.get(&key).is_some_and(|stream| stream.done) {
+                continue;
+            }
+
+            let keys = InitialKeys::derive(header.version, header.destination_id);
+            let Some(payload) = remove_protection(&keys, &packet[..header.packet_length], &header)
+            else {
+                continue;
+            };
+
+            if !self.streams.contains_key(&key) && self.streams.len() >= self.max_connections {
+                self.evict_least_recent();
+            }
+            let stream = self.streams.entry(key.clone()).or_insert(CryptoStream {
+                fragments: BTreeMap::new(),
+                size: 0,
+                done: false,
+                last_seen: timestamp,
+            });
+            stream.last_seen = timestamp;
+            // Retransmitted frames repeat data already buffered at the same offset
+            for (offset, data) in parse_crypto_frames(&payload)? {
+                if let Entry::Vacant(entry) = stream.fragments.entry(offset) {
+                    stream.size += data.len();
+                    entry.insert(data.to_vec());
+                }
+            }
+            if stream.size > self.buffer_limit {
+                self.streams.remove(&key);
+                continue;
+            }
+
+            let data = stream.contiguous();
+            if data.len() < 4 {
+                continue;
+            }
+            let length = u32::from_be_bytes([0, data[1], data[2], data[3]]) as usize;
+            if data.len() >= 4 + length {
+                // Keep the key so that retransmitted Initial packets are not decoded again
+                stream.done = true;
+                stream.fragments.clear();
+                return tls::parse_client_hello(&data[..4 + length]).map(Some);
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn evict_least_recent(&mut self) {
+        let least_recent = self
+            .streams
+            .iter()
+            .min_by_key(|(_, stream)| stream.last_seen)
+            .map(|(key, _)| key.clone());
+        if let Some(key) = least_recent {
+            self.streams.remove(&key);
+        }
+    }
+
+    /// Drops the connections that have not sent an Initial packet for longer than
+    /// the timeout.
+    fn expire(&mut self, timestamp: Duration) {
+        let timeout = self.timeout;
+        self.streams
+            .retain(|_, stream| timestamp.saturating_sub(stream.last_seen) < timeout);
+        self.last_expiry = timestamp;
+    }
+}
+
+/// The fields of a long header needed to find and decrypt an Initial packet.
+// https://datatracker.ietf.org/doc/html/rfc9000#section-17.2
+struct LongHeader<'a> {
+    version: u32,
+    is_initial: bool,
+    destination_id: &'a [u8],
+
+    // Offset of the protected packet number from the start of the packet
+    packet_number_offset: usize,
+
+    // Length of the whole packet, header included
+    packet_length: usize,
+}
+
+/// Parses the long header at the start of `packet`. Returns `None` for versions
+/// other than v1 and v2, whose header layout past the connection IDs is unknown,
+/// and for Retry packets, which carry no length.
+fn parse_long_header(packet: &[u8]) -> DecodeResult<Option<LongHeader<'_>>> {
+    DecodeError::check_len("quic", packet, 6)?;
+    let version = u32::from_be_bytes([packet[1], packet[2], packet[3], packet[4]]);
+    if version != QUIC_V1 && version != QUIC_V2 {
+        return Ok(None);
+    }
+
+    // The packet types are numbered differently in v2 (RFC 9369 section 3.2)
+    let packet_type = (packet[0] >> 4) & 0x03;
+    let (initial, retry) = if version == QUIC_V2 { (1, 0) } else { (0, 3) };
+    if packet_type == retry {
+        return Ok(None);
+    }
+
+    let mut index = 5;
+    let destination_id = read_connection_id(packet, &mut index)?;
+    read_connection_id(packet, &mut index)?;
+
+    if packet_type == initial {
+        let token_length = read_varint(packet, &mut index)? as usize;
+        index += token_length;
+    }
+    let length = read_varint(packet, &mut index)? as usize;
+    DecodeError::check_len("quic", packet, index + length)?;
+
+    Ok(Some(LongHeader {
+        version,
+        is_initial: packet_type == initial,
+        destination_id,
+        packet_number_offset: index,
+        packet_length: index + length,
+    }))
+}
+
+fn read_connection_id<'a>(packet: &'a [u8], index: &mut usize) -> DecodeResult<&'a [u8]> {
+    DecodeError::check_len("quic", packet, *index + 1)?;
+    let length = packet[*index] as usize;
+    if length > MAX_CONNECTION_ID_LENGTH {
+        return Err(DecodeError::BadLength {
+            layer: "quic connection id",
+            length,
+        });
+    }
+    DecodeError::check_len("quic", packet, *index + 1 + length)?;
+    let id = &packet[*index + 1..*index + 1 + length];
+    *index += 1 + length;
+    Ok(id)
+}
+
+/// Reads a variable-length integer, whose two most significant bits give its
+/// length as 1, 2, 4 or 8 bytes (RFC 9000 section 16).
+fn read_varint(data: &[u8], index: &mut usize) -> DecodeResult<u64> {
+    DecodeError::check_len("quic", data, *index + 1)?;
+    let length = 1 << (data[*index] >> 6);
+    DecodeError::check_len("quic", data, *index + length)?;
+
+    let mut value = (data[*index] & 0x3f) as u64;
+    for byte in &data[*index + 1..*index + length] {
+        value = (value << 8) | *byte as u64;
+    }
+    *index += length;
+    Ok(value)
+}
+
+/// Removes header protection from the packet and decrypts its payload, returning
+/// `None` if the packet was not protected with these keys (RFC 9001 section 5).
+fn remove_protection(keys: &InitialKeys, packet: &[u8], header: &LongHeader) -> Option<Vec<u8>> {
+    let sample_start = header.packet_number_offset + SAMPLE_OFFSET;
+    let sample = packet.get(sample_start..sample_start + SAMPLE_LENGTH)?;
+    let mut mask: [u8; 16] = sample.try_into().unwrap();
+    keys.header_protection.encrypt_block(&mut mask);
+
+    // The low 4 bits of the first byte, including the packet number length, are masked
+    let first_byte = packet[0] ^ (mask[0] & 0x0f);
+    let packet_number_length = (first_byte & 0x03) as usize + 1;
+    let payload_start = header.packet_number_offset + packet_number_length;
+
+    let mut unprotected_header = packet[..payload_start].to_vec();
+    unprotected_header[0] = first_byte;
+    let mut packet_number = 0u64;
+    for i in 0..packet_number_length {
+        let byte = &mut unprotected_header[header.packet_number_offset + i];
+        *byte ^= mask[1 + i];
+        packet_number = (packet_number << 8) | *byte as u64;
+    }
+
+    // The packet number is truncated on the wire, but the first packets of a
+    // connection are numbered from zero so the truncated value is the full one
+    let mut nonce = keys.iv;
+    for (byte, number) in nonce[4..].iter_mut().zip(packet_number.to_be_bytes()) {
+        *byte ^= number;
+    }
+
+    keys.aead
+        .open(&nonce, &unprotected_header, &packet[payload_start..])
+}
+
+/// Returns the offset and data of the CRYPTO frames in a decrypted payload.
+// https://datatracker.ietf.org/doc/html/rfc9000#section-19
+fn parse_crypto_frames(payload: &[u8]) -> DecodeResult<Vec<(u64, &[u8])>> {
+    let mut frames = Vec::new();
+    let mut index = 0;
+
+    while index < payload.len() {
+        match read_varint(payload, &mut index)? {
+            FRAME_PADDING | FRAME_PING => (),
+            frame_type @ (FRAME_ACK | FRAME_ACK_ECN) => {
+                // Largest acknowledged, delay, range count and first range
+                read_varint(payload, &mut index)?;
+                read_varint(payload, &mut index)?;
+                let range_count = read_varint(payload, &mut index)?;
+                read_varint(payload, &mut index)?;
+
+                // A gap and a length for each further range
+                for _ in 0..range_count {
+                    read_varint(payload, &mut index)?;
+                    read_varint(payload, &mut index)?;
+                }
+
+                // The counts of packets marked ECT(0), ECT(1) and CE
+                if frame_type == FRAME_ACK_ECN {
+                    for _ in 0..3 {
+                        read_varint(payload, &mut index)?;
+                    }
+                }
+            }
+            FRAME_CRYPTO => {
+                let offset = read_varint(payload, &mut index)?;
+                let length = read_varint(payload, &mut index)? as usize;
+                DecodeError::check_len("quic crypto frame", payload, index + length)?;
+                frames.push((offset, &payload[index..index + length]));
+                index += length;
+            }
+            FRAME_CONNECTION_CLOSE => {
+                // Error code, the type of the frame that caused it, then the reason
+                read_varint(payload, &mut index)?;
+                read_varint(payload, &mut index)?;
+                let reason_length = read_varint(payload, &mut index)? as usize;
+                index += reason_length;
+            }
+            // Other frames are not allowed in Initial packets, and their length is
+            // unknown, so nothing after them can be read
+            _ => break,
+        }
+    }
+
+    Ok(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::tls::tests::client_hello;
+
+    const CLIENT: &str = "192.0.2.10:51234";
+    const TAG_LENGTH: usize = 16;
+    const SERVER: &str = "192.0.2.1:443";
+
+    fn hex(string: &str) -> Vec<u8> {
+        (0..string.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&string[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn encrypt_block(cipher: &Aes128, block: &[u8]) -> [u8; 16] {
+        let mut block = block.try_into().unwrap();
+        cipher.encrypt_block(&mut block);
+        block
+    }
+
+    /// Encodes a variable-length integer below 2^14 in as few bytes as possible.
+    fn varint(value: usize) -> Vec<u8> {
+        if value < 64 {
+            vec![value as u8]
+        } else {
+            (0x4000 | value as u16).to_be_bytes().to_vec()
+        }
+    }
+
+    fn crypto_frame(offset: usize, data: &[u8]) -> Vec<u8> {
+        let mut frame = vec![FRAME_CRYPTO as u8];
+        frame.extend(varint(offset));
+        frame.extend(varint(data.len()));
+        frame.extend_from_slice(data);
+        frame
+    }
+
+    /// Protects a QUIC v1 client Initial packet the way RFC 9001 appendix A.2 does:
+    /// a 4 byte packet number, and `frames` padded to fill a 1200 byte datagram.
+    fn protect_initial(destination_id: &[u8], packet_number: u32, frames: &[u8]) -> Vec<u8> {
+        let header_length = 1 + 4 + 1 + destination_id.len() + 1 + 1 + 2 + 4;
+        let mut payload = frames.to_vec();
+        payload.resize(1200 - header_length - TAG_LENGTH, FRAME_PADDING as u8);
+
+        let mut packet = vec![0xc3];
+        packet.extend_from_slice(&QUIC_V1.to_be_bytes());
+        packet.push(destination_id.len() as u8);
+        packet.extend_from_slice(destination_id);
+        packet.extend_from_slice(&[0, 0]); // source connection id and token
+        let length = 4 + payload.len() + TAG_LENGTH;
+        packet.extend_from_slice(&(0x4000 | length as u16).to_be_bytes());
+        let packet_number_offset = packet.len();
+        packet.extend_from_slice(&packet_number.to_be_bytes());
+
+        let keys = InitialKeys::derive(QUIC_V1, destination_id);
+        let mut nonce = keys.iv;
+        for (byte, number) in nonce[8..].iter_mut().zip(packet_number.to_be_bytes()) {
+            *byte ^= number;
+        }
+        let ciphertext = keys.aead.seal(&nonce, &packet, &payload);
+        packet.extend_from_slice(&ciphertext);
+
+        let sample_start = packet_number_offset + SAMPLE_OFFSET;
+        let mask = encrypt_block(
+            &keys.header_protection,
+            &packet[sample_start..sample_start + SAMPLE_LENGTH],
+        );
+        packet[0] ^= mask[0] & 0x0f;
+        for i in 0..4 {
+            packet[packet_number_offset + i] ^= mask[1 + i];
+        }
+        packet
+    }
+
+    fn add(decoder: &mut InitialDecoder, packet: &[u8], seconds: u64) -> Option<ClientHello> {
+        let (src, dst) = (CLIENT.parse().unwrap(), SERVER.parse().unwrap());
+        decoder
+            .add(src, dst, packet, Duration::from_secs(seconds))
+            .unwrap()
+    }
+
+    // RFC 9001 appendix A.1 and A.2
+    #[test]
+    fn derives_rfc9001_initial_keys() {
+        let keys = InitialKeys::derive(QUIC_V1, &hex("8394c8f03e515708"));
+
+        let key = hex("1f369613dd76d5467730efcbe3b1a22d").try_into().unwrap();
+        let nonce = [0; 12];
+        assert_eq!(
+            keys.aead.seal(&nonce, b"aad", b"plaintext"),
+            Aes128Gcm::new(&key).seal(&nonce, b"aad", b"plaintext")
+        );
+        assert_eq!(keys.iv.to_vec(), hex("fa044b2f42a3fd3b46fb255c"));
+
+        let sample = hex("d1b1c98dd7689fb8ec11d242b123dc9b");
+        let hp = hex("9f50449e04a0e810283a1e9933adedd2").try_into().unwrap();
+        let mask = encrypt_block(&keys.header_protection, &sample);
+        assert_eq!(mask, encrypt_block(&Aes128::new(&hp), &sample));
+        assert_eq!(mask[..5].to_vec(), hex("437b9aec36"));
+    }
+
+    // RFC 9369 appendix A.1
+    #[test]
+    fn derives_rfc9369_initial_keys() {
+        let keys = InitialKeys::derive(QUIC_V2, &hex("8394c8f03e515708"));
+
+        let key = hex("8b1a0bc121284290a29e0971b5cd045d").try_into().unwrap();
+        let nonce = [0; 12];
+        assert_eq!(
+            keys.aead.seal(&nonce, b"aad", b"plaintext"),
+            Aes128Gcm::new(&key).seal(&nonce, b"aad", b"plaintext")
+        );
+        assert_eq!(keys.iv.to_vec(), hex("91f73e2351d8fa91660e909f"));
+
+        let block = [0; 16];
+        let hp = hex("45b95e15235d6f45a6b19cbcb0294ba9").try_into().unwrap();
+        assert_eq!(
+            encrypt_block(&keys.header_protection, &block),
+            encrypt_block(&Aes128::new(&hp), &block)
+        );
+    }
+
+    // RFC 9001 appendix A.2
+    #[test]
+    fn decodes_rfc9001_client_initial() {
+        let packet = protect_initial(
+            &hex("8394c8f03e515708"),
+            2,
+            &crypto_frame(0, &client_hello()),
+        );
+
+        // The protected header and the sample taken for header protection
+        assert_eq!(
+            packet[..22].to_vec(),
+            hex("c000000001088394c8f03e5157080000449e7b9aec34")
+        );
+        assert_eq!(
+            packet[22..38].to_vec(),
+            hex("d1b1c98dd7689fb8ec11d242b123dc9b")
+        );
+
+        let mut decoder = InitialDecoder::default();
+        let hello = add(&mut decoder, &packet, 1).unwrap();
+        assert_eq!(hello.server_name.as_deref(), Some("example.com"));
+        assert_eq!(hello.alpn, ["alpn"]);
+
+        // A retransmission is not decoded again
+        assert_eq!(add(&mut decoder, &packet, 2), None);
+    }
+
+    #[test]
+    fn reassembles_client_hello_from_several_packets() {
+        let destination_id = hex("8394c8f03e515708");
+        let hello = client_hello();
+        let (first, second) = hello.split_at(100);
+
+        // The second half arrives first, along with an ACK and a PING
+        let frames = [
+            [FRAME_ACK as u8, 0, 0, 0, 0, FRAME_PING as u8].as_slice(),
+            &crypto_frame(100, second),
+        ]
+        .concat();
+        let mut decoder = InitialDecoder::default();
+        assert_eq!(
+            add(
+                &mut decoder,
+                &protect_initial(&destination_id, 0, &frames),
+                1
+            ),
+            None
+        );
+
+        let packet = protect_initial(&destination_id, 1, &crypto_frame(0, first));
+        let decoded = add(&mut decoder, &packet, 2).unwrap();
+        assert_eq!(decoded.server_name.as_deref(), Some("example.com"));
+    }
+
+    #[test]
+    fn ignores_packet_with_corrupted_payload() {
+        let mut packet = protect_initial(
+            &hex("8394c8f03e515708"),
+            2,
+            &crypto_frame(0, &client_hello()),
+        );
+        packet[100] ^= 1;
+
+        let mut decoder = InitialDecoder::default();
+        assert_eq!(add(&mut decoder, &packet, 1), None);
+    }
+
+    #[test]
+    fn drops_least_recent_connection_when_full() {
+        let hello = client_hello();
+        let (first, second) = hello.split_at(100);
+        let mut decoder = InitialDecoder::new(DEFAULT_TIMEOUT, DEFAULT_BUFFER_LIMIT, 2);
+
+        let ids = [[1u8; 8], [2; 8], [3; 8]];
+        for (seconds, id) in ids.iter().enumerate() {
+            let packet = protect_initial(id, 0, &crypto_frame(0, first));
+            assert_eq!(add(&mut decoder, &packet, seconds as u64), None);
+        }
+        assert_eq!(decoder.streams.len(), 2);
+
+        // The first connection was dropped, so the rest of its hello is not enough
+        let packet = protect_initial(&ids[0], 1, &crypto_frame(100, second));
+        assert_eq!(add(&mut decoder, &packet, 3), None);
+
+        let packet = protect_initial(&ids[2], 1, &crypto_frame(100, second));
+        assert!(add(&mut decoder, &packet, 3).is_some());
+    }
+}
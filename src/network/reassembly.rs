@@ -0,0 +1,335 @@
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::network::error::{DecodeError, DecodeResult};
+
+// Linux discards incomplete datagrams after 30 seconds (net.ipv4.ipfrag_time)
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Upper bound on the fragment data buffered across all datagrams
+const DEFAULT_MEMORY_LIMIT: usize = 4 * 1024 * 1024;
+
+// A reassembled payload must fit in the 16 bit length fields of IPv4 and IPv6
+const MAX_DATAGRAM_LENGTH: usize = 65535;
+
+// How often timed out datagrams are looked for, in capture time
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Identifies the fragments that belong to the same datagram.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FragmentKey {
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    pub protocol: u8,
+    pub identification: u32,
+}
+
+#[derive(Debug)]
+struct FragmentBuffer {
+    // Fragment data keyed by byte offset into the datagram payload
+    fragments: BTreeMap<usize, Vec<u8>>,
+
+    // Length of the datagram payload, known once the last fragment has arrived
+    total_length: Option<usize>,
+
+    // Number of bytes buffered for this datagram
+    size: usize,
+
+    // Capture time of the first fragment
+    first_seen: Duration,
+}
+
+impl FragmentBuffer {
+    /// Returns the payload if the fragments cover it from start to end. Overlapping
+    /// data is taken from the fragment with the lowest offset.
+    fn assemble(&self) -> Option<Vec<u8>> {
+        let total_length = self.total_length?;
+        let mut payload = Vec::with_capacity(total_length);
+
+        for (&offset, data) in &self.fragments {
+            if offset > payload.len() {
+                return None;
+            }
+            let end = (offset + data.len()).min(total_length);
+            if end > payload.len() {
+                payload.extend_from_slice(&data[payload.len() - offset..end - offset]);
+            }
+        }
+
+        (payload.len() == total_length).then_some(payload)
+    }
+}
+
+/// Buffers IPv4 and IPv6 fragments until every fragment of a datagram has arrived.
+///
+/// Incomplete datagrams are dropped after a timeout, and the oldest datagrams are
+/// dropped when the buffered data exceeds the memory limit.
+#[derive(Debug)]
+pub struct FragmentReassembler {
+    buffers: HashMap<FragmentKey, FragmentBuffer>,
+    memory: usize,
+    timeout: Duration,
+    memory_limit: usize,
+    last_expiry: Duration,
+}
+
+impl Default for FragmentReassembler {
+    fn default() -> Self {
+        Self::new(DEFAULT_TIMEOUT, DEFAULT_MEMORY_LIMIT)
+    }
+}
+
+impl FragmentReassembler {
+    pub fn new(timeout: Duration, memory_limit: usize) -> Self {
+        Self {
+            buffers: HashMap::new(),
+            memory: 0,
+            timeout,
+            memory_limit,
+            last_expiry: Duration::ZERO,
+        }
+    }
+
+    /// Adds the fragment at byte `offset` of the datagram identified by `key`, captured
+    /// at `timestamp`. Returns the reassembled payload once all fragments have arrived.
+    pub fn add(
+        &mut self,
+        key: FragmentKey,
+        offset: usize,
+        more_fragments: bool,
+        data: &[u8],
+        timestamp: Duration,
+    ) -> DecodeResult<Option<Vec<u8>>> {
+        let end = offset + data.len();
+        if end > MAX_DATAGRAM_LENGTH {
+            return Err(DecodeError::BadLength {
+                layer: "ip fragment",
+                length: end,
+            });
+        }
+
+        if timestamp.saturating_sub(self.last_expiry) >= EXPIRY_INTERVAL {
+            self.expire(timestamp);
+        }
+
+        let buffer = self.buffers.entry(key).or_insert(FragmentBuffer {
+            fragments: BTreeMap::new(),
+            total_length: None,
+            size: 0,
+            first_seen: timestamp,
+        });
+
+        if !more_fragments {
+            buffer.total_length = Some(end);
+        }
+        // Keep the first copy of a retransmitted fragment
+        if let Entry::Vacant(entry) = buffer.fragments.entry(offset) {
+            entry.insert(data.to_vec());
+            buffer.size += data.len();
+            self.memory += data.len();
+        }
+
+        if let Some(payload) = buffer.assemble() {
+            self.remove(&key);
+            return Ok(Some(payload));
+        }
+
+        self.enforce_memory_limit();
+        Ok(None)
+    }
+
+    /// Drops datagrams whose first fragment arrived more than the timeout ago.
+    fn expire(&mut self, timestamp: Duration) {
+        let timeout = self.timeout;
+        let mut expired = 0;
+        self.buffers.retain(|_, buffer| {
+            let keep = timestamp.saturating_sub(buffer.first_seen) < timeout;
+            if !keep {
+                expired += buffer.size;
+            }
+            keep
+        });
+        self.memory -= expired;
+        self.last_expiry = timestamp;
+    }
+
+    fn enforce_memory_limit(&mut self) {
+        while self.memory > self.memory_limit {
+            let oldest = self
+                .buffers
+                .iter()
+                .min_by_key(|(_, buffer)| buffer.first_seen)
+                .map(|(key, _)| *key);
+
+            match oldest {
+                Some(key) => self.remove(&key),
+                None => break,
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &FragmentKey) {
+        if let Some(buffer) = self.buffers.remove(key) {
+            self.memory -= buffer.size;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    fn key(identification: u32) -> FragmentKey {
+        FragmentKey {
+            src: IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+            dst: IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2)),
+            protocol: 17,
+            identification,
+        }
+    }
+
+    fn payload(length: usize) -> Vec<u8> {
+        (0..length).map(|i| i as u8).collect()
+    }
+
+    fn add(
+        reassembler: &mut FragmentReassembler,
+        key: FragmentKey,
+        data: &[u8],
+        range: std::ops::Range<usize>,
+        more_fragments: bool,
+        seconds: u64,
+    ) -> Option<Vec<u8>> {
+        reassembler
+            .add(
+                key,
+                range.start,
+                more_fragments,
+                &data[range],
+                Duration::from_secs(seconds),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn reassembles_out_of_order_fragments() {
+        let data = payload(40);
+        let mut reassembler = FragmentReassembler::default();
+
+        assert_eq!(add(&mut reassembler, key(1), &data, 32..40, false, 0), None);
+        assert_eq!(add(&mut reassembler, key(1), &data, 0..16, true, 0), None);
+        assert_eq!(
+            add(&mut reassembler, key(1), &data, 16..32, true, 0),
+            Some(data)
+        );
+        assert!(reassembler.buffers.is_empty());
+        assert_eq!(reassembler.memory, 0);
+    }
+
+    #[test]
+    fn takes_overlapping_data_from_the_lowest_offset() {
+        let data = payload(32);
+        let mut other = data.clone();
+        other.iter_mut().for_each(|byte| *byte = !*byte);
+        let mut reassembler = FragmentReassembler::default();
+
+        assert_eq!(add(&mut reassembler, key(1), &data, 0..16, true, 0), None);
+        // A retransmission with different data does not replace the first copy
+        assert_eq!(add(&mut reassembler, key(1), &other, 0..16, true, 0), None);
+        assert_eq!(add(&mut reassembler, key(1), &other, 8..24, true, 0), None);
+        let reassembled = add(&mut reassembler, key(1), &data, 24..32, false, 0).unwrap();
+
+        assert_eq!(reassembled[..16], data[..16]);
+        assert_eq!(reassembled[16..24], other[16..24]);
+        assert_eq!(reassembled[24..], data[24..]);
+    }
+
+    #[test]
+    fn drops_datagram_after_timeout() {
+        let data = payload(16);
+        let mut reassembler = FragmentReassembler::default();
+
+        assert_eq!(add(&mut reassembler, key(1), &data, 0..8, true, 0), None);
+        assert_eq!(add(&mut reassembler, key(2), &data, 0..8, true, 0), None);
+        assert_eq!(
+            add(&mut reassembler, key(1), &data, 8..16, false, 29),
+            Some(data.clone())
+        );
+
+        // Only the fragment that arrived after the timeout is left
+        assert_eq!(add(&mut reassembler, key(2), &data, 8..16, false, 30), None);
+        assert_eq!(reassembler.buffers.len(), 1);
+        assert_eq!(reassembler.memory, 8);
+    }
+
+    #[test]
+    fn drops_oldest_datagram_over_memory_limit() {
+        let data = payload(80);
+        let mut reassembler = FragmentReassembler::new(DEFAULT_TIMEOUT, 100);
+
+        assert_eq!(add(&mut reassembler, key(1), &data, 0..64, true, 0), None);
+        assert_eq!(add(&mut reassembler, key(2), &data, 0..40, true, 1), None);
+        assert!(!reassembler.buffers.contains_key(&key(1)));
+        assert_eq!(reassembler.memory, 40);
+
+        assert_eq!(add(&mut reassembler, key(1), &data, 64..80, false, 1), None);
+        assert_eq!(
+            add(&mut reassembler, key(2), &data, 40..80, false, 1),
+            Some(data)
+        );
+    }
+
+    #[test]
+    fn rejects_fragment_past_maximum_datagram_length() {
+        let mut reassembler = FragmentReassembler::default();
+        assert!(reassembler
+            .add(key(1), 65528, false, &[0; 8], Duration::ZERO)
+            .is_err());
+    }
+
+    #[test]
+    fn keeps_ipv6_datagrams_apart_by_address_and_identification() {
+        let data = payload(16);
+        let ipv6_key = |identification| FragmentKey {
+            src: IpAddr::V6(Ipv6Addr::LOCALHOST),
+            dst: IpAddr::V6(Ipv6Addr::LOCALHOST),
+            protocol: 17,
+            identification,
+        };
+        let mut reassembler = FragmentReassembler::default();
+
+        assert_eq!(
+            add(
+                &mut reassembler,
+                ipv6_key(0x8000_0001),
+                &data,
+                0..8,
+                true,
+                0
+            ),
+            None
+        );
+        assert_eq!(
+            add(&mut reassembler, ipv6_key(1), &data, 8..16, false, 0),
+            None
+        );
+        assert_eq!(
+            add(&mut reassembler, key(0x8000_0001), &data, 8..16, false, 0),
+            None
+        );
+        assert_eq!(
+            add(
+                &mut reassembler,
+                ipv6_key(0x8000_0001),
+                &data,
+                8..16,
+                false,
+                0
+            ),
+            Some(data)
+        );
+    }
+}
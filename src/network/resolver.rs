@@ -0,0 +1,387 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::Path;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::network::dns;
+
+// Maximum number of addresses waiting to be resolved. Lookups requested while the
+// queue is full are dropped, and have to be requested again after `FAILURE_TTL`.
+const QUEUE_SIZE: usize = 256;
+const NUM_WORKERS: usize = 4;
+
+pub const RESOLV_CONF: &str = "/etc/resolv.conf";
+
+// Defaults from resolv.conf(5), used when the file does not override them
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_ATTEMPTS: u32 = 2;
+const MAX_ATTEMPTS: u32 = 5;
+const MAX_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Bounds on how long a resolved name is cached, regardless of the PTR record's TTL
+const MIN_POSITIVE_TTL: Duration = Duration::from_secs(60);
+const MAX_POSITIVE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// How long to remember addresses without a PTR record, and addresses whose
+// lookup timed out or failed. Callers should wait as long before looking up an
+// address again whose name is still unknown.
+const NEGATIVE_TTL: Duration = Duration::from_secs(10 * 60);
+pub const FAILURE_TTL: Duration = Duration::from_secs(60);
+
+// Maximum number of cached results. Every host ever seen is looked up, so the cache
+// is pruned once it fills up rather than growing with the capture.
+const MAX_CACHE_SIZE: usize = 4096;
+
+/// Upstream DNS servers and query options, usually read from `/etc/resolv.conf`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolverConfig {
+    // Servers to query, in order of preference
+    pub nameservers: Vec<SocketAddr>,
+
+    // Search list for host-name lookup. Reverse lookups query fully qualified names
+    // so the list is informational only.
+    pub search: Vec<String>,
+
+    // How long to wait for a response from a single server
+    pub timeout: Duration,
+
+    // Number of times to cycle through the servers before giving up
+    pub attempts: u32,
+}
+
+impl Default for ResolverConfig {
+    fn default() -> Self {
+        Self {
+            // With no nameserver lines, the resolver library uses the local machine
+            nameservers: vec![SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 53)],
+            search: Vec::new(),
+            timeout: DEFAULT_TIMEOUT,
+            attempts: DEFAULT_ATTEMPTS,
+        }
+    }
+}
+
+impl ResolverConfig {
+    /// Reads the system resolver configuration, falling back to the defaults if
+    /// `/etc/resolv.conf` cannot be read.
+    pub fn system() -> Self {
+        Self::from_file(RESOLV_CONF).unwrap_or_default()
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Parses the `nameserver`, `search`, `domain` and `options` lines of a
+    /// resolv.conf file. Unknown lines and malformed values are ignored.
+    pub fn parse(contents: &str) -> Self {
+        let mut config = Self {
+            nameservers: Vec::new(),
+            ..Self::default()
+        };
+
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some("nameserver") => {
+                    if let Some(server) = fields.next().and_then(parse_nameserver) {
+                        config.nameservers.push(server);
+                    }
+                }
+                // The last of the search and domain lines takes precedence
+                Some("search") => config.search = fields.map(String::from).collect(),
+                Some("domain") => config.search = fields.take(1).map(String::from).collect(),
+                Some("options") => {
+                    for option in fields {
+                        if let Some(timeout) = option.strip_prefix("timeout:") {
+                            if let Ok(timeout) = timeout.parse::<u64>() {
+                                // A zero timeout is not a valid socket timeout
+                                config.timeout = Duration::from_secs(timeout)
+                                    .clamp(Duration::from_secs(1), MAX_TIMEOUT);
+                            }
+                        } else if let Some(attempts) = option.strip_prefix("attempts:") {
+                            if let Ok(attempts) = attempts.parse::<u32>() {
+                                config.attempts = attempts.clamp(1, MAX_ATTEMPTS);
+                            }
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        if config.nameservers.is_empty() {
+            config.nameservers = Self::default().nameservers;
+        }
+        config
+    }
+}
+
+/// Parses a nameserver given as an ip address, optionally with a port. Link-local
+/// IPv6 addresses with a zone index such as `fe80::1%eth0` are not supported.
+pub fn parse_nameserver(server: &str) -> Option<SocketAddr> {
+    if let Ok(ip) = server.parse::<IpAddr>() {
+        return Some(SocketAddr::new(ip, 53));
+    }
+    server.parse::<SocketAddr>().ok()
+}
+
+/// The result of a reverse lookup, delivered back to the main thread.
+#[derive(Debug)]
+pub struct ReverseLookup {
+    pub ip: IpAddr,
+    pub domain: Option<String>,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    domain: Option<String>,
+    expires: Instant,
+}
+
+#[derive(Debug, Default)]
+struct ResolverState {
+    cache: HashMap<IpAddr, CacheEntry>,
+
+    // Addresses that are queued or being looked up, so that each is only resolved once
+    in_flight: HashSet<IpAddr>,
+}
+
+impl ResolverState {
+    /// Caches the result of a lookup. When the cache is full, expired entries are
+    /// evicted first, then the entries closest to expiring.
+    fn cache_result(&mut self, ip: IpAddr, entry: CacheEntry, now: Instant) {
+        if self.cache.len() >= MAX_CACHE_SIZE && !self.cache.contains_key(&ip) {
+            self.cache.retain(|_, entry| entry.expires > now);
+        }
+        while self.cache.len() >= MAX_CACHE_SIZE && !self.cache.contains_key(&ip) {
+            let Some(oldest) = self
+                .cache
+                .iter()
+                .min_by_key(|(_, entry)| entry.expires)
+                .map(|(ip, _)| *ip)
+            else {
+                break;
+            };
+            self.cache.remove(&oldest);
+        }
+
+        self.cache.insert(ip, entry);
+    }
+}
+
+/// Resolves PTR records on a pool of background threads so that the UI thread never
+/// blocks on the network.
+///
+/// Results are sent to the channel passed to [`Resolver::new`] as [`ReverseLookup`]s.
+#[derive(Debug)]
+pub struct Resolver {
+    queue: SyncSender<IpAddr>,
+    results: Sender<ReverseLookup>,
+    state: Arc<Mutex<ResolverState>>,
+}
+
+impl Resolver {
+    pub fn new(config: ResolverConfig, results: Sender<ReverseLookup>) -> Self {
+        let config = Arc::new(config);
+        let (queue, requests) = mpsc::sync_channel(QUEUE_SIZE);
+        let requests = Arc::new(Mutex::new(requests));
+        let state = Arc::new(Mutex::new(ResolverState::default()));
+
+        // Seed transaction ids from the clock so that they differ between runs
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos() as u16)
+            .unwrap_or(0);
+        let next_id = Arc::new(AtomicU16::new(seed));
+
+        for _ in 0..NUM_WORKERS {
+            let config = Arc::clone(&config);
+            let requests = Arc::clone(&requests);
+            let state = Arc::clone(&state);
+            let next_id = Arc::clone(&next_id);
+            let results = results.clone();
+            thread::spawn(move || worker(config, requests, state, next_id, results));
+        }
+
+        Self {
+            queue,
+            results,
+            state,
+        }
+    }
+
+    /// Requests the domain name of `ip`. Cached results are delivered immediately, and
+    /// addresses that are already being resolved are not queued again.
+    pub fn lookup(&self, ip: IpAddr) {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(entry) = state.cache.get(&ip) {
+            if entry.expires > Instant::now() {
+                let _ = self.results.send(ReverseLookup {
+                    ip,
+                    domain: entry.domain.clone(),
+                });
+                return;
+            }
+            state.cache.remove(&ip);
+        }
+
+        if !state.in_flight.insert(ip) {
+            return;
+        }
+
+        // Drop the request if the queue is full, it is up to the caller to look the
+        // address up again later
+        if self.queue.try_send(ip).is_err() {
+            state.in_flight.remove(&ip);
+        }
+    }
+}
+
+fn worker(
+    config: Arc<ResolverConfig>,
+    requests: Arc<Mutex<Receiver<IpAddr>>>,
+    state: Arc<Mutex<ResolverState>>,
+    next_id: Arc<AtomicU16>,
+    results: Sender<ReverseLookup>,
+) {
+    loop {
+        // Only hold the lock while waiting for a request, not while resolving it
+        let ip = match requests.lock().unwrap().recv() {
+            Ok(ip) => ip,
+            Err(_) => return,
+        };
+
+        let (domain, ttl) = resolve(&config, ip, &next_id);
+
+        {
+            let mut state = state.lock().unwrap();
+            state.in_flight.remove(&ip);
+
+            let now = Instant::now();
+            state.cache_result(
+                ip,
+                CacheEntry {
+                    domain: domain.clone(),
+                    expires: now + ttl,
+                },
+                now,
+            );
+        }
+
+        if results.send(ReverseLookup { ip, domain }).is_err() {
+            return;
+        }
+    }
+}
+
+/// Looks up the PTR record of `ip`, trying each configured server in turn and cycling
+/// through them `attempts` times on timeouts and errors. Returns the domain, if any,
+/// and how long the result should be cached for.
+fn resolve(config: &ResolverConfig, ip: IpAddr, next_id: &AtomicU16) -> (Option<String>, Duration) {
+    for _ in 0..config.attempts {
+        for server in &config.nameservers {
+            let id = next_id.fetch_add(1, Ordering::Relaxed);
+            match dns::reverse_lookup(ip, *server, id, config.timeout) {
+                Ok(Some(record)) => {
+                    let ttl = Duration::from_secs(record.ttl as u64)
+                        .clamp(MIN_POSITIVE_TTL, MAX_POSITIVE_TTL);
+                    return (Some(record.domain), ttl);
+                }
+                Ok(None) => return (None, NEGATIVE_TTL),
+                Err(_) => continue,
+            }
+        }
+    }
+
+    (None, FAILURE_TTL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv6Addr;
+
+    #[test]
+    fn parses_resolv_conf_options() {
+        let config = ResolverConfig::parse(
+            "nameserver 10.0.0.1\nnameserver 2001:db8::1\noptions timeout:3 attempts:9\n",
+        );
+
+        assert_eq!(
+            config.nameservers,
+            vec![
+                "10.0.0.1:53".parse().unwrap(),
+                "[2001:db8::1]:53".parse().unwrap()
+            ]
+        );
+        assert_eq!(config.timeout, Duration::from_secs(3));
+        assert_eq!(config.attempts, MAX_ATTEMPTS);
+    }
+
+    #[test]
+    fn clamps_zero_timeout_and_attempts() {
+        let config = ResolverConfig::parse("options timeout:0 attempts:0\n");
+
+        assert_eq!(config.timeout, Duration::from_secs(1));
+        assert_eq!(config.attempts, 1);
+    }
+
+    fn entry(expires: Instant) -> CacheEntry {
+        CacheEntry {
+            domain: None,
+            expires,
+        }
+    }
+
+    fn ip(index: usize) -> IpAddr {
+        IpAddr::V6(Ipv6Addr::from(index as u128))
+    }
+
+    #[test]
+    fn full_cache_evicts_expired_entries() {
+        let now = Instant::now();
+        let mut state = ResolverState::default();
+        for index in 0..MAX_CACHE_SIZE {
+            let expires = if index % 2 == 0 {
+                now - Duration::from_secs(1)
+            } else {
+                now + NEGATIVE_TTL
+            };
+            state.cache_result(ip(index), entry(expires), now);
+        }
+
+        state.cache_result(ip(MAX_CACHE_SIZE), entry(now + NEGATIVE_TTL), now);
+
+        assert_eq!(state.cache.len(), MAX_CACHE_SIZE / 2 + 1);
+        assert!(state.cache.values().all(|entry| entry.expires > now));
+    }
+
+    #[test]
+    fn full_cache_evicts_entry_closest_to_expiring() {
+        let now = Instant::now();
+        let mut state = ResolverState::default();
+        for index in 0..MAX_CACHE_SIZE {
+            let expires = now + Duration::from_secs(60 + index as u64);
+            state.cache_result(ip(index), entry(expires), now);
+        }
+
+        state.cache_result(ip(MAX_CACHE_SIZE), entry(now + NEGATIVE_TTL), now);
+
+        assert_eq!(state.cache.len(), MAX_CACHE_SIZE);
+        assert!(!state.cache.contains_key(&ip(0)));
+        assert!(state.cache.contains_key(&ip(MAX_CACHE_SIZE)));
+    }
+}
@@ -1,107 +1,294 @@
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
 use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use pcap::Device;
+use pcap::{Activated, Capture, Device, PacketHeader};
 
-use crate::network::dns;
-use crate::network::ethernet;
-use crate::network::ip;
-use crate::network::udp;
+use crate::network::decoder::Decoder;
+use crate::network::error::DecodeError;
+use crate::network::ip::IpProtocol;
 
-use super::dns::DnsDirectRecord;
-use super::dns::DnsMessage;
+use super::dhcp::DhcpMessage;
+use super::dns::{DnsMessage, DnsProtocol};
+use super::http::HttpRequest;
+use super::tls::ClientHello;
+
+/// A decoded event produced by the capture pipeline.
+pub enum SnifferEvent {
+    /// An IP packet was seen on the wire
+    Packet(SnifferPacket),
+
+    /// A DNS query or response was decoded from a UDP datagram or TCP stream
+    Dns(DnsEvent),
+
+    /// A DHCPv4 or DHCPv6 message was decoded from a UDP datagram
+    Dhcp(DhcpEvent),
+
+    /// A TLS ClientHello was decoded from the start of a TCP stream, or from the
+    /// Initial packets of a QUIC connection
+    Tls(TlsEvent),
+
+    /// A cleartext HTTP/1.x request was decoded from a TCP stream
+    Http(HttpEvent),
+
+    /// A frame could not be decoded
+    ParseError(DecodeError),
+
+    /// Capturing stopped because of an error, e.g. the device went away
+    CaptureError(pcap::Error),
+}
+
+/// A [`SnifferEvent`] tagged with the name of the interface it was captured on.
+pub struct CaptureEvent {
+    pub interface: Arc<str>,
+    pub event: SnifferEvent,
+}
+
+/// A DNS message along with where and when it was sent.
+#[derive(Debug)]
+pub struct DnsEvent {
+    /// Capture time as the duration since the Unix epoch
+    pub timestamp: Duration,
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+    pub protocol: DnsProtocol,
+    pub message: DnsMessage,
+}
+
+/// A DHCP message along with where and when it was sent.
+#[derive(Debug)]
+pub struct DhcpEvent {
+    /// Capture time as the duration since the Unix epoch
+    pub timestamp: Duration,
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+    pub message: DhcpMessage,
+}
+
+/// A TLS ClientHello along with the connection it started.
+#[derive(Debug)]
+pub struct TlsEvent {
+    /// Capture time as the duration since the Unix epoch
+    pub timestamp: Duration,
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+    pub client_hello: ClientHello,
+}
+
+/// An HTTP request head along with the connection it was sent on.
+#[derive(Debug)]
+pub struct HttpEvent {
+    /// Capture time as the duration since the Unix epoch
+    pub timestamp: Duration,
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+    pub request: HttpRequest,
+}
 
 pub struct SnifferPacket {
     pub src: IpAddr,
     pub dst: IpAddr,
-    // pub host: String,
+    pub protocol: IpProtocol,
+    pub src_port: Option<u16>,
+    pub dst_port: Option<u16>,
+
+    // Set if the IPv4 header or the TCP or UDP checksum is wrong
+    pub bad_checksum: bool,
+}
+
+/// Where a [`Sniffer`] reads its packets from.
+enum CaptureSource {
+    /// Live capture on a network device
+    Device(Device),
+
+    /// Replay of a pcap or pcapng file. If `realtime` is set, packets are delivered
+    /// with the same spacing as they were captured, otherwise as fast as possible.
+    File { path: PathBuf, realtime: bool },
+}
+
+/// Delays replayed packets so that they are delivered at their original speed.
+struct Pacer {
+    start: Option<(Instant, Duration)>,
+}
+
+impl Pacer {
+    fn new() -> Self {
+        Self { start: None }
+    }
+
+    /// Sleeps until the packet captured at `timestamp` is due, relative to the first
+    /// packet of the capture.
+    fn wait(&mut self, timestamp: Duration) {
+        if let Some(delay) = self.delay(timestamp, Instant::now()) {
+            thread::sleep(delay);
+        }
+    }
+
+    /// Returns how long after `now` the packet captured at `timestamp` is due, or
+    /// `None` if it is due already.
+    fn delay(&mut self, timestamp: Duration, now: Instant) -> Option<Duration> {
+        let (started_at, first_timestamp) = *self.start.get_or_insert((now, timestamp));
+
+        let due = timestamp.saturating_sub(first_timestamp);
+        due.checked_sub(now.saturating_duration_since(started_at))
+            .filter(|delay| !delay.is_zero())
+    }
+}
+
+/// Returns the capture time of a packet as the duration since the Unix epoch.
+fn packet_timestamp(header: &PacketHeader) -> Duration {
+    Duration::from_secs(header.ts.tv_sec as u64) + Duration::from_micros(header.ts.tv_usec as u64)
 }
 
 pub struct Sniffer {
-    device: Device,
+    source: CaptureSource,
+
+    // Name that events from this sniffer are tagged with
+    interface: Arc<str>,
+
+    // Whether to skip checksum verification for packets sent from the device
+    ignore_local_checksums: bool,
 }
 
 impl Sniffer {
-    pub fn new(device_name: String) -> Self {
-        let devices = pcap::Device::list().expect("devices lookup failed");
+    /// Creates a sniffer that captures live traffic on the given device, see
+    /// [`crate::network::interface`] for looking devices up.
+    pub fn new(device: Device) -> Self {
+        Self {
+            interface: device.name.as_str().into(),
+            source: CaptureSource::Device(device),
+            ignore_local_checksums: false,
+        }
+    }
 
-        // get the wifi device
-        let device = devices
-            .into_iter()
-            .find(|d| d.name == device_name)
-            .expect("could not get wifi device");
+    /// Creates a sniffer that replays the packets of a pcap or pcapng file instead
+    /// of capturing on a live device.
+    pub fn from_file(path: PathBuf, realtime: bool) -> Self {
+        let interface = path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default()
+            .as_ref()
+            .into();
 
-        Self { device }
+        Self {
+            source: CaptureSource::File { path, realtime },
+            interface,
+            ignore_local_checksums: false,
+        }
     }
 
-    // Listens for A and AAAA records and sends them back to the main thread
-    // TODO: listen to CNAME
-    pub fn start_dns_capture(&self, tx: Sender<DnsMessage>) {
-        let mut cap = pcap::Capture::from_device(self.device.clone())
-            .expect("failed to get capture")
-            .immediate_mode(true)
-            .open()
-            .unwrap();
-
-        // filter DNS responses
-        cap.filter("udp src port 53", true).unwrap();
-
-        cap.for_each(None, |packet| {
-            let frame = ethernet::parse_ethernet_frame(packet.data);
-
-            let ip_payload = if frame.ethertype[0] == 0x08 && frame.ethertype[1] == 0x00 {
-                ip::parse_ipv4_packet(frame.payload).payload
-            } else if frame.ethertype[0] == 0x86 && frame.ethertype[1] == 0xdd {
-                ip::parse_ipv6_packet(frame.payload).payload
-            } else {
-                panic!("unsupported ethertype {:?}", frame.ethertype);
-            };
-
-            let datagram = udp::parse_udp_packet(ip_payload);
-            let message = dns::DnsMessage::parse(datagram.data);
-
-            tx.send(message).unwrap();
-            // if message.questions[0].qtype != 12 {
-            //     println!("{:?}", message);
-            // }
-            // let record = dns::DnsResourceRecord::parse_direct_record(datagram.data);
-            // if let Some(record) = record {
-            //     tx.send(record).unwrap();
-            // }
+    /// Skips checksum verification for packets sent from the addresses of the capture
+    /// device, whose checksums are left to the network card when checksum offloading
+    /// is enabled. Has no effect when replaying a file.
+    pub fn ignore_local_checksums(mut self, ignore: bool) -> Self {
+        self.ignore_local_checksums = ignore;
+        self
+    }
+
+    fn decoder(&self) -> Decoder {
+        let mut decoder = Decoder::new();
+        if self.ignore_local_checksums {
+            if let CaptureSource::Device(device) = &self.source {
+                decoder.skip_checksums_from(device.addresses.iter().map(|address| address.addr));
+            }
+        }
+        decoder
+    }
+
+    /// Opens the capture device or file, so that a device that cannot be captured on
+    /// or a file that cannot be read is reported before capturing starts.
+    pub fn open(&self) -> Result<Capture<dyn Activated>, pcap::Error> {
+        Ok(match &self.source {
+            CaptureSource::Device(device) => pcap::Capture::from_device(device.clone())?
+                .immediate_mode(true)
+                .open()?
+                .into(),
+            CaptureSource::File { path, .. } => pcap::Capture::from_file(path)?.into(),
         })
-        .unwrap();
     }
 
-    pub fn start_packet_capture(&self, tx: Sender<SnifferPacket>) {
-        let mut cap = pcap::Capture::from_device(self.device.clone())
-            .expect("failed to get capture")
-            .immediate_mode(true)
-            .open()
-            .unwrap();
-
-        // TODO: expand filter
-        // cap.filter("src port 80 or src port 443", true).unwrap();
-        cap.for_each(None, |packet| {
-            let frame = ethernet::parse_ethernet_frame(packet.data);
-
-            if frame.ethertype[0] == 0x08 && frame.ethertype[1] == 0x00 {
-                let packet = ip::parse_ipv4_packet(frame.payload);
-                tx.send(SnifferPacket {
-                    src: IpAddr::V4(packet.src),
-                    dst: IpAddr::V4(packet.dst),
-                })
-                .expect("sniffer: failed to send ipv4 packet");
-            } else if frame.ethertype[0] == 0x86 && frame.ethertype[1] == 0xdd {
-                let packet = ip::parse_ipv6_packet(frame.payload);
-                tx.send(SnifferPacket {
-                    src: IpAddr::V6(packet.src),
-                    dst: IpAddr::V6(packet.dst),
+    fn pacer(&self) -> Option<Pacer> {
+        match self.source {
+            CaptureSource::File { realtime: true, .. } => Some(Pacer::new()),
+            _ => None,
+        }
+    }
+
+    /// Captures every frame from `cap`, opened with [`Sniffer::open`], decodes it once
+    /// and sends the resulting events back to the main thread in capture order.
+    pub fn start_capture(&self, mut cap: Capture<dyn Activated>, tx: Sender<CaptureEvent>) {
+        let mut pacer = self.pacer();
+        let mut decoder = self.decoder();
+        let linktype = cap.get_datalink();
+
+        let result = cap.for_each(None, |packet| {
+            if let Some(pacer) = pacer.as_mut() {
+                pacer.wait(packet_timestamp(packet.header));
+            }
+
+            let timestamp = packet_timestamp(packet.header);
+            for event in decoder.decode_frame(linktype, timestamp, packet.data) {
+                tx.send(CaptureEvent {
+                    interface: Arc::clone(&self.interface),
+                    event,
                 })
-                .expect("sniffer: failed to send ipv6 packet");
-            } else {
-                // panic!("unsupported ethertype {:?}", frame.ethertype);
+                .expect("sniffer: failed to send event");
             }
-        })
-        .unwrap();
+        });
+
+        if let Err(error) = result {
+            let _ = tx.send(CaptureEvent {
+                interface: Arc::clone(&self.interface),
+                event: SnifferEvent::CaptureError(error),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pacer_spaces_packets_as_captured() {
+        let mut pacer = Pacer::new();
+        let start = Instant::now();
+        let first = Duration::from_secs(1_700_000_000);
+
+        assert_eq!(pacer.delay(first, start), None);
+        assert_eq!(
+            pacer.delay(first + Duration::from_millis(1500), start),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(
+            pacer.delay(
+                first + Duration::from_millis(1500),
+                start + Duration::from_millis(500)
+            ),
+            Some(Duration::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn pacer_does_not_wait_for_late_packets() {
+        let mut pacer = Pacer::new();
+        let start = Instant::now();
+        let first = Duration::from_secs(1_700_000_000);
+
+        pacer.delay(first, start);
+        assert_eq!(
+            pacer.delay(
+                first + Duration::from_secs(1),
+                start + Duration::from_secs(2)
+            ),
+            None
+        );
+
+        // Timestamps that go backwards are delivered right away
+        assert_eq!(pacer.delay(first - Duration::from_secs(1), start), None);
     }
 }
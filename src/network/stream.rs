@@ -0,0 +1,314 @@
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use crate::network::tcp::{TcpFlags, TcpSegment};
+
+// Streams without traffic for this long are dropped
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Upper bound on the data buffered for a single stream, in order or not
+const DEFAULT_BUFFER_LIMIT: usize = 256 * 1024;
+
+// Upper bound on the streams tracked at once, the least recently seen one is dropped
+// to make room for a new one
+const DEFAULT_MAX_STREAMS: usize = 4096;
+
+// How often idle and closed streams are looked for, in capture time
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Identifies one direction of a TCP connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FlowKey {
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+}
+
+/// The in order data of a stream that the consumer has not taken yet.
+#[derive(Debug, Default)]
+pub struct StreamData {
+    pub bytes: Vec<u8>,
+
+    // Length of the start of `bytes` that the consumer has already searched, so that
+    // a search for a delimiter can resume there once more data arrives
+    pub scanned: usize,
+}
+
+impl StreamData {
+    /// Takes the first `length` bytes, which the consumer is done with.
+    pub fn consume(&mut self, length: usize) {
+        self.bytes.drain(..length);
+        self.scanned = self.scanned.saturating_sub(length);
+    }
+}
+
+#[derive(Debug)]
+struct Stream {
+    // Sequence number of the first data byte, offsets below are relative to it
+    initial_seq: u32,
+
+    // Offset of the next byte expected in order
+    next_offset: u32,
+
+    // In order data that the consumer has not taken yet
+    data: StreamData,
+
+    // Segments that arrived ahead of a gap, keyed by offset
+    out_of_order: BTreeMap<u32, Vec<u8>>,
+    out_of_order_size: usize,
+
+    last_seen: Duration,
+
+    // Set once a FIN or RST has been seen, the stream is dropped at the next expiry
+    closed: bool,
+}
+
+impl Stream {
+    fn new(syn_seq: u32, timestamp: Duration) -> Self {
+        Self {
+            initial_seq: syn_seq.wrapping_add(1),
+            next_offset: 0,
+            data: StreamData::default(),
+            out_of_order: BTreeMap::new(),
+            out_of_order_size: 0,
+            last_seen: timestamp,
+            closed: false,
+        }
+    }
+
+    /// Adds the payload of a segment at the given offset, moving any out of order
+    /// data that it makes contiguous into `data`.
+    fn add(&mut self, offset: u32, payload: &[u8]) {
+        if offset > self.next_offset {
+            // Keep the longest copy of segments that arrive more than once
+            match self.out_of_order.entry(offset) {
+                Entry::Vacant(entry) => {
+                    self.out_of_order_size += payload.len();
+                    entry.insert(payload.to_vec());
+                }
+                Entry::Occupied(mut entry) if entry.get().len() < payload.len() => {
+                    self.out_of_order_size += payload.len() - entry.get().len();
+                    entry.insert(payload.to_vec());
+                }
+                Entry::Occupied(_) => (),
+            }
+            return;
+        }
+
+        self.append(offset, payload);
+        while let Some(entry) = self.out_of_order.first_entry() {
+            if *entry.key() > self.next_offset {
+                break;
+            }
+            let (offset, payload) = entry.remove_entry();
+            self.out_of_order_size -= payload.len();
+            self.append(offset, &payload);
+        }
+    }
+
+    /// Appends the part of a segment at or before `next_offset` that has not been seen yet.
+    fn append(&mut self, offset: u32, payload: &[u8]) {
+        let seen = (self.next_offset - offset) as usize;
+        if seen < payload.len() {
+            self.data.bytes.extend_from_slice(&payload[seen..]);
+            self.next_offset += (payload.len() - seen) as u32;
+        }
+    }
+
+    fn buffered(&self) -> usize {
+        self.data.bytes.len() + self.out_of_order_size
+    }
+}
+
+/// Reassembles the byte streams of TCP connections from their segments, putting
+/// out of order segments back in order and dropping retransmitted data.
+///
+/// Only streams whose SYN was captured are tracked, so that consumers always see
+/// a stream from its first byte.
+#[derive(Debug)]
+pub struct StreamReassembler {
+    streams: HashMap<FlowKey, Stream>,
+    idle_timeout: Duration,
+    buffer_limit: usize,
+    max_streams: usize,
+    last_expiry: Duration,
+}
+
+impl Default for StreamReassembler {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_IDLE_TIMEOUT,
+            DEFAULT_BUFFER_LIMIT,
+            DEFAULT_MAX_STREAMS,
+        )
+    }
+}
+
+impl StreamReassembler {
+    pub fn new(idle_timeout: Duration, buffer_limit: usize, max_streams: usize) -> Self {
+        Self {
+            streams: HashMap::new(),
+            idle_timeout,
+            buffer_limit,
+            max_streams,
+            last_expiry: Duration::ZERO,
+        }
+    }
+
+    /// Adds a segment captured at `timestamp` to the stream identified by `key`.
+    ///
+    /// Returns the stream's in order data that has not been taken yet, which the
+    /// caller drains as it consumes it. Returns `None` if the stream is not tracked,
+    /// or was dropped for buffering more than the limit.
+    pub fn add(
+        &mut self,
+        key: FlowKey,
+        segment: &TcpSegment,
+        timestamp: Duration,
+    ) -> Option<&mut StreamData> {
+        if timestamp.saturating_sub(self.last_expiry) >= EXPIRY_INTERVAL {
+            self.expire(timestamp);
+        }
+
+        // A repeated SYN starts the stream over, e.g. when a port is reused
+        if segment.flags.contains(TcpFlags::SYN) {
+            if !self.streams.contains_key(&key) && self.streams.len() >= self.max_streams {
+                self.evict_least_recent();
+            }
+            self.streams
+                .insert(key, Stream::new(segment.seq, timestamp));
+        }
+
+        let stream = self.streams.get_mut(&key)?;
+        stream.last_seen = timestamp;
+        if segment.flags.contains(TcpFlags::FIN) || segment.flags.contains(TcpFlags::RST) {
+            stream.closed = true;
+        }
+
+        if !segment.data.is_empty() {
+            let offset = segment.seq.wrapping_sub(stream.initial_seq);
+
+            // Data before the start of the stream wraps around to a huge offset
+            if offset < u32::MAX / 2 {
+                stream.add(offset, segment.data);
+            }
+        }
+
+        if stream.buffered() > self.buffer_limit {
+            self.streams.remove(&key);
+            return None;
+        }
+        self.streams.get_mut(&key).map(|stream| &mut stream.data)
+    }
+
+    /// Stops tracking a stream, e.g. once the consumer has seen all it needs.
+    pub fn remove(&mut self, key: &FlowKey) {
+        self.streams.remove(key);
+    }
+
+    fn evict_least_recent(&mut self) {
+        let least_recent = self
+            .streams
+            .iter()
+            .min_by_key(|(_, stream)| stream.last_seen)
+            .map(|(key, _)| *key);
+        if let Some(key) = least_recent {
+            self.streams.remove(&key);
+        }
+    }
+
+    /// Drops the streams that have been closed or idle for longer than the timeout.
+    fn expire(&mut self, timestamp: Duration) {
+        let idle_timeout = self.idle_timeout;
+        self.streams.retain(|_, stream| {
+            !stream.closed && timestamp.saturating_sub(stream.last_seen) < idle_timeout
+        });
+        self.last_expiry = timestamp;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::tcp;
+
+    fn key(port: u16) -> FlowKey {
+        FlowKey {
+            src: SocketAddr::from(([192, 0, 2, 10], port)),
+            dst: SocketAddr::from(([192, 0, 2, 1], 53)),
+        }
+    }
+
+    /// Encodes a TCP segment without options, to be parsed into a [`TcpSegment`].
+    fn segment(seq: u32, flags: u8, data: &[u8]) -> Vec<u8> {
+        let mut segment = vec![0xc3, 0x50, 0, 53];
+        segment.extend_from_slice(&seq.to_be_bytes());
+        segment.extend_from_slice(&[0, 0, 0, 0, 5 << 4, flags, 0xff, 0xff, 0, 0, 0, 0]);
+        segment.extend_from_slice(data);
+        segment
+    }
+
+    fn add(
+        reassembler: &mut StreamReassembler,
+        port: u16,
+        segment: &[u8],
+        seconds: u64,
+    ) -> Option<Vec<u8>> {
+        let segment = tcp::parse_tcp_segment(segment).unwrap();
+        reassembler
+            .add(key(port), &segment, Duration::from_secs(seconds))
+            .map(|data| data.bytes.clone())
+    }
+
+    #[test]
+    fn drops_least_recent_stream_when_full() {
+        let mut reassembler = StreamReassembler::new(DEFAULT_IDLE_TIMEOUT, DEFAULT_BUFFER_LIMIT, 2);
+        add(
+            &mut reassembler,
+            50000,
+            &segment(1000, TcpFlags::SYN, &[]),
+            0,
+        );
+        add(
+            &mut reassembler,
+            50001,
+            &segment(1000, TcpFlags::SYN, &[]),
+            1,
+        );
+        add(
+            &mut reassembler,
+            50000,
+            &segment(1001, TcpFlags::ACK, b"a"),
+            2,
+        );
+
+        add(
+            &mut reassembler,
+            50002,
+            &segment(1000, TcpFlags::SYN, &[]),
+            3,
+        );
+        assert_eq!(reassembler.streams.len(), 2);
+
+        // The stream on 50001 had not been seen for the longest
+        assert_eq!(
+            add(
+                &mut reassembler,
+                50001,
+                &segment(1001, TcpFlags::ACK, b"b"),
+                3
+            ),
+            None
+        );
+        assert_eq!(
+            add(
+                &mut reassembler,
+                50000,
+                &segment(1002, TcpFlags::ACK, b"b"),
+                3
+            ),
+            Some(b"ab".to_vec())
+        );
+    }
+}
@@ -0,0 +1,277 @@
+use crate::network::error::{DecodeError, DecodeResult};
+
+// TCP: https://datatracker.ietf.org/doc/html/rfc9293#section-3.1
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct TcpSegment<'a> {
+    pub src: u16, // 16 bits
+    pub dst: u16, // 16 bits
+
+    /* The sequence number of the first data octet in this segment */
+    pub seq: u32, // 32 bits
+
+    /* If the ACK control bit is set, this field contains the value of the
+    next sequence number the sender of the segment is expecting to receive */
+    pub ack: u32, // 32 bits
+
+    /* The number of 32-bit words in the TCP header */
+    data_offset: u8, // 4 bits
+
+    pub flags: TcpFlags, // 8 bits
+
+    /* The number of data octets the sender is willing to accept */
+    pub window: u16, // 16 bits
+
+    checksum: u16, // 16 bits
+
+    urgent_pointer: u16, // 16 bits
+
+    pub options: Vec<TcpOption>, // variable bits, given by data offset
+
+    pub data: &'a [u8],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcpFlags(pub u8);
+
+impl TcpFlags {
+    pub const FIN: u8 = 0x01;
+    pub const SYN: u8 = 0x02;
+    pub const RST: u8 = 0x04;
+    pub const PSH: u8 = 0x08;
+    pub const ACK: u8 = 0x10;
+    pub const URG: u8 = 0x20;
+    pub const ECE: u8 = 0x40;
+    pub const CWR: u8 = 0x80;
+
+    pub fn contains(&self, flag: u8) -> bool {
+        self.0 & flag == flag
+    }
+}
+
+// https://www.iana.org/assignments/tcp-parameters/tcp-parameters.xhtml
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TcpOption {
+    MaximumSegmentSize(u16),
+    WindowScale(u8),
+    SackPermitted,
+    Sack(Vec<(u32, u32)>),
+    Timestamps { value: u32, echo_reply: u32 },
+    Unknown { kind: u8, data: Vec<u8> },
+}
+
+pub fn parse_tcp_segment(data: &[u8]) -> DecodeResult<TcpSegment<'_>> {
+    DecodeError::check_len("tcp", data, 20)?;
+
+    let src = u16::from_be_bytes([data[0], data[1]]);
+    let dst = u16::from_be_bytes([data[2], data[3]]);
+    let seq = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+    let ack = u32::from_be_bytes([data[8], data[9], data[10], data[11]]);
+    let data_offset = data[12] >> 4;
+    let flags = TcpFlags(data[13]);
+    let window = u16::from_be_bytes([data[14], data[15]]);
+    let checksum = u16::from_be_bytes([data[16], data[17]]);
+    let urgent_pointer = u16::from_be_bytes([data[18], data[19]]);
+
+    let header_length = data_offset as usize * 4;
+    if header_length < 20 {
+        return Err(DecodeError::BadLength {
+            layer: "tcp",
+            length: header_length,
+        });
+    }
+    DecodeError::check_len("tcp", data, header_length)?;
+    let options = parse_tcp_options(&data[20..header_length])?;
+
+    Ok(TcpSegment {
+        src,
+        dst,
+        seq,
+        ack,
+        data_offset,
+        flags,
+        window,
+        checksum,
+        urgent_pointer,
+        options,
+        data: &data[header_length..],
+    })
+}
+
+fn parse_tcp_options(data: &[u8]) -> DecodeResult<Vec<TcpOption>> {
+    let mut options = Vec::new();
+    let mut index = 0;
+
+    while index < data.len() {
+        let kind = data[index];
+        match kind {
+            // End of option list
+            0 => break,
+            // No-operation, used for padding between options
+            1 => {
+                index += 1;
+                continue;
+            }
+            _ => (),
+        }
+
+        // All remaining options carry a length octet that includes the kind and length octets
+        DecodeError::check_len("tcp option", &data[index..], 2)?;
+        let length = data[index + 1] as usize;
+        if length < 2 || index + length > data.len() {
+            return Err(DecodeError::BadLength {
+                layer: "tcp option",
+                length,
+            });
+        }
+        let value = &data[index + 2..index + length];
+
+        let option = match (kind, value.len()) {
+            (2, 2) => TcpOption::MaximumSegmentSize(u16::from_be_bytes([value[0], value[1]])),
+            (3, 1) => TcpOption::WindowScale(value[0]),
+            (4, 0) => TcpOption::SackPermitted,
+            (5, n) if n % 8 == 0 => TcpOption::Sack(
+                value
+                    .chunks_exact(8)
+                    .map(|block| {
+                        (
+                            u32::from_be_bytes([block[0], block[1], block[2], block[3]]),
+                            u32::from_be_bytes([block[4], block[5], block[6], block[7]]),
+                        )
+                    })
+                    .collect(),
+            ),
+            (8, 8) => TcpOption::Timestamps {
+                value: u32::from_be_bytes([value[0], value[1], value[2], value[3]]),
+                echo_reply: u32::from_be_bytes([value[4], value[5], value[6], value[7]]),
+            },
+            _ => TcpOption::Unknown {
+                kind,
+                data: value.to_vec(),
+            },
+        };
+        options.push(option);
+        index += length;
+    }
+
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encodes a SYN segment carrying `options`, padded to a multiple of four bytes.
+    fn segment(options: &[u8]) -> Vec<u8> {
+        let mut options = options.to_vec();
+        options.resize(options.len().div_ceil(4) * 4, 0);
+
+        let data_offset = (20 + options.len()) / 4;
+        let mut segment = vec![0xc3, 0x50, 0, 80, 0, 0, 0x03, 0xe8, 0, 0, 0, 0];
+        segment.extend_from_slice(&[(data_offset as u8) << 4, TcpFlags::SYN, 0xff, 0xff]);
+        segment.extend_from_slice(&[0, 0, 0, 0]);
+        segment.extend_from_slice(&options);
+        segment
+    }
+
+    fn options(options: &[u8]) -> DecodeResult<Vec<TcpOption>> {
+        parse_tcp_segment(&segment(options)).map(|segment| segment.options)
+    }
+
+    #[test]
+    fn parses_syn_options() {
+        // As sent by Linux: MSS, SACK permitted, timestamps, NOP and window scale
+        let data = [
+            2, 4, 0x05, 0xb4, 4, 2, 8, 10, 0, 0, 0x12, 0x34, 0, 0, 0, 0, 1, 3, 3, 7,
+        ];
+        assert_eq!(
+            options(&data),
+            Ok(vec![
+                TcpOption::MaximumSegmentSize(1460),
+                TcpOption::SackPermitted,
+                TcpOption::Timestamps {
+                    value: 0x1234,
+                    echo_reply: 0
+                },
+                TcpOption::WindowScale(7),
+            ])
+        );
+    }
+
+    #[test]
+    fn parses_sack_blocks() {
+        let mut data = vec![1, 1, 5, 18];
+        for edge in [1000u32, 2000, 3000, 4000] {
+            data.extend_from_slice(&edge.to_be_bytes());
+        }
+        assert_eq!(
+            options(&data),
+            Ok(vec![TcpOption::Sack(vec![(1000, 2000), (3000, 4000)])])
+        );
+    }
+
+    #[test]
+    fn stops_at_end_of_option_list() {
+        assert_eq!(
+            options(&[3, 3, 7, 0, 2, 4, 0x05, 0xb4]),
+            Ok(vec![TcpOption::WindowScale(7)])
+        );
+    }
+
+    #[test]
+    fn keeps_unknown_and_malformed_options() {
+        assert_eq!(
+            options(&[30, 4, 0xab, 0xcd, 2, 3, 0x05]),
+            Ok(vec![
+                TcpOption::Unknown {
+                    kind: 30,
+                    data: vec![0xab, 0xcd]
+                },
+                TcpOption::Unknown {
+                    kind: 2,
+                    data: vec![0x05]
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_options() {
+        // Length running past the end of the header
+        assert_eq!(
+            options(&[2, 8, 0x05, 0xb4]),
+            Err(DecodeError::BadLength {
+                layer: "tcp option",
+                length: 8
+            })
+        );
+        // Length too short to cover the kind and length octets
+        assert_eq!(
+            options(&[1, 1, 1, 3, 1]),
+            Err(DecodeError::BadLength {
+                layer: "tcp option",
+                length: 1
+            })
+        );
+        // Length octet missing at the end of the header
+        assert_eq!(
+            options(&[1, 1, 1, 3]),
+            Err(DecodeError::Truncated {
+                layer: "tcp option",
+                needed: 2,
+                available: 1
+            })
+        );
+        // Header longer than the segment
+        let mut data = segment(&[2, 4, 0x05, 0xb4]);
+        data.truncate(22);
+        assert_eq!(
+            parse_tcp_segment(&data).map(|segment| segment.options),
+            Err(DecodeError::Truncated {
+                layer: "tcp",
+                needed: 24,
+                available: 22
+            })
+        );
+    }
+}
@@ -0,0 +1,311 @@
+use crate::network::error::{DecodeError, DecodeResult};
+
+// Ports of the protocols that start with a TLS handshake: HTTPS, SMTPS, LDAPS, DNS
+// over TLS, FTPS, IMAPS, POP3S, SIP over TLS and the alternative HTTPS port
+pub const TLS_PORTS: [u16; 9] = [443, 465, 636, 853, 990, 993, 995, 5061, 8443];
+
+// https://datatracker.ietf.org/doc/html/rfc8446#section-5.1
+const CONTENT_TYPE_HANDSHAKE: u8 = 22;
+
+// Records carry at most 2^14 bytes of plaintext, plus some slack for older versions
+const MAX_RECORD_LENGTH: usize = (1 << 14) + 2048;
+
+// https://datatracker.ietf.org/doc/html/rfc8446#section-4
+pub const HANDSHAKE_CLIENT_HELLO: u8 = 1;
+
+// https://www.iana.org/assignments/tls-extensiontype-values/tls-extensiontype-values.xhtml
+const EXTENSION_SERVER_NAME: u16 = 0; // RFC 6066
+const EXTENSION_ALPN: u16 = 16; // RFC 7301
+
+// https://datatracker.ietf.org/doc/html/rfc6066#section-3
+const SERVER_NAME_HOST_NAME: u8 = 0;
+
+/// The fields of a ClientHello that name the server the client is connecting to.
+// https://datatracker.ietf.org/doc/html/rfc8446#section-4.1.2
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientHello {
+    /* 0x0303 for TLS 1.2 and 1.3, which is negotiated in an extension instead */
+    pub legacy_version: u16, // 16 bits
+
+    /* The host name from the server name indication extension. With encrypted
+    client hello this is the public name of the client-facing server. */
+    pub server_name: Option<String>,
+
+    /* The application protocols offered by the client, e.g. h2 and http/1.1 */
+    pub alpn: Vec<String>,
+}
+
+/// Whether `data` starts with the header of a TLS handshake record.
+pub fn is_handshake_record(data: &[u8]) -> bool {
+    // The major version is 3 for SSL 3.0 and every version of TLS
+    data.len() >= 2 && data[0] == CONTENT_TYPE_HANDSHAKE && data[1] == 3
+}
+
+/// Joins the handshake records at the start of `data` until they hold the first
+/// handshake message, which may be split across records (RFC 8446 section 5.1).
+///
+/// Returns the message with its header, or `None` if `data` ends before it does.
+pub fn read_handshake_message(data: &[u8]) -> DecodeResult<Option<Vec<u8>>> {
+    let mut message = Vec::new();
+    let mut index = 0;
+
+    loop {
+        if message.len() >= 4 {
+            let length = read_u24(&message, 1) as usize;
+            if message.len() >= 4 + length {
+                message.truncate(4 + length);
+                return Ok(Some(message));
+            }
+        }
+
+        if data.len() < index + 5 {
+            return Ok(None);
+        }
+
+        // A record of another type before the end of the message cuts it short
+        if !is_handshake_record(&data[index..]) {
+            return Err(DecodeError::Truncated {
+                layer: "tls handshake",
+                needed: message.len() + 1,
+                available: message.len(),
+            });
+        }
+
+        let length = u16::from_be_bytes([data[index + 3], data[index + 4]]) as usize;
+        if length > MAX_RECORD_LENGTH {
+            return Err(DecodeError::BadLength {
+                layer: "tls record",
+                length,
+            });
+        }
+        if data.len() < index + 5 + length {
+            return Ok(None);
+        }
+
+        message.extend_from_slice(&data[index + 5..index + 5 + length]);
+        index += 5 + length;
+    }
+}
+
+/// Parses a ClientHello handshake message, starting at its handshake header.
+pub fn parse_client_hello(message: &[u8]) -> DecodeResult<ClientHello> {
+    DecodeError::check_len("tls client hello", message, 4)?;
+    if message[0] != HANDSHAKE_CLIENT_HELLO {
+        return Err(DecodeError::BadMagic {
+            layer: "tls client hello",
+        });
+    }
+
+    let length = read_u24(message, 1) as usize;
+    DecodeError::check_len("tls client hello", message, 4 + length)?;
+    let mut reader = Reader::new(&message[4..4 + length]);
+
+    let legacy_version = reader.read_u16()?;
+    reader.read_bytes(32)?; // random
+    reader.read_vector8()?; // legacy session id
+    reader.read_vector16()?; // cipher suites
+    reader.read_vector8()?; // legacy compression methods
+
+    let mut hello = ClientHello {
+        legacy_version,
+        server_name: None,
+        alpn: Vec::new(),
+    };
+
+    // Hellos from before TLS 1.2 may end without extensions
+    if reader.is_empty() {
+        return Ok(hello);
+    }
+
+    let mut extensions = Reader::new(reader.read_vector16()?);
+    while !extensions.is_empty() {
+        let extension_type = extensions.read_u16()?;
+        let mut data = Reader::new(extensions.read_vector16()?);
+
+        match extension_type {
+            EXTENSION_SERVER_NAME => {
+                let mut names = Reader::new(data.read_vector16()?);
+                while !names.is_empty() {
+                    let name_type = names.read_u8()?;
+                    let name = names.read_vector16()?;
+                    if name_type == SERVER_NAME_HOST_NAME {
+                        hello.server_name = Some(String::from_utf8_lossy(name).into_owned());
+                    }
+                }
+            }
+            EXTENSION_ALPN => {
+                let mut protocols = Reader::new(data.read_vector16()?);
+                while !protocols.is_empty() {
+                    let protocol = protocols.read_vector8()?;
+                    hello
+                        .alpn
+                        .push(String::from_utf8_lossy(protocol).into_owned());
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Ok(hello)
+}
+
+fn read_u24(data: &[u8], index: usize) -> u32 {
+    u32::from_be_bytes([0, data[index], data[index + 1], data[index + 2]])
+}
+
+/// Reads the fields of a handshake message in order, checking that each one fits.
+struct Reader<'a> {
+    data: &'a [u8],
+    index: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, index: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.index >= self.data.len()
+    }
+
+    fn read_bytes(&mut self, length: usize) -> DecodeResult<&'a [u8]> {
+        DecodeError::check_len("tls client hello", self.data, self.index + length)?;
+        let bytes = &self.data[self.index..self.index + length];
+        self.index += length;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> DecodeResult<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> DecodeResult<u16> {
+        let bytes = self.read_bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Reads a vector preceded by its length as one byte.
+    fn read_vector8(&mut self) -> DecodeResult<&'a [u8]> {
+        let length = self.read_u8()? as usize;
+        self.read_bytes(length)
+    }
+
+    /// Reads a vector preceded by its length as two bytes.
+    fn read_vector16(&mut self) -> DecodeResult<&'a [u8]> {
+        let length = self.read_u16()? as usize;
+        self.read_bytes(length)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// The ClientHello that a QUIC client sends to example.com in RFC 9001 appendix
+    /// A.2, with a server name and ALPN extension.
+    pub(crate) fn client_hello() -> Vec<u8> {
+        let hex = concat!(
+            "010000ed0303ebf8fa56f12939b9584a3896472ec40bb863cfd3e86804fe3a47",
+            "f06a2b69484c00000413011302010000c000000010000e00000b6578616d706c",
+            "652e636f6dff01000100000a00080006001d0017001800100007000504616c70",
+            "6e000500050100000000003300260024001d00209370b2c9caa47fbabaf4559f",
+            "edba753de171fa71f50f1ce15d43e994ec74d748002b0003020304000d001000",
+            "0e0403050306030203080408050806002d00020101001c000240010039003204",
+            "08ffffffffffffffff05048000ffff07048000ffff0801100104800075300901",
+            "100f088394c8f03e51570806048000ffff",
+        );
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// Splits a handshake message into handshake records of at most `size` bytes.
+    pub(crate) fn handshake_records(message: &[u8], size: usize) -> Vec<u8> {
+        let mut records = Vec::new();
+        for fragment in message.chunks(size) {
+            records.extend_from_slice(&[CONTENT_TYPE_HANDSHAKE, 3, 1]);
+            records.extend_from_slice(&(fragment.len() as u16).to_be_bytes());
+            records.extend_from_slice(fragment);
+        }
+        records
+    }
+
+    #[test]
+    fn parses_client_hello_with_server_name_and_alpn() {
+        let records = handshake_records(&client_hello(), MAX_RECORD_LENGTH);
+        let message = read_handshake_message(&records).unwrap().unwrap();
+        assert_eq!(message, client_hello());
+
+        assert_eq!(
+            parse_client_hello(&message).unwrap(),
+            ClientHello {
+                legacy_version: 0x0303,
+                server_name: Some("example.com".to_string()),
+                alpn: vec!["alpn".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn joins_client_hello_split_across_records() {
+        // The first record is too short to hold the handshake header on its own
+        let records = handshake_records(&client_hello(), 3);
+        for end in 0..records.len() {
+            assert_eq!(read_handshake_message(&records[..end]), Ok(None));
+        }
+
+        // Data after the end of the message is left for the caller
+        let mut data = records.clone();
+        data.extend_from_slice(&[CONTENT_TYPE_HANDSHAKE, 3, 3, 0, 0]);
+        assert_eq!(read_handshake_message(&data), Ok(Some(client_hello())));
+    }
+
+    #[test]
+    fn rejects_message_cut_short_by_another_record() {
+        let hello = client_hello();
+        let mut data = handshake_records(&hello[..100], 100);
+        // A change cipher spec record
+        data.extend_from_slice(&[20, 3, 3, 0, 1, 1]);
+
+        assert!(matches!(
+            read_handshake_message(&data),
+            Err(DecodeError::Truncated { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_client_hello() {
+        let hello = client_hello();
+        for end in [0, 3, 4, 40, hello.len() - 1] {
+            assert!(matches!(
+                parse_client_hello(&hello[..end]),
+                Err(DecodeError::Truncated { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_extension_longer_than_its_list() {
+        let mut hello = client_hello();
+        // The server name extension, its length, then the length of the name list
+        let position = hello
+            .windows(6)
+            .position(|window| window == [0, 0, 0, 0x10, 0, 0x0e])
+            .unwrap();
+
+        hello[position + 3] = 0xff;
+        assert!(matches!(
+            parse_client_hello(&hello),
+            Err(DecodeError::Truncated { .. })
+        ));
+
+        // A name list that overruns its extension
+        hello[position + 3] = 0x10;
+        hello[position + 5] = 0x0f;
+        assert!(matches!(
+            parse_client_hello(&hello),
+            Err(DecodeError::Truncated { .. })
+        ));
+    }
+}
@@ -1,15 +1,16 @@
-use crate::app::App;
+use std::time::Duration;
+
+use crate::app::{App, InterfacePicker, Tab};
+use crate::network::dhcp;
+use crate::network::dns::{self, EdnsOption, Rcode};
+use crate::network::dns_tracker::{DnsTransaction, LatencyStats};
+use crate::network::interface;
 
 use ratatui::{
-    buffer::Buffer,
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Style, Stylize},
-    symbols::border,
-    text::{Line, Span, Text},
-    widgets::{
-        block::{Position, Title},
-        Block, Row, Table,
-    },
+    text::{Line, Span},
+    widgets::{Row, Table, Tabs},
     Frame,
 };
 
@@ -20,14 +21,66 @@ pub fn render(app: &mut App, frame: &mut Frame) {
         .constraints(vec![Constraint::Min(0), Constraint::Length(1)])
         .split(frame.size());
 
+    if let Some(picker) = app.interface_picker.as_mut() {
+        render_interface_picker(picker, areas[0], frame);
+        render_picker_bottom_bar(areas[1], frame);
+        return;
+    }
+
+    let main_areas = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(vec![Constraint::Length(2), Constraint::Min(0)])
+        .split(areas[0]);
+
+    let selected_tab = Tab::ALL.iter().position(|&tab| tab == app.tab);
+    let tabs = Tabs::new(Tab::ALL.iter().map(|tab| tab.title()))
+        .select(selected_tab.unwrap_or(0))
+        .highlight_style(Style::new().bg(Color::LightCyan).fg(Color::Black));
+    frame.render_widget(tabs, main_areas[0]);
+
+    match app.tab {
+        Tab::Hosts => render_hosts(app, main_areas[1], frame),
+        Tab::DnsLog => render_dns_log(app, main_areas[1], frame),
+        Tab::Latency => render_latency(app, main_areas[1], frame),
+        Tab::Leases => render_leases(app, main_areas[1], frame),
+        Tab::Http => render_http(app, main_areas[1], frame),
+    }
+    render_bottom_bar(app, areas[1], frame);
+}
+
+/// Renders the table of hosts seen in the captured traffic.
+pub fn render_hosts(app: &mut App, area: Rect, frame: &mut Frame) {
     let rows = app
         .entries_to_render()
         .map(|entry| {
+            let services = entry
+                .info
+                .services
+                .iter()
+                .map(|service| service.to_string())
+                .collect::<Vec<_>>()
+                .join(" ");
+            let interfaces = entry
+                .info
+                .interfaces
+                .iter()
+                .map(|interface| interface.as_ref())
+                .collect::<Vec<_>>()
+                .join(",");
+            let device = entry.device.unwrap_or_default().to_string();
+            let alpn = entry.alpn.map(|alpn| alpn.join(",")).unwrap_or_default();
+
             if let Some(domain) = entry.domain {
                 Row::new(vec![
                     Line::styled(entry.ip.to_string(), Color::White),
                     Line::styled(entry.info.num_packets.to_string(), Color::Green)
                         .alignment(Alignment::Right),
+                    Line::styled(entry.info.num_bad_checksums.to_string(), Color::Red)
+                        .alignment(Alignment::Right),
+                    Line::styled(interfaces, Color::White),
+                    Line::styled(services, Color::White),
+                    Line::styled(device, Color::White),
+                    Line::styled(alpn, Color::White),
                     Line::styled(domain.clone(), Color::White),
                 ])
             } else {
@@ -35,6 +88,12 @@ pub fn render(app: &mut App, frame: &mut Frame) {
                     Line::styled(entry.ip.to_string(), Color::DarkGray),
                     Line::styled(entry.info.num_packets.to_string(), Color::DarkGray)
                         .alignment(Alignment::Right),
+                    Line::styled(entry.info.num_bad_checksums.to_string(), Color::DarkGray)
+                        .alignment(Alignment::Right),
+                    Line::styled(interfaces, Color::DarkGray),
+                    Line::styled(services, Color::DarkGray),
+                    Line::styled(device, Color::DarkGray),
+                    Line::styled(alpn, Color::DarkGray),
                     Line::styled(entry.ip.to_string(), Color::DarkGray),
                 ])
             }
@@ -45,41 +104,574 @@ pub fn render(app: &mut App, frame: &mut Frame) {
     let widths = [
         Constraint::Length(40),
         Constraint::Length(6),
+        Constraint::Length(5),
+        Constraint::Length(10),
+        Constraint::Length(24),
+        Constraint::Length(14),
+        Constraint::Length(12),
+        Constraint::Min(20),
+    ];
+    let table = Table::new(rows, widths)
+        .column_spacing(2)
+        .header(
+            Row::new(vec![
+                " IP", "# PKTS", "# BAD", "IFACE", "SERVICES", "DEVICE", "ALPN", "HOST",
+            ])
+            .style(Style::new().bg(Color::Green).fg(Color::Black))
+            .bottom_margin(1),
+        )
+        .highlight_style(Style::new().bg(Color::LightCyan).fg(Color::Black));
+
+    frame.render_stateful_widget(table, area, &mut app.state);
+}
+
+/// Renders the DNS transactions seen on the wire, newest first, along with the
+/// filters applied to them.
+pub fn render_dns_log(app: &mut App, area: Rect, frame: &mut Frame) {
+    let areas = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(vec![Constraint::Length(2), Constraint::Min(0)])
+        .split(area);
+
+    let rows = app.dns_log_entries().map(dns_log_row).collect::<Vec<_>>();
+
+    let cursor = if app.editing_log_filter { "_" } else { "" };
+    frame.render_widget(
+        Line::from(vec![
+            Span::from(" / ").fg(Color::White),
+            Span::from(format!("Name: {}{} ", app.log_name_filter, cursor))
+                .bg(Color::LightCyan)
+                .fg(Color::Black),
+            Span::from("  R ").fg(Color::White),
+            Span::from(format!("Rcode: {} ", app.log_rcode_filter))
+                .bg(Color::LightCyan)
+                .fg(Color::Black),
+            Span::from(format!(
+                "  {} of {} transactions",
+                rows.len(),
+                app.dns_pending.len() + app.dns_log.len()
+            ))
+            .fg(Color::Gray),
+        ]),
+        areas[0],
+    );
+
+    let widths = [
+        Constraint::Length(13),
+        Constraint::Length(16),
+        Constraint::Length(16),
+        Constraint::Length(20),
+        Constraint::Length(32),
+        Constraint::Length(6),
+        Constraint::Length(9),
+        Constraint::Length(20),
+        Constraint::Length(10),
+        Constraint::Length(10),
         Constraint::Min(20),
     ];
     let table = Table::new(rows, widths)
         .column_spacing(2)
         .header(
-            Row::new(vec![" IP", "# PKTS", "HOST"])
+            Row::new(vec![
+                " TIME", "CLIENT", "RESOLVER", "ECS", "NAME", "TYPE", "RCODE", "EDE", "LATENCY",
+                "TTL", "ANSWERS",
+            ])
+            .style(Style::new().bg(Color::Green).fg(Color::Black))
+            .bottom_margin(1),
+        )
+        .highlight_style(Style::new().bg(Color::LightCyan).fg(Color::Black));
+
+    frame.render_stateful_widget(table, areas[1], &mut app.log_state);
+}
+
+fn dns_log_row(transaction: &DnsTransaction) -> Row<'static> {
+    // The client subnet a query revealed upstream, e.g. `192.0.2.0/24`
+    let client_subnet = match &transaction.client_subnet {
+        Some(EdnsOption::ClientSubnet {
+            address,
+            source_prefix,
+            ..
+        }) => format!("{}/{}", address, source_prefix),
+        _ => String::new(),
+    };
+
+    // Why the resolver failed or filtered the query, e.g. `Blocked: ads`
+    let extended_errors = transaction
+        .response
+        .iter()
+        .flat_map(|response| response.extended_errors())
+        .filter_map(|option| match option {
+            EdnsOption::ExtendedError {
+                info_code,
+                extra_text,
+            } => {
+                let name = dns::extended_error_name(*info_code)
+                    .map(String::from)
+                    .unwrap_or_else(|| format!("EDE{}", info_code));
+                Some(if extra_text.is_empty() {
+                    name
+                } else {
+                    format!("{}: {}", name, extra_text)
+                })
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let (rcode, rcode_color, ttls, answers) = match &transaction.response {
+        Some(response) => {
+            let rcode = response.rcode();
+            let mut ttls = Vec::new();
+            for answer in &response.answers {
+                if !ttls.contains(&answer.ttl) {
+                    ttls.push(answer.ttl);
+                }
+            }
+            let answers = response
+                .answers
+                .iter()
+                .map(|answer| format!("{} {}", dns::type_name(answer.rtype), answer.rdata))
+                .collect::<Vec<_>>();
+            let color = if rcode == Rcode::NoError {
+                Color::Green
+            } else {
+                Color::Red
+            };
+            (
+                rcode.to_string(),
+                color,
+                ttls.iter()
+                    .map(|ttl| ttl.to_string())
+                    .collect::<Vec<_>>()
+                    .join(","),
+                answers.join(", "),
+            )
+        }
+        None if transaction.pending => (
+            "PENDING".to_string(),
+            Color::Yellow,
+            String::new(),
+            String::new(),
+        ),
+        None => (
+            "TIMEOUT".to_string(),
+            Color::Red,
+            String::new(),
+            String::new(),
+        ),
+    };
+
+    Row::new(vec![
+        Line::from(format!(" {}", format_timestamp(transaction.timestamp))),
+        Line::from(transaction.client.ip().to_string()),
+        Line::from(transaction.resolver.ip().to_string()),
+        Line::styled(client_subnet, Color::Yellow),
+        Line::styled(transaction.qname.clone(), Color::White),
+        Line::from(dns::type_name(transaction.qtype)),
+        Line::styled(rcode, rcode_color),
+        Line::styled(extended_errors, Color::Red),
+        Line::from(format_latency(transaction.latency)).alignment(Alignment::Right),
+        Line::from(ttls),
+        Line::from(answers),
+    ])
+    .style(Style::new().fg(Color::Gray))
+}
+
+/// Formats a capture timestamp as the UTC time of day, e.g. `13:45:07.291`.
+fn format_timestamp(timestamp: Duration) -> String {
+    let seconds = timestamp.as_secs() % (24 * 60 * 60);
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        timestamp.subsec_millis()
+    )
+}
+
+/// Renders the DNS latency of each resolver, and of each queried domain slowest first.
+pub fn render_latency(app: &mut App, area: Rect, frame: &mut Frame) {
+    let areas = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(vec![
+            Constraint::Length(app.dns_tracker.resolvers.len() as u16 + 3),
+            Constraint::Min(0),
+        ])
+        .split(area);
+
+    let widths = [
+        Constraint::Length(40),
+        Constraint::Length(8),
+        Constraint::Length(10),
+        Constraint::Length(10),
+        Constraint::Length(10),
+        Constraint::Length(10),
+        Constraint::Length(8),
+    ];
+    let header = |name| {
+        Row::new(vec![
+            name,
+            "QUERIES",
+            "AVG",
+            "MIN",
+            "MAX",
+            "UNANSWERED",
+            "RETRANS",
+        ])
+        .style(Style::new().bg(Color::Green).fg(Color::Black))
+        .bottom_margin(1)
+    };
+
+    let rows = app
+        .dns_tracker
+        .resolvers
+        .iter()
+        .map(|(ip, stats)| latency_row(ip.to_string(), stats))
+        .collect::<Vec<_>>();
+    let table = Table::new(rows, widths)
+        .column_spacing(2)
+        .header(header(" RESOLVER"));
+    frame.render_widget(table, areas[0]);
+
+    let rows = app
+        .domain_latencies()
+        .into_iter()
+        .map(|(domain, stats)| latency_row(domain.clone(), stats))
+        .collect::<Vec<_>>();
+    let table = Table::new(rows, widths)
+        .column_spacing(2)
+        .header(header(" DOMAIN"))
+        .highlight_style(Style::new().bg(Color::LightCyan).fg(Color::Black));
+    frame.render_stateful_widget(table, areas[1], &mut app.latency_state);
+}
+
+fn latency_row(name: String, stats: &LatencyStats) -> Row<'static> {
+    let color = if stats.unanswered > 0 {
+        Color::Red
+    } else {
+        Color::White
+    };
+    Row::new(vec![
+        Line::styled(format!(" {}", name), color),
+        Line::styled(
+            (stats.answered + stats.unanswered).to_string(),
+            Color::Green,
+        )
+        .alignment(Alignment::Right),
+        Line::from(format_latency(stats.average())).alignment(Alignment::Right),
+        Line::from(format_latency(stats.min)).alignment(Alignment::Right),
+        Line::from(format_latency(stats.max)).alignment(Alignment::Right),
+        Line::from(stats.unanswered.to_string()).alignment(Alignment::Right),
+        Line::from(stats.retransmits.to_string()).alignment(Alignment::Right),
+    ])
+    .style(Style::new().fg(Color::Gray))
+}
+
+/// Formats a latency in milliseconds, e.g. `12.3 ms`, or `-` if there is none.
+fn format_latency(latency: Option<Duration>) -> String {
+    match latency {
+        Some(latency) => format!("{:.1} ms", latency.as_secs_f64() * 1000.0),
+        None => "-".to_string(),
+    }
+}
+
+/// Renders the leases acknowledged by DHCP servers, ordered by address.
+pub fn render_leases(app: &mut App, area: Rect, frame: &mut Frame) {
+    let rows = app
+        .dhcp_leases
+        .iter()
+        .map(|(ip, lease)| {
+            Row::new(vec![
+                Line::styled(format!(" {}", ip), Color::White),
+                Line::styled(lease.hostname.clone().unwrap_or_default(), Color::White),
+                Line::from(lease.device().unwrap_or_default().to_string()),
+                Line::from(dhcp::format_client_id(&lease.client_id)),
+                Line::from(lease.server.to_string()),
+                Line::from(format_lease_time(lease.lease_time)).alignment(Alignment::Right),
+                Line::from(format_timestamp(lease.granted)),
+            ])
+            .style(Style::new().fg(Color::Gray))
+        })
+        .collect::<Vec<_>>();
+
+    let widths = [
+        Constraint::Length(40),
+        Constraint::Length(24),
+        Constraint::Length(14),
+        Constraint::Length(29),
+        Constraint::Length(16),
+        Constraint::Length(8),
+        Constraint::Min(12),
+    ];
+    let table = Table::new(rows, widths)
+        .column_spacing(2)
+        .header(
+            Row::new(vec![
+                " ADDRESS", "HOSTNAME", "DEVICE", "CLIENT", "SERVER", "LEASE", "GRANTED",
+            ])
+            .style(Style::new().bg(Color::Green).fg(Color::Black))
+            .bottom_margin(1),
+        )
+        .highlight_style(Style::new().bg(Color::LightCyan).fg(Color::Black));
+
+    frame.render_stateful_widget(table, area, &mut app.leases_state);
+}
+
+/// Renders the servers that cleartext HTTP requests were sent to, ordered by address.
+pub fn render_http(app: &mut App, area: Rect, frame: &mut Frame) {
+    let rows = app
+        .http_servers
+        .iter()
+        .map(|(ip, server)| {
+            let clients = server
+                .clients
+                .iter()
+                .map(|client| client.to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+            let user_agents = server
+                .user_agents
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", ");
+            let content_types = server
+                .content_types
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            Row::new(vec![
+                Line::styled(format!(" {}", ip), Color::White),
+                Line::styled(server.host.clone().unwrap_or_default(), Color::White),
+                Line::styled(server.num_requests.to_string(), Color::Green)
+                    .alignment(Alignment::Right),
+                Line::from(clients),
+                Line::from(server.last_request.clone()),
+                Line::from(content_types),
+                Line::from(user_agents),
+            ])
+            .style(Style::new().fg(Color::Gray))
+        })
+        .collect::<Vec<_>>();
+
+    let widths = [
+        Constraint::Length(40),
+        Constraint::Length(24),
+        Constraint::Length(6),
+        Constraint::Length(16),
+        Constraint::Length(32),
+        Constraint::Length(20),
+        Constraint::Min(20),
+    ];
+    let table = Table::new(rows, widths)
+        .column_spacing(2)
+        .header(
+            Row::new(vec![
+                " SERVER",
+                "HOST",
+                "# REQS",
+                "CLIENTS",
+                "LAST REQUEST",
+                "CONTENT TYPES",
+                "USER AGENTS",
+            ])
+            .style(Style::new().bg(Color::Green).fg(Color::Black))
+            .bottom_margin(1),
+        )
+        .highlight_style(Style::new().bg(Color::LightCyan).fg(Color::Black));
+
+    frame.render_stateful_widget(table, area, &mut app.http_state);
+}
+
+/// Formats a lease time in its largest whole units, e.g. `1d 12h` or `30m`, or `-`
+/// if there is none.
+fn format_lease_time(lease_time: Option<Duration>) -> String {
+    let Some(lease_time) = lease_time else {
+        return "-".to_string();
+    };
+    // An all-ones lease time means the lease never expires (RFC 2131 section 3.3)
+    if lease_time.as_secs() == u32::MAX as u64 {
+        return "forever".to_string();
+    }
+
+    let seconds = lease_time.as_secs();
+    let units = [
+        (seconds / 86400, "d"),
+        (seconds / 3600 % 24, "h"),
+        (seconds / 60 % 60, "m"),
+        (seconds % 60, "s"),
+    ];
+    let parts = units
+        .iter()
+        .skip_while(|(value, _)| *value == 0)
+        .take(2)
+        .filter(|(value, _)| *value != 0)
+        .map(|(value, unit)| format!("{}{}", value, unit))
+        .collect::<Vec<_>>();
+    if parts.is_empty() {
+        "0s".to_string()
+    } else {
+        parts.join(" ")
+    }
+}
+
+/// Renders the list of capture devices shown at startup when no interface was given.
+pub fn render_interface_picker(picker: &mut InterfacePicker, area: Rect, frame: &mut Frame) {
+    let areas = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(vec![Constraint::Length(2), Constraint::Min(0)])
+        .split(area);
+
+    frame.render_widget(
+        Line::styled(
+            " Select the interfaces to capture on, or press Enter to capture on the highlighted one",
+            Color::White,
+        ),
+        areas[0],
+    );
+
+    let rows = picker
+        .devices
+        .iter()
+        .enumerate()
+        .map(|(idx, device)| {
+            let color = if device.flags.is_up() {
+                Color::White
+            } else {
+                Color::DarkGray
+            };
+            let mark = if picker.marked.contains(&idx) {
+                "[x]"
+            } else {
+                "[ ]"
+            };
+            Row::new(vec![
+                Line::styled(format!(" {} {}", mark, device.name), color),
+                Line::styled(interface::describe_flags(device), color),
+                Line::styled(interface::describe_addresses(device), color),
+            ])
+        })
+        .collect::<Vec<_>>();
+
+    let widths = [
+        Constraint::Length(24),
+        Constraint::Length(28),
+        Constraint::Min(20),
+    ];
+    let table = Table::new(rows, widths)
+        .column_spacing(2)
+        .header(
+            Row::new(vec![" INTERFACE", "FLAGS", "ADDRESSES"])
                 .style(Style::new().bg(Color::Green).fg(Color::Black))
                 .bottom_margin(1),
         )
         .highlight_style(Style::new().bg(Color::LightCyan).fg(Color::Black));
 
-    frame.render_stateful_widget(table, areas[0], &mut app.state);
-    render_bottom_bar(app, areas[1], frame);
+    frame.render_stateful_widget(table, areas[1], &mut picker.state);
+}
+
+pub fn render_picker_bottom_bar(area: Rect, frame: &mut Frame) {
+    let areas = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(vec![
+            Constraint::Length(6),
+            Constraint::Length(6),
+            Constraint::Length(2),
+            Constraint::Length(4),
+            Constraint::Length(6),
+            Constraint::Length(7),
+            Constraint::Length(6),
+            Constraint::Min(0),
+        ])
+        .split(area);
+
+    frame.render_widget(Span::from("Space"), areas[0]);
+    frame.render_widget(
+        Span::from("Mark ").bg(Color::LightCyan).fg(Color::Black),
+        areas[1],
+    );
+    frame.render_widget(Span::from("A"), areas[2]);
+    frame.render_widget(
+        Span::from("All ").bg(Color::LightCyan).fg(Color::Black),
+        areas[3],
+    );
+    frame.render_widget(Span::from("Enter"), areas[4]);
+    frame.render_widget(
+        Span::from("Start ").bg(Color::LightCyan).fg(Color::Black),
+        areas[5],
+    );
+    frame.render_widget(Span::from("Esc/Q"), areas[6]);
+    frame.render_widget(
+        Line::from("Quit").bg(Color::LightCyan).fg(Color::Black),
+        areas[7],
+    );
 }
 
 #[allow(unused)]
 pub fn render_bottom_bar(app: &mut App, area: Rect, frame: &mut Frame) {
+    let interface_label = format!(
+        "Iface: {} ",
+        app.interface_filter.as_deref().unwrap_or("all")
+    );
+
     let areas = Layout::default()
         .direction(Direction::Horizontal)
         .constraints(vec![
+            Constraint::Length(4),
+            Constraint::Length(5),
             Constraint::Length(2),
             Constraint::Length(6),
+            Constraint::Length(2),
+            Constraint::Length(interface_label.len() as u16),
             Constraint::Length(6),
+            Constraint::Length(5),
             Constraint::Min(0),
         ])
         .split(area);
 
-    frame.render_widget(Span::from("C"), areas[0]);
+    frame.render_widget(Span::from("Tab"), areas[0]);
     frame.render_widget(
-        Span::from("Clear ").bg(Color::LightCyan).fg(Color::Black),
+        Span::from("View ").bg(Color::LightCyan).fg(Color::Black),
         areas[1],
     );
-    frame.render_widget(Span::from("Esc/Q"), areas[2]);
+    frame.render_widget(Span::from("C"), areas[2]);
     frame.render_widget(
-        Line::from("Quit").bg(Color::LightCyan).fg(Color::Black),
+        Span::from("Clear ").bg(Color::LightCyan).fg(Color::Black),
         areas[3],
     );
+    frame.render_widget(Span::from("I"), areas[4]);
+    frame.render_widget(
+        Span::from(interface_label)
+            .bg(Color::LightCyan)
+            .fg(Color::Black),
+        areas[5],
+    );
+    frame.render_widget(Span::from("Esc/Q"), areas[6]);
+    frame.render_widget(
+        Line::from("Quit").bg(Color::LightCyan).fg(Color::Black),
+        areas[7],
+    );
+
+    if let Some((interface, error)) = &app.capture_error {
+        frame.render_widget(
+            Line::styled(
+                format!("capture on {} failed: {} ", interface, error),
+                Color::Red,
+            )
+            .alignment(Alignment::Right),
+            areas[8],
+        );
+    } else if app.num_parse_errors > 0 {
+        frame.render_widget(
+            Line::styled(
+                format!("{} parse errors ", app.num_parse_errors),
+                Color::Red,
+            )
+            .alignment(Alignment::Right),
+            areas[8],
+        );
+    }
 }